@@ -16,6 +16,7 @@ def print_usage():
     print(" -edges: Print all edges (one per line).", file=sys.stderr)
     print(" -edges-count: Print all edges (one per line) and their number of occurrences.", file=sys.stderr)
     print(" -histogram: Print the number of unique edges for each eventful minute w.r.t. the input file dates reported in the transcript.", file=sys.stderr)
+    print("             (Superseded by the replay mode of the LibAFL host, which measures AFL++ queues without simulation.)", file=sys.stderr)
     print(" -skiprange: Specify ranges [from,to) with edge target/source addresses to not register as edges.", file=sys.stderr)
     print(" -metalog: Specify file with only \"# File modify date \" lines (workaround for wrong transcript log line ordering)", file=sys.stderr)
     print(" -metalog-auto: Like -metalog, but for each transcript file, use <transcript>_meta as meta file", file=sys.stderr) 
@@ -0,0 +1,169 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::mem::ManuallyDrop;
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::sync::{Arc, atomic::AtomicBool};
+
+use clap::ArgMatches;
+use futures::executor;
+use libafl::executors::ExitKind;
+use snafu::ResultExt;
+
+use crate::{FuzzErr, TapascoRunSnafu};
+use crate::tapasco_host::{TapascoHostRun, TapascoRunError, TapascoRunStatusFlags};
+use crate::tapasco_runner_phys::TapascoDispatcher;
+
+//Forkserver control and status fds, as in AFL++'s config.h.
+const FORKSRV_FD: RawFd = 198;
+//Forkserver options (AFL++'s types.h): Announce the map size in the hello message.
+const FS_OPT_ENABLED: u32 = 0x80000001;
+const FS_OPT_MAPSIZE: u32 = 0x40000000;
+const FS_OPT_MAX_MAPSIZE: usize = (0x00fffffe >> 1) + 1;
+const SHM_ENV_VAR: &str = "__AFL_SHM_ID";
+
+//Signal that AFL++ sees for a crash with the given RISC-V exception cause.
+fn crash_signal(cause: u32) -> i32 {
+    match cause {
+        2 => libc::SIGILL, //Illegal instruction
+        3 => libc::SIGTRAP, //Breakpoint
+        0 | 4 | 6 => libc::SIGBUS, //Misaligned accesses
+        1 | 5 | 7 | 12 | 13 | 15 => libc::SIGSEGV, //Access and page faults
+        _ => libc::SIGABRT
+    }
+}
+
+fn write_u32(fd: RawFd, val: u32) -> Result<(), std::io::Error> {
+    let buf = val.to_ne_bytes();
+    if unsafe { libc::write(fd, buf.as_ptr() as *const libc::c_void, 4) } != 4 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+//Reads a u32 from the fd, or returns None if the fd is closed (AFL++ exits).
+fn read_u32(fd: RawFd) -> Result<Option<u32>, std::io::Error> {
+    let mut buf = [0u8; 4];
+    match unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, 4) } {
+        4 => Ok(Some(u32::from_ne_bytes(buf))),
+        0 => Ok(None),
+        n if n < 0 => Err(std::io::Error::last_os_error()),
+        _ => Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "Short read from the forkserver control fd"))
+    }
+}
+
+//Attaches AFL++'s coverage map.
+fn attach_afl_map() -> Result<&'static mut [u8], TapascoRunError> {
+    let shm_id = std::env::var(SHM_ENV_VAR)
+        .map_err(|_| TapascoRunError::Unknown { s: format!("{} not set, not running under AFL++?", SHM_ENV_VAR) })?
+        .parse::<i32>()
+        .map_err(|e| TapascoRunError::Unknown { s: format!("Invalid {}: {}", SHM_ENV_VAR, e) })?;
+    let map_ptr = unsafe { libc::shmat(shm_id, std::ptr::null(), 0) };
+    if map_ptr as isize == -1 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let mut shm_info: libc::shmid_ds = unsafe { std::mem::zeroed() };
+    if unsafe { libc::shmctl(shm_id, libc::IPC_STAT, &mut shm_info) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    //The segment stays attached until the process exits.
+    Ok(unsafe { std::slice::from_raw_parts_mut(map_ptr as *mut u8, shm_info.shm_segsz as usize) })
+}
+
+//Creates a placeholder child process, whose pid is reported to AFL++ for the current testcase.
+// AFL++ kills that pid on a timeout, which must not hit the forkserver itself.
+fn spawn_placeholder_child() -> Result<libc::pid_t, std::io::Error> {
+    match unsafe { libc::fork() } {
+        -1 => Err(std::io::Error::last_os_error()),
+        0 => loop {
+            //Child: Only async-signal-safe calls, since the parent is multi-threaded.
+            unsafe { libc::pause(); }
+        },
+        pid => Ok(pid)
+    }
+}
+
+//Reads the current testcase from the given file, or from the start of stdin.
+fn read_testcase(input_path: Option<&str>) -> Result<Vec<u8>, std::io::Error> {
+    let mut input = Vec::new();
+    match input_path {
+        Some(path) => { File::open(path)?.read_to_end(&mut input)?; },
+        None => {
+            //AFL++ rewrites the file behind stdin for each testcase, so read it unbuffered from the beginning.
+            let mut stdin_file = ManuallyDrop::new(unsafe { File::from_raw_fd(0) });
+            stdin_file.seek(SeekFrom::Start(0))?;
+            stdin_file.read_to_end(&mut input)?;
+        }
+    }
+    Ok(input)
+}
+
+//Returns the wait status to report to AFL++, or None for a timeout (reported once AFL++ has killed the placeholder child).
+fn wait_status(run: &TapascoHostRun) -> Result<Option<i32>, TapascoRunError> {
+    if (run.status & TapascoRunStatusFlags::INVALID_BITMAP_SIZE_FLAG) != 0
+        || (run.status & !TapascoRunStatusFlags::ALL_MASK) != 0 {
+        return Err(TapascoRunError::Unknown { s: format!("PE: Invalid run status 0x{:x}", run.status) });
+    }
+    if (run.status & TapascoRunStatusFlags::TIMEOUT_FLAG) != 0 {
+        return Ok(None);
+    }
+    if (run.status & TapascoRunStatusFlags::PROGRAM_CRASH_FLAG) != 0 {
+        let cause = run.exception.map_or(
+            (run.status & TapascoRunStatusFlags::PROGRAM_CRASH_CAUSE_MASK) >> TapascoRunStatusFlags::PROGRAM_CRASH_CAUSE_SHIFT,
+            |exception| exception.cause);
+        //Status of a process terminated by a signal.
+        return Ok(Some(crash_signal(cause)));
+    }
+    Ok(Some(0))
+}
+
+//Acts as an AFL++ forkserver target, running each testcase on the backend and writing the bitmap to AFL++'s coverage map.
+pub fn afl_forkserver(cmd_matches: &ArgMatches, tapasco_dispatchers: Vec<Arc<TapascoDispatcher>>) -> Result<ExitKind, FuzzErr> {
+    let input_path = cmd_matches.value_of("input");
+    let bitmap_size = cmd_matches.value_of("bitmapsize").map_or(8192, |s| s.parse::<usize>().unwrap());
+    let afl_map = attach_afl_map().context(TapascoRunSnafu)?;
+    if afl_map.len() < bitmap_size {
+        return Err(FuzzErr::ArgError { s: format!("AFL++ map size {} is smaller than the bitmap size {} (set AFL_MAP_SIZE)", afl_map.len(), bitmap_size) });
+    }
+
+    let image = crate::load_target_image(cmd_matches)?;
+    let stop_req = AtomicBool::new(false);
+    let mut tapasco_host = crate::create_tapascorunner(cmd_matches, image, tapasco_dispatchers, &stop_req)?;
+
+    //Hello message, announcing the map size.
+    let mut hello = 0;
+    if bitmap_size <= FS_OPT_MAX_MAPSIZE {
+        hello = FS_OPT_ENABLED | FS_OPT_MAPSIZE | ((((bitmap_size - 1) << 1) & 0x00fffffe) as u32);
+    }
+    write_u32(FORKSRV_FD + 1, hello).map_err(|e| FuzzErr::ArgError { s: format!("Forkserver status fd not open, not running under AFL++? ({})", e) })?;
+
+    loop {
+        //AFL++ requests the next run (the value tells whether the last child was killed, which is irrelevant here).
+        match read_u32(FORKSRV_FD).map_err(TapascoRunError::from).context(TapascoRunSnafu)? {
+            Some(_) => (),
+            None => return Ok(ExitKind::Ok)
+        };
+        let child_pid = spawn_placeholder_child().map_err(TapascoRunError::from).context(TapascoRunSnafu)?;
+        write_u32(FORKSRV_FD + 1, child_pid as u32).map_err(TapascoRunError::from).context(TapascoRunSnafu)?;
+
+        let input = read_testcase(input_path).map_err(TapascoRunError::from).context(TapascoRunSnafu)?;
+        let run_result = executor::block_on(tapasco_host.run(&input, &mut afl_map[..bitmap_size]));
+        let status_opt = run_result.and_then(|run| wait_status(&run)).context(TapascoRunSnafu)?;
+
+        let mut child_status: libc::c_int = 0;
+        let status = match status_opt {
+            Some(status) => {
+                unsafe {
+                    libc::kill(child_pid, libc::SIGKILL);
+                    libc::waitpid(child_pid, &mut child_status, 0);
+                }
+                status
+            },
+            None => {
+                //Timeout: AFL++ only reports a timeout if it killed the child itself after its time limit (-t).
+                unsafe { libc::waitpid(child_pid, &mut child_status, 0); }
+                child_status
+            }
+        };
+        write_u32(FORKSRV_FD + 1, status as u32).map_err(TapascoRunError::from).context(TapascoRunSnafu)?;
+    }
+}
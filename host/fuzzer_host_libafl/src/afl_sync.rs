@@ -0,0 +1,260 @@
+//! Stage that exchanges corpus entries with AFL++ instances fuzzing the same program.
+
+use core::marker::PhantomData;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use libafl::{
+    corpus::Corpus,
+    events::EventFirer,
+    executors::HasObservers,
+    fuzzer::ExecutionProcessor,
+    inputs::Input,
+    observers::ObserversTuple,
+    stages::Stage,
+    state::{HasClientPerfMonitor, HasCorpus, HasExecutions},
+};
+
+use crate::tapasco_host::HasBatchRun;
+
+/// Periodically imports new entries from AFL++ queue directories and exports our own corpus entries in AFL++ naming.
+/// Imported entries run through the executor and are only added to the corpus if they are interesting (e.g. add coverage).
+/// The exported entries can be picked up by AFL++ instances (`-M`/`-S`) if the export directory is placed in their sync directory (`-o`).
+pub struct AflSyncStage<E, EM, I, OT, S, Z>
+where
+    I: Input,
+    OT: ObserversTuple<I, S>,
+    S: HasClientPerfMonitor + HasCorpus<I>,
+{
+    sync_dirs: Vec<PathBuf>,
+    export_dir: Option<PathBuf>,
+    //Name of the exporting instance, used to skip AFL++ entries that were synced from us.
+    export_name: Option<String>,
+    interval: Duration,
+    batch_size: usize,
+    start_time: Instant,
+    last_sync: Option<Instant>,
+    //Already imported (or skipped) file names per queue directory.
+    seen: HashMap<PathBuf, HashSet<OsString>>,
+    //Next corpus index to export, and next AFL++ id for the export queue.
+    next_export_idx: usize,
+    next_export_id: usize,
+    #[allow(clippy::type_complexity)]
+    phantom: PhantomData<(E, EM, I, OT, S, Z)>,
+}
+
+impl<E, EM, I, OT, S, Z> AflSyncStage<E, EM, I, OT, S, Z>
+where
+    I: Input,
+    OT: ObserversTuple<I, S>,
+    S: HasClientPerfMonitor + HasCorpus<I>,
+{
+    /// Creates a new [`AflSyncStage`]. Does nothing if neither sync nor export directories are given.
+    /// * `sync_dirs`: AFL++ output directories (`-o`), instance directories, or `queue/` directories to import from.
+    /// * `export_dir`: Directory that receives our corpus entries in `queue/`, i.e. acts as an AFL++ instance directory.
+    /// * `interval`: Minimum time between two syncs.
+    /// * `batch_size`: Maximum number of imported inputs to run at once.
+    pub fn new(sync_dirs: Vec<PathBuf>, export_dir: Option<PathBuf>, interval: Duration, batch_size: usize) -> Result<Self, std::io::Error> {
+        let mut next_export_id = 0;
+        if let Some(export_dir) = &export_dir {
+            let export_queue = export_dir.join("queue");
+            std::fs::create_dir_all(&export_queue)?;
+            //Continue the ids of a previous run.
+            next_export_id = queue_entries(&export_queue)?.len();
+        }
+        Ok(Self {
+            sync_dirs: sync_dirs,
+            export_name: export_dir.as_ref()
+                .and_then(|dir| dir.file_name())
+                .map(|name| name.to_string_lossy().into_owned()),
+            export_dir: export_dir,
+            interval: interval,
+            batch_size: std::cmp::max(batch_size, 1),
+            start_time: Instant::now(),
+            last_sync: None,
+            seen: HashMap::new(),
+            next_export_idx: 0,
+            next_export_id: next_export_id,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Excludes the first `num_entries` corpus entries (e.g. the seeds, or the queue of a resumed campaign) from the export.
+    /// Resumed entries were already exported by the previous run, and AFL++ instances usually start from the same seeds.
+    pub fn skip_initial_entries(&mut self, num_entries: usize) {
+        self.next_export_idx = num_entries;
+    }
+
+    //Resolves the queue directories of the sync directories.
+    // Rescanned on each sync, since AFL++ instances may start after us.
+    fn queue_dirs(&self) -> Vec<PathBuf> {
+        let export_dir = self.export_dir.as_ref().and_then(|dir| dir.canonicalize().ok());
+        let mut queue_dirs = Vec::new();
+        for sync_dir in &self.sync_dirs {
+            let candidates: Vec<PathBuf> = if sync_dir.join("queue").is_dir() {
+                vec![sync_dir.clone()]
+            }
+            else if sync_dir.file_name().map_or(false, |name| name == "queue") {
+                queue_dirs.push(sync_dir.clone());
+                continue;
+            }
+            else {
+                //AFL++ output directory with one subdirectory per instance.
+                std::fs::read_dir(sync_dir).map_or(Vec::new(), |entries|
+                    entries.filter_map(|res| res.ok().map(|entry| entry.path()))
+                        .filter(|path| path.join("queue").is_dir())
+                        .collect())
+            };
+            for instance_dir in candidates {
+                if export_dir.is_some() && instance_dir.canonicalize().ok() == export_dir {
+                    continue;
+                }
+                queue_dirs.push(instance_dir.join("queue"));
+            }
+        }
+        queue_dirs
+    }
+
+    //Writes the corpus entries added since the last export to the export queue.
+    fn export(&mut self, state: &mut S) -> Result<(), libafl::Error>
+    where S: HasExecutions {
+        let count = state.corpus().count();
+        let export_queue = match &self.export_dir {
+            Some(export_dir) => export_dir.join("queue"),
+            None => {
+                self.next_export_idx = count;
+                return Ok(());
+            }
+        };
+        for idx in self.next_export_idx..count {
+            let input = state.corpus().get(idx)?.borrow_mut().load_input()?.clone();
+            let name = format!("id:{:06},time:{},execs:{},op:tapafuzz",
+                self.next_export_id, self.start_time.elapsed().as_millis(), *state.executions());
+            //Write to a hidden file first, so AFL++ never reads a partial entry.
+            let tmp_path = export_queue.join(".export_tmp");
+            input.to_file(&tmp_path)?;
+            std::fs::rename(&tmp_path, export_queue.join(name))?;
+            self.next_export_id += 1;
+        }
+        self.next_export_idx = count;
+        Ok(())
+    }
+
+    //Returns the new entries of all queue directories as (queue directory, file name), oldest first (in AFL++ id order per directory).
+    // The entries are only marked as seen once they were read, see `mark_seen`.
+    // Entries modified within the last second may still be written, and are left for the next sync.
+    fn new_entries(&mut self) -> Vec<(PathBuf, OsString)> {
+        let mut new_entries = Vec::new();
+        let now = SystemTime::now();
+        for queue_dir in self.queue_dirs() {
+            let entries = match queue_entries(&queue_dir) {
+                Ok(entries) => entries,
+                Err(_) => continue, //The instance may have been removed.
+            };
+            let seen = self.seen.entry(queue_dir.clone()).or_insert_with(HashSet::new);
+            for name in entries {
+                if seen.contains(&name) {
+                    continue;
+                }
+                //AFL++ names entries imported from another instance 'id:...,sync:<instance>,...'.
+                let from_us = self.export_name.as_ref().map_or(false, |export_name|
+                    name.to_string_lossy().contains(&format!(",sync:{},", export_name)));
+                if from_us {
+                    seen.insert(name);
+                    continue;
+                }
+                let recently_modified = std::fs::metadata(queue_dir.join(&name))
+                    .and_then(|metadata| metadata.modified())
+                    .map_or(true, |modified| now.duration_since(modified).map_or(true, |age| age < Duration::from_secs(1)));
+                if !recently_modified {
+                    new_entries.push((queue_dir.clone(), name));
+                }
+            }
+        }
+        new_entries
+    }
+
+    fn mark_seen(&mut self, queue_dir: &Path, name: &OsString) {
+        self.seen.entry(queue_dir.to_path_buf()).or_insert_with(HashSet::new).insert(name.clone());
+    }
+}
+
+//Returns the names of the entries in an AFL++ queue directory, sorted by name (i.e. by id).
+fn queue_entries(queue_dir: &Path) -> Result<Vec<OsString>, std::io::Error> {
+    let mut names: Vec<OsString> = std::fs::read_dir(queue_dir)?
+        .filter_map(|res| res.ok())
+        .filter(|entry| entry.path().is_file()
+            && entry.file_name().to_str().map_or(false, |fname| fname.starts_with("id:")))
+        .map(|entry| entry.file_name())
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+impl<E, EM, I, OT, S, Z> Stage<E, EM, S, Z> for AflSyncStage<E, EM, I, OT, S, Z>
+where
+    E: HasBatchRun<I> + HasObservers<I, OT, S>,
+    EM: EventFirer<I>,
+    I: Input,
+    OT: ObserversTuple<I, S>,
+    S: HasClientPerfMonitor + HasCorpus<I> + HasExecutions,
+    Z: ExecutionProcessor<I, OT, S>,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+        _corpus_idx: usize,
+    ) -> Result<(), libafl::Error> {
+        if self.sync_dirs.is_empty() && self.export_dir.is_none() {
+            return Ok(());
+        }
+        if self.last_sync.map_or(false, |last_sync| last_sync.elapsed() < self.interval) {
+            return Ok(());
+        }
+
+        //Export first, so the imported entries are not sent back.
+        self.export(state)?;
+
+        let new_entries = self.new_entries();
+        let mut num_imported = 0;
+        let mut num_read = 0;
+        for batch_entries in new_entries.chunks(self.batch_size) {
+            let mut inputs: Vec<I> = Vec::with_capacity(batch_entries.len());
+            for (queue_dir, name) in batch_entries {
+                //Entries that fail to read (e.g. removed in the meantime) are retried on the next sync.
+                match I::from_file(queue_dir.join(name)) {
+                    Ok(input) => {
+                        self.mark_seen(queue_dir, name);
+                        inputs.push(input);
+                    },
+                    Err(e) => println!("AFL++ sync: Failed to read {:?}, retrying on the next sync: {}", queue_dir.join(name), e)
+                }
+            }
+            num_read += inputs.len();
+            let results = executor.run_batch(&inputs);
+            for (i, (input, result)) in inputs.into_iter().zip(results).enumerate() {
+                //Same sequence as StdFuzzer::execute_input, with the result already available.
+                executor.observers_mut().pre_exec_all(state, &input)?;
+                let exit_kind = executor.apply_batch_run(i, result)?;
+                *state.executions_mut() += 1;
+                executor.observers_mut().post_exec_all(state, &input)?;
+
+                let (_, new_corpus_idx) = fuzzer.process_execution(state, manager, input, executor.observers(), &exit_kind, true)?;
+                if new_corpus_idx.is_some() {
+                    num_imported += 1;
+                }
+            }
+        }
+        if num_read > 0 {
+            println!("AFL++ sync: Imported {} of {} new entries.", num_imported, num_read);
+        }
+        self.next_export_idx = state.corpus().count();
+        self.last_sync = Some(Instant::now());
+        Ok(())
+    }
+}
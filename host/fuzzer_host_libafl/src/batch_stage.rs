@@ -0,0 +1,132 @@
+//! Mutational stage that executes its mutated inputs in batches, so a single fuzzing thread can keep several PEs busy.
+// Based on LibAFL's PowerMutationalStage (https://github.com/AFLplusplus/LibAFL/blob/main/libafl/src/stages/power.rs)
+
+use core::marker::PhantomData;
+use libafl::{
+    bolts::tuples::{MatchName, Named},
+    corpus::{Corpus, PowerScheduleTestcaseMetaData},
+    events::EventFirer,
+    executors::HasObservers,
+    fuzzer::{Evaluator, ExecutionProcessor},
+    inputs::Input,
+    mutators::Mutator,
+    observers::{MapObserver, ObserversTuple},
+    stages::{calibrate::PowerScheduleMetadata, MutationalStage, Stage},
+    state::{HasClientPerfMonitor, HasCorpus, HasExecutions, HasMetadata},
+};
+
+use crate::tapasco_host::HasBatchRun;
+
+/// Wraps a [`MutationalStage`] (e.g. the `PowerMutationalStage`), using its mutator and iteration count,
+///  but running up to `batch_size` mutated inputs at once through [`HasBatchRun`].
+/// The results are then evaluated in order, as if each input had run on its own.
+/// Since all inputs of a batch are mutated before the first result is known,
+///  mutator statistics based on the last mutation (as in MOpt) are only approximate.
+pub struct BatchMutationalStage<E, EM, I, M, O, OT, S, ST, Z>
+where
+    I: Input,
+    M: Mutator<I, S>,
+    O: MapObserver<u8>,
+    OT: ObserversTuple<I, S>,
+    S: HasClientPerfMonitor + HasCorpus<I>,
+    ST: MutationalStage<E, EM, I, M, S, Z>,
+    Z: Evaluator<E, EM, I, S>,
+{
+    inner: ST,
+    batch_size: usize,
+    map_observer_name: String,
+    #[allow(clippy::type_complexity)]
+    phantom: PhantomData<(E, EM, I, M, O, OT, S, Z)>,
+}
+
+impl<E, EM, I, M, O, OT, S, ST, Z> BatchMutationalStage<E, EM, I, M, O, OT, S, ST, Z>
+where
+    I: Input,
+    M: Mutator<I, S>,
+    O: MapObserver<u8>,
+    OT: ObserversTuple<I, S>,
+    S: HasClientPerfMonitor + HasCorpus<I>,
+    ST: MutationalStage<E, EM, I, M, S, Z>,
+    Z: Evaluator<E, EM, I, S>,
+{
+    /// Creates a new [`BatchMutationalStage`].
+    /// * `inner`: Stage that provides the mutator and the number of iterations per corpus entry.
+    /// * `batch_size`: Maximum number of inputs to run at once. With 1, the inner stage runs as is.
+    /// * `map_observer`: Map observer for the path frequency of the power schedule.
+    pub fn new(inner: ST, batch_size: usize, map_observer: &O) -> Self {
+        Self {
+            inner: inner,
+            batch_size: batch_size,
+            map_observer_name: map_observer.name().to_string(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<E, EM, I, M, O, OT, S, ST, Z> Stage<E, EM, S, Z> for BatchMutationalStage<E, EM, I, M, O, OT, S, ST, Z>
+where
+    E: HasBatchRun<I> + HasObservers<I, OT, S>,
+    EM: EventFirer<I>,
+    I: Input,
+    M: Mutator<I, S>,
+    O: MapObserver<u8>,
+    OT: ObserversTuple<I, S>,
+    S: HasClientPerfMonitor + HasCorpus<I> + HasExecutions + HasMetadata,
+    ST: MutationalStage<E, EM, I, M, S, Z>,
+    Z: Evaluator<E, EM, I, S> + ExecutionProcessor<I, OT, S>,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+        corpus_idx: usize,
+    ) -> Result<(), libafl::Error> {
+        if self.batch_size <= 1 {
+            return self.inner.perform(fuzzer, executor, state, manager, corpus_idx);
+        }
+        let num = self.inner.iterations(state, corpus_idx)?;
+
+        let mut i_batch_start = 0;
+        while i_batch_start < num {
+            let i_batch_end = std::cmp::min(i_batch_start + self.batch_size, num);
+            let mut inputs = Vec::with_capacity(i_batch_end - i_batch_start);
+            for i in i_batch_start..i_batch_end {
+                let mut input = state.corpus().get(corpus_idx)?.borrow_mut().load_input()?.clone();
+                self.inner.mutator_mut().mutate(state, &mut input, i as i32)?;
+                inputs.push(input);
+            }
+
+            let results = executor.run_batch(&inputs);
+
+            for (i, (input, result)) in (i_batch_start..i_batch_end).zip(inputs.into_iter().zip(results)) {
+                //Same sequence as StdFuzzer::execute_input, with the result already available.
+                executor.observers_mut().pre_exec_all(state, &input)?;
+                let exit_kind = executor.apply_batch_run(i - i_batch_start, result)?;
+                *state.executions_mut() += 1;
+                executor.observers_mut().post_exec_all(state, &input)?;
+
+                let (_, new_corpus_idx) = fuzzer.process_execution(state, manager, input, executor.observers(), &exit_kind, true)?;
+
+                //Update the path frequency, as done by the PowerMutationalStage.
+                let observer = executor.observers().match_name::<O>(&self.map_observer_name)
+                    .ok_or_else(|| libafl::Error::KeyNotFound("MapObserver not found".to_string()))?;
+                let mut hash = observer.hash() as usize;
+                let psmeta = state.metadata_mut().get_mut::<PowerScheduleMetadata>()
+                    .ok_or_else(|| libafl::Error::KeyNotFound("PowerScheduleMetadata not found".to_string()))?;
+                hash %= psmeta.n_fuzz().len();
+                psmeta.n_fuzz_mut()[hash] = psmeta.n_fuzz()[hash].saturating_add(1);
+                if let Some(idx) = new_corpus_idx {
+                    state.corpus().get(idx)?.borrow_mut().metadata_mut().get_mut::<PowerScheduleTestcaseMetaData>()
+                        .ok_or_else(|| libafl::Error::KeyNotFound("PowerScheduleTestcaseMetaData not found".to_string()))?
+                        .set_n_fuzz_entry(hash);
+                }
+
+                self.inner.mutator_mut().post_exec(state, i as i32, new_corpus_idx)?;
+            }
+            i_batch_start = i_batch_end;
+        }
+        Ok(())
+    }
+}
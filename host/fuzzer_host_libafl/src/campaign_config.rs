@@ -0,0 +1,127 @@
+use std::path::Path;
+use serde::Deserialize;
+
+use crate::tapasco_host::TapascoRunError;
+use crate::tapasco_runner_phys::{builtin_memconfig, builtin_pe_profiles, PEMemoryKind, PEProfile};
+
+/// PE profile entry of a campaign config file.
+/// Starts from the built-in memory configuration `base`, with each given field overriding the base value.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PEProfileConfig {
+    pub vlnv: String,
+    /// Name of the built-in memory configuration: dram, hbm, bram, bram_dma or bram_tapascoriscv.
+    pub base: String,
+    pub memory: Option<PEMemoryKind>,
+    pub tapascoriscv: Option<bool>,
+    pub invalidate_caches: Option<bool>,
+    pub instmem_offs: Option<u32>,
+    pub instmem_size: Option<usize>,
+    pub instmem_addr_virt: Option<u32>,
+    pub datamem_offs: Option<u32>,
+    /// Total data memory size, including the stack reserve.
+    pub datamem_size: Option<usize>,
+    pub stack_reserve: Option<usize>,
+    pub datamem_addr_virt: Option<u32>,
+    pub bitmap_via_dram_dma: Option<bool>,
+    pub bitmap_addr: Option<u32>,
+    pub bitmap_size: Option<u32>,
+    pub dram_region_shift: Option<u32>,
+}
+
+/// Campaign configuration, loaded from a TOML or JSON file.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CampaignConfig {
+    #[serde(default)]
+    pub pe: Vec<PEProfileConfig>,
+}
+
+//Returns whether two (offset, size) ranges overlap.
+fn ranges_overlap(a: (usize, usize), b: (usize, usize)) -> bool {
+    a.0 < b.0 + b.1 && b.0 < a.0 + a.1
+}
+
+impl PEProfileConfig {
+    fn to_profile(&self) -> Result<PEProfile, TapascoRunError> {
+        let config_err = |s: String| TapascoRunError::Config { s: format!("PE {}: {}", self.vlnv, s) };
+        let (mut memconf, base_memory, base_tapascoriscv) = builtin_memconfig(self.base.as_str())
+            .ok_or_else(|| config_err(format!("Unknown base profile '{}'", self.base)))?;
+        let base_stack_reserve = memconf.datamem_size_phys - memconf.datamem_size_phys_without_stack;
+
+        if let Some(v) = self.invalidate_caches { memconf.invalidate_caches = v; }
+        if let Some(v) = self.instmem_offs { memconf.instmem_offs_phys = v; }
+        if let Some(v) = self.instmem_size { memconf.instmem_size_phys = v; }
+        if let Some(v) = self.instmem_addr_virt { memconf.instmem_addr_virt = v; }
+        if let Some(v) = self.datamem_offs { memconf.datamem_offs_phys = v; }
+        if let Some(v) = self.datamem_size { memconf.datamem_size_phys = v; }
+        if let Some(v) = self.datamem_addr_virt { memconf.datamem_addr_virt = v; }
+        if let Some(v) = self.bitmap_via_dram_dma { memconf.bitmap_via_dram_dma = v; }
+        if let Some(v) = self.bitmap_addr { memconf.bitmap_bram_addr = v; }
+        if let Some(v) = self.bitmap_size { memconf.bitmap_bram_size = v; }
+        if let Some(v) = self.dram_region_shift { memconf.dram_region_shift = v; }
+        let stack_reserve = self.stack_reserve.unwrap_or(base_stack_reserve);
+        if stack_reserve >= memconf.datamem_size_phys {
+            return Err(config_err(format!("Stack reserve 0x{:x} does not fit in the data memory (0x{:x})", stack_reserve, memconf.datamem_size_phys)));
+        }
+        memconf.datamem_size_phys_without_stack = memconf.datamem_size_phys - stack_reserve;
+
+        if memconf.bitmap_bram_size != 0 && (!memconf.bitmap_bram_size.is_power_of_two() || memconf.bitmap_bram_size > 0x2000) {
+            return Err(config_err("bitmap_size must be 0 or a power of two of at most 0x2000".to_string()));
+        }
+        if memconf.dram_region_shift < 12 || memconf.dram_region_shift > 40 {
+            return Err(config_err("dram_region_shift must be in [12, 40]".to_string()));
+        }
+        let instmem_range = (memconf.instmem_offs_phys as usize, memconf.instmem_size_phys);
+        let datamem_range = (memconf.datamem_offs_phys as usize, memconf.datamem_size_phys);
+        if ranges_overlap(instmem_range, datamem_range) {
+            return Err(config_err("Instruction and data memory overlap".to_string()));
+        }
+        let memory = self.memory.unwrap_or(base_memory);
+        //The bitmap shares the address space of the program memory if both are in DRAM, or both are in PE local memory.
+        let bitmap_in_program_memory = memconf.bitmap_via_dram_dma == (memory == PEMemoryKind::Dram);
+        let bitmap_range = (memconf.bitmap_bram_addr as usize, memconf.bitmap_bram_size as usize);
+        if bitmap_in_program_memory && memconf.bitmap_bram_size != 0 {
+            if ranges_overlap(bitmap_range, instmem_range) {
+                return Err(config_err("Bitmap and instruction memory overlap".to_string()));
+            }
+            if ranges_overlap(bitmap_range, datamem_range) {
+                return Err(config_err("Bitmap and data memory overlap".to_string()));
+            }
+        }
+        Ok(PEProfile {
+            vlnv: self.vlnv.clone(),
+            memconf: memconf,
+            memory: memory,
+            tapascoriscv: self.tapascoriscv.unwrap_or(base_tapascoriscv),
+        })
+    }
+}
+
+impl CampaignConfig {
+    /// Loads a campaign config, parsed as JSON for a .json extension and as TOML otherwise.
+    pub fn load(path: &Path) -> Result<Self, TapascoRunError> {
+        let content = std::fs::read_to_string(path)?;
+        let is_json = path.extension().map_or(false, |ext| ext.eq_ignore_ascii_case("json"));
+        if is_json {
+            serde_json::from_str(&content).map_err(|e| TapascoRunError::Config { s: format!("{}: {}", path.display(), e) })
+        }
+        else {
+            toml::from_str(&content).map_err(|e| TapascoRunError::Config { s: format!("{}: {}", path.display(), e) })
+        }
+    }
+
+    /// Returns the PE profiles to acquire PEs with.
+    /// The built-in profiles are used as defaults, with config entries replacing built-in entries of the same VLNV.
+    pub fn pe_profiles(&self) -> Result<Vec<PEProfile>, TapascoRunError> {
+        let mut profiles = builtin_pe_profiles();
+        for pe_config in &self.pe {
+            let profile = pe_config.to_profile()?;
+            match profiles.iter_mut().find(|existing| existing.vlnv == profile.vlnv) {
+                Some(existing) => *existing = profile,
+                None => profiles.push(profile)
+            }
+        }
+        Ok(profiles)
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, atomic::{AtomicBool, AtomicUsize, Ordering}};
+
+use clap::ArgMatches;
+use libafl::{
+    bolts::tuples::{tuple_list, MatchName},
+    executors::{ExitKind, HasObservers},
+    inputs::BytesInput,
+    observers::{HitcountsMapObserver, MapObserver, VariableMapObserver},
+};
+use snafu::ResultExt;
+
+use crate::{FuzzErr, AFLSnafu, AFLDescSnafu, TapascoRunSnafu};
+use crate::{MAP_OBSERVER_NAME, TIME_OBSERVER_NAME, EXCEPTION_OBSERVER_NAME};
+use crate::exception_feedback::ExceptionObserver;
+use crate::showmap::{collect_input_files, count_class_human};
+use crate::tapasco_host::{TapascoHostExecutor, TapascoHostRun, TapascoRunError, TapascoRunner};
+use crate::tapasco_runner_phys::TapascoDispatcher;
+use crate::time_feedback::ManualTimeObserver;
+
+/// Result of an input that finished regularly.
+pub(crate) struct CminEntry {
+    pub len: usize,
+    pub cycles: u64,
+    /// Non-zero bitmap entries, as (index << 4) | count class (or just the index for edges_only).
+    pub tuples: Vec<u32>,
+}
+
+//Runs the inputs assigned to a thread, fetching the next input index from `next_input`.
+fn run_inputs(cmd_matches: &ArgMatches,
+    tapasco_dispatchers: Vec<Arc<TapascoDispatcher>>,
+    input_files: &[PathBuf],
+    next_input: &AtomicUsize,
+    edges_only: bool)
+    -> Result<Vec<(usize, ExitKind, Option<CminEntry>)>, FuzzErr> {
+    let bitmap_size = cmd_matches.value_of("bitmapsize").ok_or(FuzzErr::ArgError{s:"bitmapsize argument missing".to_string()})?.parse::<usize>().unwrap();
+    let mut bitmap_vec_tmp : Vec<u8> = Vec::new();
+    bitmap_vec_tmp.resize(bitmap_size, 0);
+    let mut bitmap_buf = bitmap_vec_tmp.into_boxed_slice();
+
+    let mut _tmp_bitmap_size = bitmap_size;
+    let edges_observer = HitcountsMapObserver::new(VariableMapObserver::new(
+        MAP_OBSERVER_NAME,
+        &mut *bitmap_buf,
+        &mut _tmp_bitmap_size
+    ));
+    let time_observer = ManualTimeObserver::new(TIME_OBSERVER_NAME);
+    let exception_observer = ExceptionObserver::new(EXCEPTION_OBSERVER_NAME);
+
+    let stop_req = AtomicBool::new(false);
+    let image = crate::load_target_image(cmd_matches)?;
+    let mut tapasco_host: Box<dyn TapascoRunner> = crate::create_tapascorunner(cmd_matches, image, tapasco_dispatchers, &stop_req)?;
+
+    let mut executor = TapascoHostExecutor::<BytesInput,HitcountsMapObserver<VariableMapObserver<u8>>,_,u8>::new(
+        cmd_matches.value_of("binary").ok_or(FuzzErr::ArgError{s:"binary argument missing".to_string()})?.to_string(),
+        tapasco_host.as_mut(),
+        tuple_list!(edges_observer, time_observer, exception_observer),
+        MAP_OBSERVER_NAME,
+        bitmap_size,
+        TIME_OBSERVER_NAME,
+        EXCEPTION_OBSERVER_NAME
+    )
+    .context(AFLDescSnafu {desc: "Failed to create the executor.".to_string() })?;
+
+    let mut results = Vec::new();
+    loop {
+        let i_input = next_input.fetch_add(1, Ordering::SeqCst);
+        if i_input >= input_files.len() {
+            break;
+        }
+        let input_buf = std::fs::read(&input_files[i_input]).map_err(TapascoRunError::from).context(TapascoRunSnafu)?;
+        let len = input_buf.len();
+        let exit_kind = executor.run_target(&BytesInput::new(input_buf)).context(AFLSnafu)?;
+        if exit_kind != ExitKind::Ok {
+            results.push((i_input, exit_kind, None));
+            continue;
+        }
+        let observers = executor.observers();
+        let cycles = observers.match_name::<ManualTimeObserver>(TIME_OBSERVER_NAME)
+            .and_then(|o| o.last_runtime().as_ref())
+            .map_or(0, |duration| TapascoHostRun::cycles_from_duration(duration));
+        let bitmap = observers.match_name::<HitcountsMapObserver<VariableMapObserver<u8>>>(MAP_OBSERVER_NAME)
+            .and_then(|o| o.map())
+            .ok_or_else(|| libafl::Error::KeyNotFound("MapObserver not found".to_string()))
+            .context(AFLSnafu)?;
+        let tuples = bitmap.iter().enumerate()
+            .filter(|(_, &count)| count != 0)
+            .map(|(i, &count)| if edges_only { i as u32 } else { ((i as u32) << 4) | (count_class_human(count) as u32) })
+            .collect();
+        results.push((i_input, exit_kind, Some(CminEntry { len: len, cycles: cycles, tuples: tuples })));
+    }
+    Ok(results)
+}
+
+//Selects a small subset of the entries that covers all tuples, in the same way as afl-cmin:
+// For each tuple, the best entry (smallest, then fastest) containing it is determined.
+// Going from the rarest to the most common tuple, the best entry of each tuple not yet covered is selected.
+fn select_entries(entries: &[Option<CminEntry>]) -> Vec<usize> {
+    let is_better = |a: &CminEntry, b: &CminEntry| (a.len, a.cycles) < (b.len, b.cycles);
+    //tuple -> (number of entries containing it, best entry)
+    let mut tuple_info: HashMap<u32, (usize, usize)> = HashMap::new();
+    for (i_entry, entry) in entries.iter().enumerate() {
+        let entry = match entry { Some(entry) => entry, None => continue };
+        for tuple in &entry.tuples {
+            let info = tuple_info.entry(*tuple).or_insert((0, i_entry));
+            info.0 += 1;
+            if is_better(entry, entries[info.1].as_ref().unwrap()) {
+                info.1 = i_entry;
+            }
+        }
+    }
+    let mut tuples_by_rarity: Vec<(u32, usize, usize)> = tuple_info.into_iter()
+        .map(|(tuple, (count, best))| (tuple, count, best))
+        .collect();
+    tuples_by_rarity.sort_by_key(|&(tuple, count, _)| (count, tuple));
+
+    let mut covered: HashSet<u32> = HashSet::new();
+    let mut selected: Vec<usize> = Vec::new();
+    for (tuple, _, best) in tuples_by_rarity {
+        if covered.contains(&tuple) {
+            continue;
+        }
+        selected.push(best);
+        for best_tuple in &entries[best].as_ref().unwrap().tuples {
+            covered.insert(*best_tuple);
+        }
+    }
+    selected.sort();
+    selected
+}
+
+//Runs all inputs, with each thread having its own runner.
+// Returns the result of each input, or None if it crashed or timed out.
+pub(crate) fn run_all_inputs(cmd_matches: &ArgMatches,
+    tapasco_dispatchers: Vec<Arc<TapascoDispatcher>>,
+    input_files: Arc<Vec<PathBuf>>,
+    num_threads: usize,
+    edges_only: bool)
+    -> Result<Vec<Option<CminEntry>>, FuzzErr> {
+    let next_input = Arc::new(AtomicUsize::new(0));
+    let mut threads = Vec::with_capacity(num_threads);
+    for i in 0..num_threads {
+        let thr_cmd_matches = cmd_matches.clone();
+        let thr_dispatchers = tapasco_dispatchers.clone();
+        let thr_input_files = input_files.clone();
+        let thr_next_input = next_input.clone();
+        threads.push(std::thread::spawn(move || {
+            run_inputs(&thr_cmd_matches, thr_dispatchers, &thr_input_files, &thr_next_input, edges_only)
+                .map_err(|e| format!("Thread {}: {}", i, e))
+        }));
+    }
+    let mut entries: Vec<Option<CminEntry>> = (0..input_files.len()).map(|_| None).collect();
+    let (mut num_crashes, mut num_timeouts) = (0usize, 0usize);
+    for thread in threads {
+        let results = thread.join().unwrap().map_err(|s| FuzzErr::WorkerError{s: s})?;
+        for (i_input, exit_kind, entry) in results {
+            match exit_kind {
+                ExitKind::Crash => num_crashes += 1,
+                ExitKind::Timeout => num_timeouts += 1,
+                _ => ()
+            }
+            entries[i_input] = entry;
+        }
+    }
+    if num_crashes > 0 || num_timeouts > 0 {
+        println!("Excluding {} crashing and {} timing out inputs.", num_crashes, num_timeouts);
+    }
+    Ok(entries)
+}
+
+//Runs all inputs of a corpus and copies a minimal subset with the same coverage to the output directory.
+pub fn cmin(cmd_matches: &ArgMatches, tapasco_dispatchers: Vec<Arc<TapascoDispatcher>>) -> Result<ExitKind, FuzzErr> {
+    let input_dir = cmd_matches.value_of("input").ok_or(FuzzErr::ArgError{s:"input argument missing".to_string()})?;
+    let output_dir = cmd_matches.value_of("output").ok_or(FuzzErr::ArgError{s:"output argument missing".to_string()})?;
+    let num_threads = cmd_matches.value_of("numthreads").map_or(1, |s| s.parse::<usize>().unwrap());
+    let edges_only = cmd_matches.is_present("edges_only");
+
+    let input_files = Arc::new(collect_input_files(std::iter::once(input_dir)).map_err(TapascoRunError::from).context(TapascoRunSnafu)?);
+    std::fs::create_dir_all(output_dir).map_err(TapascoRunError::from).context(TapascoRunSnafu)?;
+    println!("Running {} inputs on {} threads.", input_files.len(), num_threads);
+
+    let entries = run_all_inputs(cmd_matches, tapasco_dispatchers, input_files.clone(), num_threads, edges_only)?;
+
+    let selected = select_entries(&entries);
+    for &i_entry in &selected {
+        let input_file = &input_files[i_entry];
+        let out_path = Path::new(output_dir).join(input_file.file_name().unwrap_or_default());
+        std::fs::copy(input_file, out_path).map_err(TapascoRunError::from).context(TapascoRunSnafu)?;
+    }
+    let num_tuples: usize = {
+        let mut all_tuples: Vec<u32> = entries.iter().flatten().flat_map(|entry| entry.tuples.iter().copied()).collect();
+        all_tuples.sort_unstable();
+        all_tuples.dedup();
+        all_tuples.len()
+    };
+    println!("Selected {} of {} inputs, covering {} tuples.", selected.len(), input_files.len(), num_tuples);
+    Ok(ExitKind::Ok)
+}
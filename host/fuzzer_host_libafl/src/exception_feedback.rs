@@ -0,0 +1,388 @@
+//Based on files from LibAFL.
+
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use libafl::{
+    bolts::tuples::Named,
+    corpus::Testcase,
+    events::{Event, EventFirer},
+    executors::ExitKind,
+    feedbacks::Feedback,
+    inputs::Input,
+    observers::{Observer, ObserversTuple},
+    state::{HasClientPerfMonitor, HasMetadata},
+    stats::UserStats
+};
+use serde::{Deserialize, Serialize};
+
+use crate::tapasco_host::TapascoRunException;
+use crate::target_image::TargetImage;
+
+
+/// Based on LibAFL's TimeObserver (libafl/src/observers/mod.rs).
+/// A simple observer intended to be fed with the exception details of a crashing run after each execution.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExceptionObserver {
+    name: String,
+    last_exception: Option<TapascoRunException>,
+}
+
+impl ExceptionObserver {
+    /// Creates a new [`ExceptionObserver`] with the given name.
+    #[must_use]
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name: name.to_string(),
+            last_exception: None,
+        }
+    }
+
+    /// Gets the exception details for the last execution of this target, if it crashed.
+    #[must_use]
+    pub fn last_exception(&self) -> &Option<TapascoRunException> {
+        &self.last_exception
+    }
+
+    /// Sets the exception details for the last execution of this target.
+    pub fn set_last_exception(&mut self, last_exception: Option<TapascoRunException>) {
+        self.last_exception = last_exception;
+    }
+}
+
+impl<I, S> Observer<I, S> for ExceptionObserver {
+    fn pre_exec(&mut self, _state: &mut S, _input: &I) -> Result<(), libafl::Error> {
+        Ok(())
+    }
+
+    fn post_exec(&mut self, _state: &mut S, _input: &I) -> Result<(), libafl::Error> {
+        Ok(())
+    }
+}
+
+impl Named for ExceptionObserver {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+
+/// Testcase metadata with the exception details of a crashing input.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExceptionMetadata {
+    pub cause: u32,
+    pub epc: u32,
+    pub tval: u32,
+    /// Human-readable summary, including the symbol of epc if known.
+    pub description: String,
+}
+
+libafl::impl_serdeany!(ExceptionMetadata);
+
+impl ExceptionMetadata {
+    /// Creates the metadata for an exception, symbolizing epc through the target image (if provided).
+    #[must_use]
+    pub fn new(exception: &TapascoRunException, image: Option<&TargetImage>) -> Self {
+        let symbol = image
+            .and_then(|image| image.symbolize(exception.epc))
+            .map(|(name, offs)| format!(" ({}+0x{:x})", name, offs))
+            .unwrap_or_default();
+        Self {
+            cause: exception.cause,
+            epc: exception.epc,
+            tval: exception.tval,
+            description: format!("{}{}", exception, symbol),
+        }
+    }
+}
+
+
+/// Based on LibAFL's TimeFeedback (libafl/src/feedbacks/mod.rs).
+/// Nop feedback that annotates the exception details in the new testcase, if any
+/// for this Feedback, the testcase is never interesting (use with an OR)
+#[derive(Clone, Debug)]
+pub struct ExceptionFeedback {
+    last_exception: Option<TapascoRunException>,
+    image: Option<Arc<TargetImage>>,
+    name: String,
+}
+
+impl<I, S> Feedback<I, S> for ExceptionFeedback
+where
+    I: Input,
+    S: HasClientPerfMonitor,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, libafl::Error>
+    where
+        EM: EventFirer<I>,
+        OT: ObserversTuple<I, S>,
+    {
+        // TODO Replace with match_name_type when stable
+        let observer = observers.match_name::<ExceptionObserver>(self.name()).unwrap();
+        self.last_exception = *observer.last_exception();
+        Ok(false)
+    }
+
+    /// Append to the testcase the generated metadata in case of a new corpus item
+    #[inline]
+    fn append_metadata(&mut self, _state: &mut S, testcase: &mut Testcase<I>) -> Result<(), libafl::Error> {
+        if let Some(exception) = self.last_exception.take() {
+            testcase.add_metadata(ExceptionMetadata::new(&exception, self.image.as_deref()));
+        }
+        Ok(())
+    }
+
+    /// Discard the stored metadata in case that the testcase is not added to the corpus
+    #[inline]
+    fn discard_metadata(&mut self, _state: &mut S, _input: &I) -> Result<(), libafl::Error> {
+        self.last_exception = None;
+        Ok(())
+    }
+}
+
+impl Named for ExceptionFeedback {
+    #[inline]
+    fn name(&self) -> &str {
+        self.name.as_str()
+    }
+}
+
+impl ExceptionFeedback {
+    /// Creates a new [`ExceptionFeedback`] for the given [`ExceptionObserver`].
+    /// If `image` is provided, its symbols are used to describe the exception location.
+    #[must_use]
+    pub fn new_with_observer(observer: &ExceptionObserver, image: Option<Arc<TargetImage>>) -> Self {
+        Self {
+            last_exception: None,
+            image: image,
+            name: observer.name().to_string(),
+        }
+    }
+}
+
+
+/// Strategy to decide whether a crash is kept as a new solution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrashDedupStrategy {
+    /// Keep crashes that trigger new coverage over all previous crashes, as AFL does.
+    Coverage,
+    /// Keep one crash per (cause, epc, tval & tval_mask) bucket.
+    Exception { tval_mask: u32 },
+}
+
+/// Key of a crash bucket for [`CrashDedupStrategy::Exception`].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CrashBucket {
+    pub cause: u32,
+    pub epc: u32,
+    /// tval with the mask applied.
+    pub tval: u32,
+}
+
+impl CrashBucket {
+    #[must_use]
+    pub fn new(exception: &TapascoRunException, tval_mask: u32) -> Self {
+        Self {
+            cause: exception.cause,
+            epc: exception.epc,
+            tval: exception.tval & tval_mask,
+        }
+    }
+}
+
+//Entry of the crash buckets file.
+#[derive(Serialize, Deserialize)]
+struct CrashBucketHits {
+    #[serde(flatten)]
+    bucket: CrashBucket,
+    hits: u64,
+}
+
+/// Number of crashes seen in each bucket, shared by the fuzzer threads (see [`SharedCrashBuckets`]).
+/// The hit counts are written to a JSON file on new buckets, at most once per second on further hits, and when dropped.
+#[derive(Debug, Default)]
+pub struct CrashBuckets {
+    hits: BTreeMap<CrashBucket, u64>,
+    path: Option<PathBuf>,
+    last_write: Option<Instant>,
+    //Hits not written to the file yet.
+    dirty: bool,
+}
+
+/// Crash bucket table shared by all fuzzer threads, since they store their crashes in the same directory.
+pub type SharedCrashBuckets = Arc<Mutex<CrashBuckets>>;
+
+impl CrashBuckets {
+    /// Creates a new [`CrashBuckets`] table that is written to `path`, if given.
+    /// With `resume`, continues the hit counts of an existing file from the previous campaign.
+    pub fn new(path: Option<PathBuf>, resume: bool) -> Result<Self, std::io::Error> {
+        let mut hits = BTreeMap::new();
+        if let Some(path) = path.as_ref().filter(|path| resume && path.is_file()) {
+            let entries: Vec<CrashBucketHits> = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+            hits.extend(entries.into_iter().map(|entry| (entry.bucket, entry.hits)));
+        }
+        Ok(Self {
+            hits: hits,
+            path: path,
+            last_write: None,
+            dirty: false,
+        })
+    }
+
+    /// Counts a crash in `bucket`, and returns the number of hits of the bucket so far.
+    pub fn hit(&mut self, bucket: CrashBucket) -> u64 {
+        let hits = self.hits.entry(bucket).or_insert(0);
+        *hits += 1;
+        let hits = *hits;
+        self.dirty = true;
+        if hits == 1 || self.last_write.map_or(true, |last_write| last_write.elapsed() >= Duration::from_secs(1)) {
+            if let Err(e) = self.write() {
+                eprintln!("Failed to write the crash buckets: {}", e);
+            }
+        }
+        hits
+    }
+
+    /// Returns the hit counts of all buckets.
+    #[must_use]
+    pub fn hits(&self) -> &BTreeMap<CrashBucket, u64> {
+        &self.hits
+    }
+
+    //Replaces the file at once, so readers never see a partial file.
+    fn write(&mut self) -> Result<(), std::io::Error> {
+        self.last_write = Some(Instant::now());
+        self.dirty = false;
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(())
+        };
+        let entries: Vec<CrashBucketHits> = self.hits.iter()
+            .map(|(bucket, hits)| CrashBucketHits { bucket: *bucket, hits: *hits })
+            .collect();
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(&entries)?)?;
+        std::fs::rename(&tmp_path, path)
+    }
+}
+
+impl Drop for CrashBuckets {
+    //Record the final hit counts.
+    fn drop(&mut self) {
+        if self.dirty {
+            if let Err(e) = self.write() {
+                eprintln!("Failed to write the crash buckets: {}", e);
+            }
+        }
+    }
+}
+
+
+/// Crash deduplication feedback, to be combined with a CrashFeedback (AND).
+/// For [`CrashDedupStrategy::Coverage`], forwards to the given coverage feedback (e.g. a MaxMapFeedback over the crash edges).
+/// For [`CrashDedupStrategy::Exception`], a crash is interesting only if its bucket has not been hit before by any thread;
+///  the hit counts are kept in the [`SharedCrashBuckets`].
+#[derive(Clone, Debug)]
+pub struct CrashDedupFeedback<F> {
+    strategy: CrashDedupStrategy,
+    coverage_feedback: F,
+    buckets: SharedCrashBuckets,
+    observer_name: String,
+    name: String,
+}
+
+impl<I, S, F> Feedback<I, S> for CrashDedupFeedback<F>
+where
+    I: Input,
+    S: HasClientPerfMonitor,
+    F: Feedback<I, S>,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        input: &I,
+        observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, libafl::Error>
+    where
+        EM: EventFirer<I>,
+        OT: ObserversTuple<I, S>,
+    {
+        let tval_mask = match self.strategy {
+            CrashDedupStrategy::Coverage => {
+                return self.coverage_feedback.is_interesting(state, manager, input, observers, exit_kind);
+            },
+            CrashDedupStrategy::Exception { tval_mask } => tval_mask
+        };
+        if *exit_kind != ExitKind::Crash {
+            return Ok(false);
+        }
+        // TODO Replace with match_name_type when stable
+        let observer = observers.match_name::<ExceptionObserver>(&self.observer_name).unwrap();
+        let bucket = match observer.last_exception() {
+            Some(exception) => CrashBucket::new(exception, tval_mask),
+            None => return Ok(false)
+        };
+        let num_buckets = {
+            let mut buckets = self.buckets.lock().unwrap();
+            if buckets.hit(bucket) > 1 {
+                return Ok(false);
+            }
+            buckets.hits().len() as u64
+        };
+        manager.fire(state, Event::UpdateUserStats {
+            name: "crash_buckets".to_string(),
+            value: UserStats::Number(num_buckets),
+            phantom: PhantomData
+        })?;
+        Ok(true)
+    }
+
+    #[inline]
+    fn append_metadata(&mut self, state: &mut S, testcase: &mut Testcase<I>) -> Result<(), libafl::Error> {
+        match self.strategy {
+            CrashDedupStrategy::Coverage => self.coverage_feedback.append_metadata(state, testcase),
+            CrashDedupStrategy::Exception { .. } => Ok(())
+        }
+    }
+
+    #[inline]
+    fn discard_metadata(&mut self, state: &mut S, input: &I) -> Result<(), libafl::Error> {
+        match self.strategy {
+            CrashDedupStrategy::Coverage => self.coverage_feedback.discard_metadata(state, input),
+            CrashDedupStrategy::Exception { .. } => Ok(())
+        }
+    }
+}
+
+impl<F> Named for CrashDedupFeedback<F> {
+    #[inline]
+    fn name(&self) -> &str {
+        self.name.as_str()
+    }
+}
+
+impl<F> CrashDedupFeedback<F> {
+    /// Creates a new [`CrashDedupFeedback`] using the given strategy.
+    /// `coverage_feedback` is only evaluated for [`CrashDedupStrategy::Coverage`], and `buckets` only for [`CrashDedupStrategy::Exception`].
+    #[must_use]
+    pub fn new(strategy: CrashDedupStrategy, observer: &ExceptionObserver, coverage_feedback: F, buckets: SharedCrashBuckets) -> Self {
+        Self {
+            strategy: strategy,
+            coverage_feedback: coverage_feedback,
+            buckets: buckets,
+            observer_name: observer.name().to_string(),
+            name: "crash_dedup".to_string(),
+        }
+    }
+}
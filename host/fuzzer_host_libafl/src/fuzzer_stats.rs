@@ -0,0 +1,202 @@
+use std::fmt::Write as FmtWrite;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use libafl::monitors::{ClientStats, Monitor};
+use libafl::stats::UserStats;
+
+use crate::tapasco_runner_phys::{PEBusyTime, TapascoDispatcher};
+
+//Campaign totals over all fuzzer threads.
+struct StatsSnapshot {
+    execs: u64,
+    execs_per_sec: u64,
+    corpus_count: u64,
+    crashes: u64,
+    hangs: u64,
+    //Filled and total bitmap entries.
+    map_filled: u64,
+    map_size: u64,
+}
+
+/// Wraps the [`Monitor`] of the broker, and periodically writes AFL-style `fuzzer_stats` and `plot_data` files.
+/// `fuzzer_stats` is rewritten with the current totals, while `plot_data` receives a CSV line per interval.
+/// Besides the AFL++ fields, both contain the utilization of each PE (its busy time over the last interval).
+pub struct StatsFileMonitor<M: Monitor> {
+    inner: M,
+    out_dir: PathBuf,
+    interval: Duration,
+    //User stats name of the map feedback, which reports the filled bitmap entries.
+    map_stats_name: String,
+    tapasco_dispatchers: Vec<Arc<TapascoDispatcher>>,
+    start_time: SystemTime,
+    last_write: Option<Instant>,
+    last_corpus_count: u64,
+    last_find: Option<SystemTime>,
+    //Maximum bitmap fill reported by each client, since the crash map feedback reports its own (smaller) fill under the same name.
+    map_filled: Vec<(u64, u64)>,
+    last_busy: Vec<PEBusyTime>,
+    last_busy_time: Instant,
+}
+
+impl<M: Monitor> StatsFileMonitor<M> {
+    /// Creates a new [`StatsFileMonitor`], starting a new `plot_data` file in `out_dir`.
+    /// * `map_stats_name`: Name of the user stats with the bitmap fill (the name of the map observer).
+    /// * `tapasco_dispatchers`: Dispatchers whose PE utilization is recorded, if any.
+    pub fn new(inner: M, out_dir: PathBuf, interval: Duration, map_stats_name: &str,
+        tapasco_dispatchers: Vec<Arc<TapascoDispatcher>>) -> Result<Self, std::io::Error> {
+        let last_busy: Vec<PEBusyTime> = tapasco_dispatchers.iter().flat_map(|dispatcher| dispatcher.pe_busy_times()).collect();
+        let mut header = "# relative_time, corpus_count, saved_crashes, saved_hangs, map_size, execs_per_sec, total_execs, pe_utilization".to_string();
+        for busy in &last_busy {
+            write!(header, ", pe{}_utilization", busy.index).unwrap();
+        }
+        header.push('\n');
+        std::fs::write(out_dir.join("plot_data"), header)?;
+        Ok(Self {
+            inner: inner,
+            out_dir: out_dir,
+            interval: interval,
+            map_stats_name: map_stats_name.to_string(),
+            tapasco_dispatchers: tapasco_dispatchers,
+            start_time: SystemTime::now(),
+            last_write: None,
+            last_corpus_count: 0,
+            last_find: None,
+            map_filled: Vec::new(),
+            last_busy: last_busy,
+            last_busy_time: Instant::now(),
+        })
+    }
+
+    fn snapshot(&mut self) -> StatsSnapshot {
+        //Keep the maximum bitmap fill per client.
+        let client_fills: Vec<Option<(u64, u64)>> = self.inner.client_stats().iter()
+            .map(|client| match client.user_monitor.get(&self.map_stats_name) {
+                Some(UserStats::Ratio(filled, size)) => Some((*filled, *size)),
+                _ => None
+            })
+            .collect();
+        self.map_filled.resize(client_fills.len(), (0, 0));
+        for (max_fill, fill) in self.map_filled.iter_mut().zip(client_fills) {
+            if let Some(fill) = fill {
+                if fill.0 > max_fill.0 {
+                    *max_fill = fill;
+                }
+            }
+        }
+        let (map_filled, map_size) = self.map_filled.iter().copied().max().unwrap_or((0, 0));
+        let hangs = self.inner.client_stats().iter()
+            .map(|client: &ClientStats| match client.user_monitor.get("hangs") {
+                Some(UserStats::Number(n)) => *n,
+                _ => 0
+            })
+            .sum();
+        StatsSnapshot {
+            execs: self.inner.total_execs(),
+            execs_per_sec: self.inner.execs_per_sec(),
+            corpus_count: self.inner.corpus_size(),
+            crashes: self.inner.objective_size(),
+            hangs: hangs,
+            map_filled: map_filled,
+            map_size: map_size,
+        }
+    }
+
+    //Returns the utilization of each PE since the last call, and the mean over all PEs.
+    fn pe_utilization(&mut self) -> (Vec<(usize, f64)>, f64) {
+        let busy_times: Vec<PEBusyTime> = self.tapasco_dispatchers.iter().flat_map(|dispatcher| dispatcher.pe_busy_times()).collect();
+        let elapsed = self.last_busy_time.elapsed().as_secs_f64();
+        self.last_busy_time = Instant::now();
+        let utilization: Vec<(usize, f64)> = busy_times.iter().zip(&self.last_busy)
+            .map(|(busy, last_busy)| (busy.index,
+                if elapsed > 1e-9 { ((busy.busy - last_busy.busy).as_secs_f64() / elapsed).min(1.0) } else { 0.0 }))
+            .collect();
+        self.last_busy = busy_times;
+        let mean = if utilization.is_empty() { 0.0 }
+            else { utilization.iter().map(|(_, u)| u).sum::<f64>() / utilization.len() as f64 };
+        (utilization, mean)
+    }
+
+    fn write_files(&mut self) -> Result<(), std::io::Error> {
+        let stats = self.snapshot();
+        let (utilization, mean_utilization) = self.pe_utilization();
+        let now = SystemTime::now();
+        if stats.corpus_count > self.last_corpus_count {
+            self.last_corpus_count = stats.corpus_count;
+            self.last_find = Some(now);
+        }
+        let epoch_secs = |time: SystemTime| time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let run_time = now.duration_since(self.start_time).unwrap_or_default().as_secs();
+        let map_density = if stats.map_size > 0 { 100.0 * stats.map_filled as f64 / stats.map_size as f64 } else { 0.0 };
+
+        let mut fuzzer_stats = String::new();
+        writeln!(fuzzer_stats, "start_time        : {}", epoch_secs(self.start_time)).unwrap();
+        writeln!(fuzzer_stats, "last_update       : {}", epoch_secs(now)).unwrap();
+        writeln!(fuzzer_stats, "run_time          : {}", run_time).unwrap();
+        writeln!(fuzzer_stats, "fuzzer_pid        : {}", std::process::id()).unwrap();
+        writeln!(fuzzer_stats, "execs_done        : {}", stats.execs).unwrap();
+        writeln!(fuzzer_stats, "execs_per_sec     : {:.2}", stats.execs_per_sec as f64).unwrap();
+        writeln!(fuzzer_stats, "corpus_count      : {}", stats.corpus_count).unwrap();
+        writeln!(fuzzer_stats, "saved_crashes     : {}", stats.crashes).unwrap();
+        writeln!(fuzzer_stats, "saved_hangs       : {}", stats.hangs).unwrap();
+        writeln!(fuzzer_stats, "last_find         : {}", self.last_find.map_or(0, epoch_secs)).unwrap();
+        writeln!(fuzzer_stats, "bitmap_cvg        : {:.2}%", map_density).unwrap();
+        writeln!(fuzzer_stats, "edges_found       : {}", stats.map_filled).unwrap();
+        writeln!(fuzzer_stats, "total_edges       : {}", stats.map_size).unwrap();
+        if !utilization.is_empty() {
+            writeln!(fuzzer_stats, "pe_utilization    : {:.2}%", 100.0 * mean_utilization).unwrap();
+            for (index, pe_utilization) in &utilization {
+                writeln!(fuzzer_stats, "{:<18}: {:.2}%", format!("pe{}_utilization", index), 100.0 * pe_utilization).unwrap();
+            }
+        }
+        //Replace the file at once, so readers never see a partial file.
+        let tmp_path = self.out_dir.join(".fuzzer_stats_tmp");
+        std::fs::write(&tmp_path, fuzzer_stats)?;
+        std::fs::rename(&tmp_path, self.out_dir.join("fuzzer_stats"))?;
+
+        let mut plot_line = format!("{}, {}, {}, {}, {:.2}%, {:.2}, {}, {:.2}%",
+            run_time, stats.corpus_count, stats.crashes, stats.hangs, map_density,
+            stats.execs_per_sec as f64, stats.execs, 100.0 * mean_utilization);
+        for (_, pe_utilization) in &utilization {
+            write!(plot_line, ", {:.2}%", 100.0 * pe_utilization).unwrap();
+        }
+        plot_line.push('\n');
+        std::fs::OpenOptions::new().append(true).open(self.out_dir.join("plot_data"))?
+            .write_all(plot_line.as_bytes())
+    }
+}
+
+impl<M: Monitor> Monitor for StatsFileMonitor<M> {
+    fn client_stats_mut(&mut self) -> &mut Vec<ClientStats> {
+        self.inner.client_stats_mut()
+    }
+
+    fn client_stats(&self) -> &[ClientStats] {
+        self.inner.client_stats()
+    }
+
+    fn start_time(&mut self) -> Duration {
+        self.inner.start_time()
+    }
+
+    fn display(&mut self, event_msg: String, sender_id: u32) {
+        self.inner.display(event_msg, sender_id);
+        if self.last_write.map_or(true, |last_write| last_write.elapsed() >= self.interval) {
+            self.last_write = Some(Instant::now());
+            if let Err(e) = self.write_files() {
+                println!("Failed to write the fuzzer stats: {}", e);
+            }
+        }
+    }
+}
+
+impl<M: Monitor> Drop for StatsFileMonitor<M> {
+    //Record the final state of the campaign.
+    fn drop(&mut self) {
+        if let Err(e) = self.write_files() {
+            println!("Failed to write the fuzzer stats: {}", e);
+        }
+    }
+}
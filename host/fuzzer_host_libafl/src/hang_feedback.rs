@@ -0,0 +1,156 @@
+use std::marker::PhantomData;
+use futures::executor;
+use libafl::{
+    bolts::tuples::{MatchName, Named},
+    corpus::{Corpus, OnDiskCorpus, Testcase},
+    events::{Event, EventFirer},
+    executors::ExitKind,
+    feedbacks::{Feedback, FeedbackStatesTuple, MapFeedbackState},
+    inputs::{HasTargetBytes, Input},
+    observers::{MapObserver, ObserversTuple},
+    state::{HasClientPerfMonitor, HasFeedbackStates},
+    stats::UserStats
+};
+
+use crate::tapasco_host::{TapascoRunner, TapascoRunError, TapascoRunStatusFlags};
+
+/// Creates the runner used to confirm hangs, with the larger cycle budget.
+pub type HangRunnerFactory<'a> = Box<dyn FnMut() -> Result<Box<dyn TapascoRunner + 'a>, libafl::Error> + 'a>;
+
+/// Feedback that stores timeouts in a separate hangs corpus, to be used in the objective (OR).
+/// A timeout is kept if it triggers new coverage over all previous hangs (the history of a [`MapFeedbackState`]),
+///  and if it still times out when re-run with a larger cycle budget.
+/// The coverage is only added to the history once the hang is confirmed,
+///  so an unconfirmed timeout does not shadow a later hang with the same coverage.
+/// For this Feedback, the testcase is never interesting, so hangs do not end up with the crashes.
+pub struct HangFeedback<'a, I, O, FT>
+where
+    I: Input,
+{
+    map_observer_name: String,
+    hang_state_name: String,
+    hangs: OnDiskCorpus<I>,
+    runner_factory: HangRunnerFactory<'a>,
+    //Created on the first hang, since a second runner may be costly (e.g. another simulator instance).
+    confirm_runner: Option<Box<dyn TapascoRunner + 'a>>,
+    bitmap: Box<[u8]>,
+    name: String,
+    phantom: PhantomData<(O, FT)>,
+}
+
+impl<'a, I, O, FT> HangFeedback<'a, I, O, FT>
+where
+    I: Input,
+    O: MapObserver<u8>,
+    FT: FeedbackStatesTuple,
+{
+    //Returns whether the map has an entry above the hang history, and adds the map to the history if `commit` is set.
+    fn update_history<S, OT>(&self, state: &mut S, observers: &OT, commit: bool) -> Result<bool, libafl::Error>
+    where
+        S: HasFeedbackStates<FT>,
+        OT: ObserversTuple<I, S>,
+    {
+        let observer = observers.match_name::<O>(&self.map_observer_name)
+            .ok_or_else(|| libafl::Error::KeyNotFound("MapObserver not found".to_string()))?;
+        let map_state = state.feedback_states_mut().match_name_mut::<MapFeedbackState<u8>>(&self.hang_state_name)
+            .ok_or_else(|| libafl::Error::KeyNotFound("MapFeedbackState for the hangs not found".to_string()))?;
+        let map = observer.map().ok_or_else(|| libafl::Error::KeyNotFound("MapObserver has no map".to_string()))?;
+        let mut novel = false;
+        for (history, &count) in map_state.history_map.iter_mut().zip(map.iter()) {
+            if count > *history {
+                novel = true;
+                if commit {
+                    *history = count;
+                }
+            }
+        }
+        Ok(novel)
+    }
+}
+
+impl<'a, I, O, S, FT> Feedback<I, S> for HangFeedback<'a, I, O, FT>
+where
+    I: Input + HasTargetBytes,
+    O: MapObserver<u8>,
+    S: HasClientPerfMonitor + HasFeedbackStates<FT>,
+    FT: FeedbackStatesTuple,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        input: &I,
+        observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, libafl::Error>
+    where
+        EM: EventFirer<I>,
+        OT: ObserversTuple<I, S>,
+    {
+        //Only check for new coverage here, without touching the history.
+        if *exit_kind != ExitKind::Timeout || !self.update_history(state, observers, false)? {
+            return Ok(false);
+        }
+
+        //Confirm the hang with the larger cycle budget.
+        if self.confirm_runner.is_none() {
+            self.confirm_runner = Some((self.runner_factory)()?);
+        }
+        let input_slice_owner = input.target_bytes();
+        let run = match executor::block_on(self.confirm_runner.as_mut().unwrap().run(input_slice_owner.as_slice(), &mut self.bitmap)) {
+            Ok(run) => run,
+            Err(TapascoRunError::LibAFL { source: libafl::Error::ShuttingDown }) => return Err(libafl::Error::ShuttingDown),
+            Err(e) => return Err(libafl::Error::Unknown(format!("Hang confirmation run failed: {}", e)))
+        };
+        if (run.status & TapascoRunStatusFlags::TIMEOUT_FLAG) == 0 {
+            println!("Timeout not confirmed with the hang cycle budget.");
+            return Ok(false);
+        }
+
+        //Confirmed, add the coverage of the original run to the history.
+        self.update_history(state, observers, true)?;
+        self.hangs.add(Testcase::new(input.clone()))?;
+        manager.fire(state, Event::UpdateUserStats {
+            name: "hangs".to_string(),
+            value: UserStats::Number(self.hangs.count() as u64),
+            phantom: PhantomData
+        })?;
+        Ok(false)
+    }
+}
+
+impl<'a, I, O, FT> Named for HangFeedback<'a, I, O, FT>
+where
+    I: Input,
+{
+    #[inline]
+    fn name(&self) -> &str {
+        self.name.as_str()
+    }
+}
+
+impl<'a, I, O, FT> HangFeedback<'a, I, O, FT>
+where
+    I: Input,
+    O: MapObserver<u8>,
+{
+    /// Creates a new [`HangFeedback`].
+    /// * `hang_state`: Coverage history of the hangs, registered in the state.
+    /// * `map_observer`: Observer of the bitmap to compare against the history.
+    /// * `hangs`: Corpus to store the confirmed hangs in.
+    /// * `bitmap_len`: Bitmap length for the confirmation runs.
+    /// * `runner_factory`: Creates the runner for the confirmation runs, configured with the larger cycle budget.
+    #[must_use]
+    pub fn new(hang_state: &MapFeedbackState<u8>, map_observer: &O, hangs: OnDiskCorpus<I>, bitmap_len: usize, runner_factory: HangRunnerFactory<'a>) -> Self {
+        Self {
+            map_observer_name: map_observer.name().to_string(),
+            hang_state_name: hang_state.name().to_string(),
+            hangs: hangs,
+            runner_factory: runner_factory,
+            confirm_runner: None,
+            bitmap: vec![0u8; bitmap_len].into_boxed_slice(),
+            name: "hang".to_string(),
+            phantom: PhantomData,
+        }
+    }
+}
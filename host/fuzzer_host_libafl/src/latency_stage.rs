@@ -0,0 +1,72 @@
+//! Stage that reports the phase times of the TaPaSCo jobs of the current thread as user stats.
+
+use core::marker::PhantomData;
+use std::time::{Duration, Instant};
+
+use libafl::{
+    events::{Event, EventFirer},
+    inputs::Input,
+    stages::Stage,
+    stats::UserStats,
+};
+
+use crate::tapasco_runner_phys::{thread_latency_stats, LatencyStats};
+
+/// Periodically sends the mean phase times per job (see [`LatencyStats`]) of the current thread since the last report,
+///  as the `latency` user stats. Does nothing if the thread did not run jobs on TaPaSCo PEs.
+pub struct LatencyStatsStage<E, EM, I, S, Z>
+where
+    I: Input,
+{
+    interval: Duration,
+    last_report: Instant,
+    last_stats: LatencyStats,
+    #[allow(clippy::type_complexity)]
+    phantom: PhantomData<(E, EM, I, S, Z)>,
+}
+
+impl<E, EM, I, S, Z> LatencyStatsStage<E, EM, I, S, Z>
+where
+    I: Input,
+{
+    /// Creates a new [`LatencyStatsStage`] that reports every `interval`.
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval: interval,
+            last_report: Instant::now(),
+            last_stats: thread_latency_stats(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<E, EM, I, S, Z> Stage<E, EM, S, Z> for LatencyStatsStage<E, EM, I, S, Z>
+where
+    EM: EventFirer<I>,
+    I: Input,
+{
+    fn perform(
+        &mut self,
+        _fuzzer: &mut Z,
+        _executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+        _corpus_idx: usize,
+    ) -> Result<(), libafl::Error> {
+        if self.last_report.elapsed() < self.interval {
+            return Ok(());
+        }
+        self.last_report = Instant::now();
+        let stats = thread_latency_stats();
+        let interval_stats = stats.since(&self.last_stats);
+        self.last_stats = stats;
+        if interval_stats.runs == 0 {
+            return Ok(());
+        }
+        manager.fire(state, Event::UpdateUserStats {
+            name: "latency".to_string(),
+            value: UserStats::String(interval_stats.mean_summary()),
+            phantom: PhantomData
+        })
+    }
+}
@@ -1,43 +1,72 @@
 //Modified based on LibAFL's forkserver fuzzer example ( https://github.com/AFLplusplus/LibAFL/blob/main/fuzzers/forkserver_simple )
 // and the fuzzbench host ( https://github.com/AFLplusplus/LibAFL/blob/main/fuzzers/fuzzbench/src ).
 
+mod afl_forkserver;
+mod afl_sync;
+mod batch_stage;
+mod campaign_config;
+mod exception_feedback;
+mod fuzzer_stats;
+mod hang_feedback;
+mod latency_stage;
+mod pe_completion;
+mod cmin;
+mod replay;
+mod repro;
+mod serve;
+mod showmap;
+mod tmin;
 mod tapasco_host;
 mod tapasco_runner_sim;
 mod tapasco_runner_phys;
+mod tapasco_runner_emu;
+mod tapasco_runner_remote;
+mod target_image;
 mod time_feedback;
 
 use libafl::{
     bolts::{
         current_nanos,
-        rands::StdRand,
+        rands::{Rand, StdRand},
         os::{Cores, CoreId},
-        tuples::{tuple_list, Merge},
+        tuples::{tuple_list, MatchName, Merge},
         llmp::{LlmpClient, LlmpBroker},
         shmem::{StdShMemProvider, ShMemProvider},
     },
     corpus::{
         Corpus, IndexesLenTimeMinimizerCorpusScheduler, OnDiskCorpus,
         PowerQueueCorpusScheduler, CachedOnDiskCorpus,
+        ondisk::OnDiskMetadataFormat,
     },
     events::{LlmpEventManager, EventConfig, LlmpEventBroker},
     feedback_and_fast, feedback_or,
     feedbacks::{CrashFeedback, MapFeedbackState, MaxMapFeedback},
-    fuzzer::{Fuzzer, StdFuzzer},
-    inputs::BytesInput,
+    fuzzer::{Evaluator, Fuzzer, StdFuzzer},
+    inputs::{BytesInput, Input},
     monitors::MultiMonitor,
     mutators::{scheduled::havoc_mutations, tokens_mutations, Tokens, StdMOptMutator},
     observers::{VariableMapObserver, HitcountsMapObserver},
     stages::{CalibrationStage, power::PowerSchedule, PowerMutationalStage},
-    state::{HasCorpus, StdState, HasMetadata}, executors::ExitKind,
+    state::{HasCorpus, StdState, HasMetadata}, executors::{ExitKind, HasObservers},
 };
 use libc::sigaction;
-use tapasco_host::{TapascoHostExecutor, TapascoRunError, TapascoRunner};
+use tapasco_host::{TapascoHostExecutor, TapascoHostRun, TapascoRunError, TapascoRunner};
 use tapasco_runner_sim::{TapascoRunnerSim};
-use tapasco_runner_phys::{TapascoRunnerPhysical, TapascoDispatcher};
+use tapasco_runner_phys::{TapascoRunnerPhysical, TapascoDispatcher, DataUploadStats, DispatcherConfig, DispatcherPolicy, LatencyStats, PEDesc, PEMemConfig, PEProfile, PESelection};
+use campaign_config::CampaignConfig;
+use tapasco_runner_emu::{TapascoRunnerEmu};
+use tapasco_runner_remote::TapascoRunnerRemote;
+use target_image::TargetImage;
 use time_feedback::{ManualTimeObserver, ManualTimeFeedback};
+use hang_feedback::HangFeedback;
+use batch_stage::BatchMutationalStage;
+use fuzzer_stats::StatsFileMonitor;
+use latency_stage::LatencyStatsStage;
+use afl_sync::AflSyncStage;
+use exception_feedback::{ExceptionObserver, ExceptionFeedback, CrashDedupFeedback, CrashDedupStrategy, CrashBuckets, SharedCrashBuckets};
 use std::{
-    path::PathBuf,
-    sync::{Arc, atomic::{AtomicBool, AtomicU64}, Barrier, Once},
+    path::{Path, PathBuf},
+    sync::{Arc, atomic::AtomicBool, Barrier, Once},
     marker::PhantomData,
     num::ParseIntError, io::Read,
     u32
@@ -61,23 +90,48 @@ enum FuzzErr {
     ArgError { s: String },
     #[snafu(display("{}", source))]
     ParseIntError { source: ParseIntError },
-    #[snafu(display("Program execution with a benchmark corpus entry did not succeed"))]
-    BenchmarkCorpusError { }
+    #[snafu(display("{}", s))]
+    WorkerError { s: String }
+}
+
+fn load_target_image(cmd_matches: &ArgMatches) -> Result<Arc<TargetImage>, FuzzErr> {
+    let target: String = cmd_matches.value_of("binary").ok_or(FuzzErr::ArgError{s:"binary argument missing".to_string()})?.to_string();
+    Ok(Arc::new(TargetImage::load(target.as_str()).context(TapascoRunSnafu)?))
 }
 
 fn create_tapascorunner<'a>(cmd_matches: &ArgMatches,
+    image: Arc<TargetImage>,
     tapasco_dispatchers: Vec<Arc<TapascoDispatcher>>,
     stop_req: &'a AtomicBool)
 -> Result<Box<dyn TapascoRunner + 'a>, FuzzErr> {
-    let target: String = cmd_matches.value_of("binary").ok_or(FuzzErr::ArgError{s:"binary argument missing".to_string()})?.to_string();
-    let ignoreaddr_min: u32 = cmd_matches.value_of("ignore_min").map_or(0xffffffff, |s| u32::from_str_radix(s, 16).unwrap());
     let timeout_cycles = cmd_matches.value_of("timeout").map_or(0,|s| s.parse::<u64>().unwrap());
+    create_tapascorunner_with_timeout(cmd_matches, image, tapasco_dispatchers, stop_req, timeout_cycles)
+}
+
+fn create_tapascorunner_with_timeout<'a>(cmd_matches: &ArgMatches,
+    image: Arc<TargetImage>,
+    tapasco_dispatchers: Vec<Arc<TapascoDispatcher>>,
+    stop_req: &'a AtomicBool,
+    timeout_cycles: u64)
+-> Result<Box<dyn TapascoRunner + 'a>, FuzzErr> {
+    let ignoreaddr_min: u32 = cmd_matches.value_of("ignore_min").map_or(0xffffffff, |s| u32::from_str_radix(s, 16).unwrap());
+    create_tapascorunner_with_params(cmd_matches, image, tapasco_dispatchers, stop_req, ignoreaddr_min, timeout_cycles)
+}
+
+//Creates the runner for the backend subcommand, with the ignored address range and timeout given explicitly (e.g. by a serve client).
+fn create_tapascorunner_with_params<'a>(cmd_matches: &ArgMatches,
+    image: Arc<TargetImage>,
+    tapasco_dispatchers: Vec<Arc<TapascoDispatcher>>,
+    stop_req: &'a AtomicBool,
+    ignoreaddr_min: u32,
+    timeout_cycles: u64)
+-> Result<Box<dyn TapascoRunner + 'a>, FuzzErr> {
     let tapasco_host: Box<dyn TapascoRunner>;
     match cmd_matches.subcommand() {
         Some(("sim", simcmd_matches)) => {
             //Simulation runner backend, invokes a tapasco-pe-tb testbench and uses IPC for parameter and result transfer.
             tapasco_host = Box::new(TapascoRunnerSim::<'a>::new(
-                    target,
+                    &*image,
                     ignoreaddr_min,
                     simcmd_matches.value_of("simdir").ok_or(FuzzErr::ArgError{s:"simdir argument missing".to_string()})?.to_string(),
                     simcmd_matches.value_of("simlogfile").and_then(|s| Some(s.to_string())),
@@ -89,7 +143,7 @@ fn create_tapascorunner<'a>(cmd_matches: &ArgMatches,
         Some(("tapasco", _tapascocmd_matches)) => {
             //TaPaSCo runner backend. Takes a PE, configures it and waits for the results.
             tapasco_host = Box::new(TapascoRunnerPhysical::<'a>::new(
-                    target,
+                    image,
                     ignoreaddr_min,
                     tapasco_dispatchers,
                     timeout_cycles,
@@ -97,13 +151,143 @@ fn create_tapascorunner<'a>(cmd_matches: &ArgMatches,
                 ).context(AFLDescSnafu {desc: "Failed to create the TaPaSCo runner.".to_string() })?
             );
         },
+        Some(("emu", emucmd_matches)) => {
+            //Emulator runner backend, executes the program on a software model of the PE.
+            tapasco_host = Box::new(TapascoRunnerEmu::<'a>::new(
+                    &*image,
+                    emu_memconfig(cmd_matches, emucmd_matches)?,
+                    ignoreaddr_min,
+                    timeout_cycles,
+                    stop_req
+                ).context(AFLDescSnafu {desc: "Failed to create the emulator runner.".to_string() })?
+            );
+        },
+        Some(("remote", remotecmd_matches)) => {
+            //Remote runner backend, sends the program and inputs to a serve process.
+            let program_path = cmd_matches.value_of("binary").ok_or(FuzzErr::ArgError{s:"binary argument missing".to_string()})?;
+            let program = std::fs::read(program_path).map_err(TapascoRunError::from).context(TapascoRunSnafu)?;
+            tapasco_host = Box::new(TapascoRunnerRemote::<'a>::new(
+                    remotecmd_matches.value_of("address").ok_or(FuzzErr::ArgError{s:"address argument missing".to_string()})?,
+                    &program,
+                    ignoreaddr_min,
+                    timeout_cycles,
+                    stop_req
+                ).context(TapascoRunSnafu)?
+            );
+        },
         _ => unreachable!()
     }
     Ok(tapasco_host)
 }
 
+/// Output directories of a fuzzing campaign.
+struct OutputDirs {
+    queue: PathBuf,
+    crashes: PathBuf,
+    hangs: PathBuf,
+    //Queue entries of the previous run, imported on --resume.
+    resume: PathBuf,
+    //Directory of the fuzzer_stats and plot_data files.
+    stats: PathBuf,
+}
+
+impl OutputDirs {
+    //With --out, uses the queue, crashes and hangs subdirectories.
+    //Otherwise, uses ./runtimecorpus, ./crashes and ./hangs in the working directory.
+    fn from_matches(cmd_matches: &ArgMatches) -> Self {
+        match cmd_matches.value_of("out") {
+            Some(out) => Self {
+                queue: PathBuf::from(out).join("queue"),
+                crashes: PathBuf::from(out).join("crashes"),
+                hangs: PathBuf::from(out).join("hangs"),
+                resume: PathBuf::from(out).join("_resume"),
+                stats: PathBuf::from(out),
+            },
+            None => Self {
+                queue: PathBuf::from("./runtimecorpus"),
+                crashes: PathBuf::from("./crashes"),
+                hangs: PathBuf::from("./hangs"),
+                resume: PathBuf::from("./runtimecorpus_resume"),
+                stats: PathBuf::from("."),
+            }
+        }
+    }
+
+    fn create_all(&self) -> Result<(), std::io::Error> {
+        std::fs::create_dir_all(&self.queue)?;
+        std::fs::create_dir_all(&self.crashes)?;
+        std::fs::create_dir_all(&self.hangs)
+    }
+
+    //Returns the regular (non-hidden) files in a directory.
+    fn list_entries(dir: &PathBuf) -> Result<Vec<PathBuf>, std::io::Error> {
+        Ok(std::fs::read_dir(dir)?
+            .filter_map(|res| res.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file()
+                && path.file_name()
+                   .and_then(|os_fname| os_fname.to_str())
+                   .map(|fname| !fname.starts_with(".")).unwrap_or(false))
+            .collect())
+    }
+
+    //Moves the queue entries of the previous run to the resume directory, from where the fuzzer threads import them.
+    // (The threads add all imported entries to the queue again, so they should not read from the queue directly.)
+    // Entries left over from an earlier interrupted resume are kept. The directory is removed once the campaign ends.
+    // Returns the number of entries to import.
+    fn prepare_resume(&self) -> Result<usize, std::io::Error> {
+        std::fs::create_dir_all(&self.resume)?;
+        for path in Self::list_entries(&self.queue)? {
+            std::fs::rename(&path, self.resume.join(path.file_name().unwrap()))?;
+        }
+        Ok(Self::list_entries(&self.resume)?.len())
+    }
+}
+
 const MAP_OBSERVER_NAME: &'static str = "copied_bitmap";
 const TIME_OBSERVER_NAME: &'static str = "time";
+const EXCEPTION_OBSERVER_NAME: &'static str = "exception";
+
+//Outcomes and PE cycle counts of the benchmark runs.
+#[derive(Clone, Copy, Debug, Default)]
+struct BenchmarkStats {
+    executions: u64,
+    ok: u64,
+    crashes: u64,
+    timeouts: u64,
+    //Over the runs that reported a cycle count.
+    cycles_runs: u64,
+    cycles_min: u64,
+    cycles_max: u64,
+    cycles_sum: u64,
+}
+
+impl BenchmarkStats {
+    fn add_run(&mut self, exit_kind: &ExitKind, cycles: Option<u64>) {
+        self.executions += 1;
+        match exit_kind {
+            ExitKind::Ok => self.ok += 1,
+            ExitKind::Crash => self.crashes += 1,
+            ExitKind::Timeout => self.timeouts += 1,
+            _ => ()
+        }
+        if let Some(cycles) = cycles {
+            self.merge(&BenchmarkStats { cycles_runs: 1, cycles_min: cycles, cycles_max: cycles, cycles_sum: cycles, ..Default::default() });
+        }
+    }
+
+    fn merge(&mut self, other: &BenchmarkStats) {
+        self.executions += other.executions;
+        self.ok += other.ok;
+        self.crashes += other.crashes;
+        self.timeouts += other.timeouts;
+        if other.cycles_runs > 0 {
+            self.cycles_min = if self.cycles_runs > 0 { std::cmp::min(self.cycles_min, other.cycles_min) } else { other.cycles_min };
+            self.cycles_max = std::cmp::max(self.cycles_max, other.cycles_max);
+            self.cycles_sum += other.cycles_sum;
+            self.cycles_runs += other.cycles_runs;
+        }
+    }
+}
 
 //Runs an execution benchmark.
 fn benchmark(i_thread: usize,
@@ -112,7 +296,7 @@ fn benchmark(i_thread: usize,
     inputs: &Vec<BytesInput>,
     barrier_start: &Barrier,
     stop_req: &AtomicBool)
-    -> Result<u64, FuzzErr> {
+    -> Result<BenchmarkStats, FuzzErr> {
     
     let bitmap_size = cmd_matches.value_of("bitmapsize").ok_or(FuzzErr::ArgError{s:"bitmapsize argument missing".to_string()})?.parse::<usize>().unwrap();
     let mut bitmap_vec_tmp : Vec<u8> = Vec::new();
@@ -128,17 +312,21 @@ fn benchmark(i_thread: usize,
     ));
     // Create an observation channel to keep track of the execution time
     let time_observer = ManualTimeObserver::new(TIME_OBSERVER_NAME);
+    // Create an observation channel for the exception details of crashes
+    let exception_observer = ExceptionObserver::new(EXCEPTION_OBSERVER_NAME);
 
-    let mut tapasco_host: Box<dyn TapascoRunner> = create_tapascorunner(&cmd_matches, tapasco_dispatchers, stop_req)?;
+    let image = load_target_image(&cmd_matches)?;
+    let mut tapasco_host: Box<dyn TapascoRunner> = create_tapascorunner(&cmd_matches, image, tapasco_dispatchers, stop_req)?;
 
     // Executor to interface with the TapascoRunner.
     let mut executor = TapascoHostExecutor::<BytesInput,HitcountsMapObserver<VariableMapObserver<u8>>,_,u8>::new(
         cmd_matches.value_of("binary").ok_or(FuzzErr::ArgError{s:"binary argument missing".to_string()})?.to_string(),
         tapasco_host.as_mut(),
-        tuple_list!(edges_observer, time_observer),
+        tuple_list!(edges_observer, time_observer, exception_observer),
         MAP_OBSERVER_NAME,
         bitmap_size,
-        TIME_OBSERVER_NAME
+        TIME_OBSERVER_NAME,
+        EXCEPTION_OBSERVER_NAME
     )
     .context(AFLDescSnafu {desc: "Failed to create the executor.".to_string() })?;
 
@@ -151,37 +339,44 @@ fn benchmark(i_thread: usize,
     barrier_start.wait();
     let run_start_time = std::time::Instant::now();
 
-    let mut num_executions: u64 = 0;
+    let mut stats = BenchmarkStats::default();
     for _ in 0..numiter {
         for input in inputs {
-            if executor.run_target(input).context(AFLSnafu)? != ExitKind::Ok {
-                return Err(FuzzErr::BenchmarkCorpusError {});
-            }
-            num_executions += 1;
+            //Crashes and timeouts are counted, not treated as errors (e.g. for corpora with crashing inputs).
+            let exit_kind = executor.run_target(input).context(AFLSnafu)?;
+            let cycles = executor.observers().match_name::<ManualTimeObserver>(TIME_OBSERVER_NAME)
+                .and_then(|o| o.last_runtime().as_ref())
+                .map(|duration| TapascoHostRun::cycles_from_duration(duration));
+            stats.add_run(&exit_kind, cycles);
         }
     }
     let elapsed_run = run_start_time.elapsed().as_secs_f64();
     if elapsed_run > 1e-9 {
         println!("Thread {} finished after {:.3e} seconds ({:.2} exec per second).",
             i_thread, elapsed_run,
-            (num_executions as f64) / elapsed_run);
+            (stats.executions as f64) / elapsed_run);
     }
     else {
         println!("Thread {} finished after {:.3e} seconds", i_thread, elapsed_run);
     }
+    let latency = tapasco_runner_phys::thread_latency_stats();
+    if latency.runs > 0 {
+        println!("Thread {} job phases: {}", i_thread, latency.mean_summary());
+    }
 
-    Ok(num_executions)
+    Ok(stats)
 }
 
 //Initialises and runs the fuzzer loop for a thread. 
 fn fuzz<SP>(i_thread: usize,
     cmd_matches: ArgMatches, llmp_client: LlmpClient<SP>, tapasco_dispatchers: Vec<Arc<TapascoDispatcher>>,
-    stop_req: &AtomicBool)
+    crash_buckets: SharedCrashBuckets, stop_req: &AtomicBool)
     -> Result<(), FuzzErr>
 where SP: ShMemProvider, SP: 'static {
     println!("Thread {} starting up.",  i_thread);
 
     let corpus_dirs = vec![PathBuf::from(cmd_matches.value_of("in").ok_or(FuzzErr::ArgError{s:"in argument missing".to_string()})?.to_string())];
+    let out_dirs = OutputDirs::from_matches(&cmd_matches);
 
     let bitmap_size = cmd_matches.value_of("bitmapsize").ok_or(FuzzErr::ArgError{s:"bitmapsize argument missing".to_string()})?.parse::<usize>().unwrap();
 
@@ -201,11 +396,19 @@ where SP: ShMemProvider, SP: 'static {
     // Create an observation channel to keep track of the execution time
     let time_observer = ManualTimeObserver::new(TIME_OBSERVER_NAME);
 
+    // Create an observation channel for the exception details of crashes
+    let exception_observer = ExceptionObserver::new(EXCEPTION_OBSERVER_NAME);
+
+    let image = load_target_image(&cmd_matches)?;
+
     // The state of the edges feedback.
     let feedback_state = MapFeedbackState::with_observer(&edges_observer);
 
     // The state of the edges feedback for crashes.
     let objective_state = MapFeedbackState::new("crash_edges", bitmap_size);
+
+    // The state of the edges feedback for hangs.
+    let hang_state = MapFeedbackState::new("hang_edges", bitmap_size);
     
     // Feedback to rate the interestingness of an input
     // This one is composed by two Feedbacks in OR
@@ -216,13 +419,43 @@ where SP: ShMemProvider, SP: 'static {
         ManualTimeFeedback::new_with_observer(&time_observer)
     );
 
+    let crash_dedup = match cmd_matches.value_of("crash_dedup") {
+        Some("exception") => CrashDedupStrategy::Exception {
+            tval_mask: cmd_matches.value_of("crash_tval_mask").map_or(0, |s| u32::from_str_radix(s, 16).unwrap())
+        },
+        _ => CrashDedupStrategy::Coverage
+    };
+
+    // Hangs are confirmed by a runner with a larger cycle budget before they are stored.
+    let timeout_cycles = cmd_matches.value_of("timeout").ok_or(FuzzErr::ArgError{s:"timeout argument missing".to_string()})?.parse::<u64>().unwrap();
+    let hang_timeout_cycles = cmd_matches.value_of("hang_timeout").map_or(timeout_cycles.saturating_mul(2), |s| s.parse::<u64>().unwrap());
+    let hang_cmd_matches = cmd_matches.clone();
+    let hang_image = image.clone();
+    let hang_dispatchers = tapasco_dispatchers.clone();
+    let hang_feedback = HangFeedback::new(
+        // Take it only if it triggers new coverage over hangs
+        &hang_state, &edges_observer,
+        OnDiskCorpus::new(out_dirs.hangs.clone()).context(AFLSnafu)?,
+        bitmap_size,
+        Box::new(move || create_tapascorunner_with_timeout(&hang_cmd_matches,
+                hang_image.clone(), hang_dispatchers.clone(), stop_req, hang_timeout_cycles)
+            .map_err(|e| libafl::Error::Unknown(format!("Failed to create the hang confirmation runner: {}", e))))
+    );
+
     // A feedback to choose if an input is a solution or not
-    // We want to do the same crash deduplication that AFL does
-    let objective = feedback_and_fast!(
-        // Must be a crash
-        CrashFeedback::new(),
-        // Take it onlt if trigger new coverage over crashes
-        MaxMapFeedback::new(&objective_state, &edges_observer)
+    // By default, we want to do the same crash deduplication that AFL does
+    let objective = feedback_or!(
+        feedback_and_fast!(
+            // Must be a crash
+            CrashFeedback::new(),
+            // Take it only if it triggers new coverage over crashes, or hits a new exception bucket
+            CrashDedupFeedback::new(crash_dedup, &exception_observer,
+                MaxMapFeedback::new(&objective_state, &edges_observer), crash_buckets)
+        ),
+        // Hang feedback, stores confirmed timeouts with new coverage in the hangs corpus
+        hang_feedback,
+        // Exception feedback, annotates the crash with the exception details
+        ExceptionFeedback::new_with_observer(&exception_observer, Some(image.clone()))
     );
 
     // create a State from scratch
@@ -230,13 +463,14 @@ where SP: ShMemProvider, SP: 'static {
         // RNG
         StdRand::with_seed(current_nanos()),
         // Corpus that will be evolved, saved to disk for external analysis but cached in memory for performance
-        CachedOnDiskCorpus::<BytesInput>::new(PathBuf::from("./runtimecorpus"), 256).context(AFLSnafu)?,
+        CachedOnDiskCorpus::<BytesInput>::new(out_dirs.queue.clone(), 256).context(AFLSnafu)?,
         // Corpus in which we store solutions (crashes in this example),
-        // on disk so the user can get them after stopping the fuzzer
-        OnDiskCorpus::new(PathBuf::from("./crashes")).context(AFLSnafu)?,
+        // on disk so the user can get them after stopping the fuzzer.
+        // The exception details are stored as JSON metadata next to each crash.
+        OnDiskCorpus::new_save_meta(out_dirs.crashes.clone(), Some(OnDiskMetadataFormat::JsonPretty)).context(AFLSnafu)?,
         // States of the feedbacks.
         // They are the data related to the feedbacks that you want to persist in the State.
-        tuple_list!(feedback_state, objective_state),
+        tuple_list!(feedback_state, objective_state, hang_state),
     );
     // Add tokens.
     if state.metadata().get::<Tokens>().is_none() {
@@ -268,27 +502,58 @@ where SP: ShMemProvider, SP: 'static {
     let mut fuzzer = StdFuzzer::new(scheduler, feedback, objective);
 
     // Create the executor for the forkserver
-    let mut tapasco_host: Box<dyn TapascoRunner> = create_tapascorunner(&cmd_matches, tapasco_dispatchers, stop_req)?;
+    let mut tapasco_host: Box<dyn TapascoRunner> = create_tapascorunner(&cmd_matches, image, tapasco_dispatchers, stop_req)?;
 
     let calibration = CalibrationStage::new(&mut state, &edges_observer);
 
     // Setup a MOPT mutator
     let mutator = StdMOptMutator::new(&mut state, havoc_mutations().merge(tokens_mutations()), 5).context(AFLSnafu)?;
     let power = PowerMutationalStage::new(mutator, PowerSchedule::FAST, &edges_observer);
+    // Run the mutated inputs in batches, to keep several PEs busy from this thread.
+    let batch_size = cmd_matches.value_of("batch").map_or(1, |s| s.parse::<usize>().unwrap());
+    let power_batched = BatchMutationalStage::new(power, batch_size, &edges_observer);
 
-    let mut stages = tuple_list!(calibration, power);
+    // Exchange corpus entries with AFL++ instances. Only the first thread syncs, the others receive the imported entries as events.
+    let (afl_sync_dirs, afl_export_dir) = match i_thread {
+        0 => (
+            cmd_matches.values_of("afl_sync").map_or(Vec::new(), |dirs| dirs.map(PathBuf::from).collect()),
+            cmd_matches.value_of("afl_export").map(PathBuf::from)
+        ),
+        _ => (Vec::new(), None)
+    };
+    let afl_sync_interval = std::time::Duration::from_secs(cmd_matches.value_of("afl_sync_interval").unwrap().parse::<u64>().unwrap());
+    let mut afl_sync = AflSyncStage::new(afl_sync_dirs, afl_export_dir, afl_sync_interval, batch_size)
+        .map_err(libafl::Error::from)
+        .context(AFLDescSnafu { desc: "Failed to create the AFL++ export directory".to_string() })?;
+
+    // Report the phase times of the TaPaSCo jobs as user stats.
+    let stats_interval = std::time::Duration::from_secs(cmd_matches.value_of("stats_interval").unwrap().parse::<u64>().unwrap());
+    let latency_stats = LatencyStatsStage::new(stats_interval);
 
     // Executor as an 'adapter' between the LibAFL API and the TapascoRunner.
     let mut executor = TapascoHostExecutor::<_,HitcountsMapObserver<VariableMapObserver<u8>>,_,_>::new(
         cmd_matches.value_of("binary").ok_or(FuzzErr::ArgError{s:"binary argument missing".to_string()})?.to_string(),
         tapasco_host.as_mut(),
-        tuple_list!(edges_observer, time_observer),
+        tuple_list!(edges_observer, time_observer, exception_observer),
         MAP_OBSERVER_NAME,
         bitmap_size,
-        TIME_OBSERVER_NAME
+        TIME_OBSERVER_NAME,
+        EXCEPTION_OBSERVER_NAME
     )
     .context(AFLDescSnafu {desc: "Failed to create the executor.".to_string() })?;
 
+    // On --resume, import the queue of the previous run instead of the seeds.
+    // All entries are added (not only the interesting ones), so the queue keeps the whole previous corpus.
+    if cmd_matches.is_present("resume") {
+        let resume_entries = OutputDirs::list_entries(&out_dirs.resume).map_err(TapascoRunError::from).context(TapascoRunSnafu)?;
+        for path in resume_entries {
+            let input = BytesInput::from_file(&path)
+                .context(AFLDescSnafu {desc: format!("Failed to load the previous queue entry {:?}", &path)})?;
+            fuzzer.add_input(&mut state, &mut executor, &mut mgr, input)
+                .context(AFLDescSnafu {desc: format!("Failed to add the previous queue entry {:?}", &path)})?;
+        }
+        println!("We resumed with {} inputs from the previous queue.", state.corpus().count());
+    }
     // In case the corpus is empty (on first run), reset
     if state.corpus().count() < 1 {
         state
@@ -300,6 +565,9 @@ where SP: ShMemProvider, SP: 'static {
             )?;
         println!("We imported {} inputs from disk.", state.corpus().count());
     }
+    afl_sync.skip_initial_entries(state.corpus().count());
+
+    let mut stages = tuple_list!(calibration, power_batched, afl_sync, latency_stats);
     
     let fuzz_result = match cmd_matches.value_of("numiter") {
         Some(s) => {
@@ -323,10 +591,9 @@ where SP: ShMemProvider, SP: 'static {
     Ok(())
 }
 
-#[allow(clippy::similar_names)]
-pub fn main() {
-    let cmd_matches = Command::new("fuzzer_host_libafl")
-        .about("Fuzzer host for TaPaSCo-based fuzzing accelerator")
+//Adds the backend subcommands and the arguments shared by all modes that execute the target.
+fn runner_args(cmd: Command<'static>) -> Command<'static> {
+    cmd
         .subcommand_required(true)
         .subcommand(Command::new("sim")
             .about("Simulate the hardware through tapasco-pe-tb.")
@@ -344,18 +611,104 @@ pub fn main() {
         )   
         .subcommand(Command::new("tapasco")
             .about("Run on hardware accessible through TaPaSCo.")
+            .arg(
+                Arg::new("device").long("device")
+                    .help("TLKM device ID to use. Can be repeated or comma-separated. All devices by default.")
+                    .validator(|s| s.parse::<u32>())
+                    .multiple_occurrences(true)
+                    .use_value_delimiter(true)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("pes").long("pes")
+                    .help("PE type to use, with an optional maximum count (e.g. cva5_bram_pe_fuzzer:4). Can be repeated or comma-separated.")
+                    .long_help("PE type to use, either as the full VLNV or as its name (e.g. cva5_bram_pe_fuzzer),\n\
+                                followed by an optional maximum number of PEs over all selected devices (e.g. cva5_bram_pe_fuzzer:4).\n\
+                                Can be repeated or comma-separated. By default, all PEs of all known types are used.")
+                    .validator(|s| PESelection::parse_pes_entry(s).map(|_| ()))
+                    .multiple_occurrences(true)
+                    .use_value_delimiter(true)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("shared_access").long("shared_access")
+                    .help("Open the devices with shared access, so another campaign can use the PEs not selected here.")
+            )
+            .arg(
+                Arg::new("data_snapshot").long("data_snapshot")
+                    .help("Upload only the writable data sections after the first run of each PE.")
+                    .long_help("Upload the full data section once per PE, and afterwards only the writable sections (e.g. .data) and the input.\n\
+                                The read-only data (e.g. .rodata) of the first upload is kept in the PE memory.\n\
+                                Only use this if the target does not write to read-only data, since such writes would persist to the following runs.\n\
+                                The PEs have no memory protection: To limit the effect of such writes, the full data section is uploaded again\n\
+                                after a crash or timeout, and when a readback of the read-only data every 64 runs of a PE finds a difference.\n\
+                                Flat binaries have no section information, and are always uploaded completely.")
+            )
+            .arg(
+                Arg::new("pe_watchdog").long("pe_watchdog")
+                    .help("Wall-clock time in seconds after which a running job is considered hung (default: derived from the cycle budget).")
+                    .long_help("Wall-clock time in seconds after which a running job is considered hung.\n\
+                                By default, twice the largest cycle budget (--timeout, --hang_timeout) at --pe_clock_mhz plus 5 seconds, and at least 10 seconds.\n\
+                                Watchdogs shorter than the largest cycle budget are rejected, since valid runs would be considered hung.\n\
+                                A hung PE gets one more watchdog period as a grace period to complete, and is quarantined otherwise.\n\
+                                The job is then retried on another PE.")
+                    .validator(|s| s.parse::<f64>().map_err(|e| e.to_string()).and_then(|v|
+                        if v > 0.0 { Ok(()) } else { Err("must be positive".to_string()) }))
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("pe_clock_mhz").long("pe_clock_mhz")
+                    .help("Clock frequency of the PEs in MHz, to convert the cycle budget into the wall-clock time for --pe_watchdog.")
+                    .validator(|s| s.parse::<f64>().map_err(|e| e.to_string()).and_then(|v|
+                        if v > 0.0 { Ok(()) } else { Err("must be positive".to_string()) }))
+                    .default_value("100")
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("pe_max_errors").long("pe_max_errors")
+                    .help("Number of failed runs in a row (e.g. DMA errors) after which a PE is recovered, or quarantined if it was recovered before.")
+                    .validator(|s| s.parse::<u32>().map_err(|e| e.to_string()).and_then(|v|
+                        if v > 0 { Ok(()) } else { Err("must be at least 1".to_string()) }))
+                    .default_value("3")
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("pe_policy").long("pe_policy")
+                    .help("How the PEs are split across the fuzzing threads.")
+                    .long_help("How the PEs are split into pools (dispatchers) for the fuzzing threads:\n\
+                                shared: A single pool with all PEs.\n\
+                                thread: An exclusive pool per thread, with the PEs distributed evenly.\n\
+                                device: A pool per FPGA device.\n\
+                                petype: A pool per PE type (VLNV).\n\
+                                Threads are assigned to the pools round-robin. If the own pool has no idle PE, a thread takes an idle PE of another pool.")
+                    .possible_values(DispatcherPolicy::NAMES)
+                    .default_value("shared")
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("no_work_stealing").long("no_work_stealing")
+                    .help("Only use the PEs of the thread's own pool (see --pe_policy), even if PEs of other pools are idle.")
+            )
         )   
-        .arg(
-            Arg::new("binary")
-                .help("The instrumented binary we want to fuzz")
-                .required(true)
-                .takes_value(true),
+        .subcommand(Command::new("emu")
+            .about("Run on a software RV32IM emulation of the PE (no FPGA or simulator required).")
+            .arg(
+                Arg::new("pe_type").long("pe_type")
+                    .help("PE type to emulate the memory layout of (e.g. cva5_bram_pe_fuzzer).")
+                    .long_help("PE type to emulate the memory layout of, either as the full VLNV or as its name (e.g. cva5_bram_pe_fuzzer).\n\
+                                Includes the PE types of the campaign config (--config). The program must be linked for the same layout.")
+                    .default_value("cva5_pe_fuzzer")
+                    .takes_value(true)
+            )
         )
-        .arg(
-            Arg::new("in")
-                .help("The directory to read initial inputs from ('seeds')")
-                .required(true)
-                .takes_value(true),
+        .subcommand(Command::new("remote")
+            .about("Run on the backend of a serve process (e.g. PEs shared with other campaigns).")
+            .arg(
+                Arg::new("address")
+                    .help("Unix socket path or TCP host:port address of the serve process")
+                    .required(true)
+                    .takes_value(true),
+            )
         )
         .arg(
             Arg::new("ignore_min").long("ignore_min")
@@ -386,6 +739,347 @@ pub fn main() {
                 .takes_value(true)
                 .default_value("8192"),
         )
+        .arg(
+            Arg::new("timeout").long("timeout")
+                .help("Timeout for each individual execution, in PE cycles")
+                .validator(|s| s.parse::<u64>())
+                .default_value("400000000") //Equivalent to 4 seconds at 100 MHz
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("config").long("config")
+                .help("Campaign config file (TOML, or JSON with a .json extension) with PE memory layouts by VLNV")
+                .long_help("Campaign config file (TOML, or JSON with a .json extension) that maps PE VLNVs to memory layouts.\n\
+                            Entries start from a built-in layout ('base') and override individual values.\n\
+                            The built-in PE types remain supported unless overridden.")
+                .takes_value(true),
+        )
+}
+
+//Returns the PE profiles, i.e. the built-in profiles with the changes from the campaign config (if any).
+fn load_pe_profiles(cmd_matches: &ArgMatches) -> Result<Vec<PEProfile>, FuzzErr> {
+    let config = match cmd_matches.value_of("config") {
+        Some(config_path) => CampaignConfig::load(Path::new(config_path)).context(TapascoRunSnafu)?,
+        None => CampaignConfig::default()
+    };
+    config.pe_profiles().context(TapascoRunSnafu)
+}
+
+//Returns the memory layout of the PE type selected in the emu subcommand arguments.
+fn emu_memconfig(cmd_matches: &ArgMatches, emucmd_matches: &ArgMatches) -> Result<PEMemConfig, FuzzErr> {
+    let pe_type = emucmd_matches.value_of("pe_type").ok_or(FuzzErr::ArgError{s:"pe_type argument missing".to_string()})?;
+    let profiles = load_pe_profiles(cmd_matches)?;
+    let profile = profiles.iter().find(|profile| PESelection::matches(pe_type, &profile.vlnv))
+        .ok_or_else(|| FuzzErr::ArgError{s: format!("Unknown PE type '{}'", pe_type)})?;
+    if profile.tapascoriscv {
+        return Err(FuzzErr::ArgError{s: format!("PE type '{}' has no fuzzer hardware to emulate", pe_type)});
+    }
+    Ok(profile.memconf)
+}
+
+//Returns the device and PE selection from the tapasco subcommand arguments.
+fn pe_selection(tapascocmd_matches: &ArgMatches) -> PESelection {
+    PESelection {
+        devices: tapascocmd_matches.values_of("device").map_or(Vec::new(), |values| values.map(|s| s.parse().unwrap()).collect()),
+        pes: tapascocmd_matches.values_of("pes").map_or(Vec::new(), |values| values.map(|s| PESelection::parse_pes_entry(s).unwrap()).collect()),
+        shared_access: tapascocmd_matches.is_present("shared_access"),
+    }
+}
+
+//Fetches the selected PEs of the TaPaSCo devices (by default, all PEs of all devices).
+fn open_tapasco_pes(cmd_matches: &ArgMatches) -> Vec<PEDesc> {
+    let profiles = load_pe_profiles(cmd_matches).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    let selection = cmd_matches.subcommand_matches("tapasco").map(pe_selection).unwrap_or_default();
+    let tlkm = tapasco::tlkm::TLKM::new().unwrap();
+    let mut devices = tlkm.device_enum(&std::collections::HashMap::new()).unwrap();
+    tapasco_runner_phys::get_pes(&mut devices[..], &profiles, &selection).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    })
+}
+
+//Returns the TapascoDispatcher settings from the tapasco subcommand arguments.
+// * `max_cycles`: Largest cycle budget of the runs, which the watchdog must not cut short.
+fn dispatcher_config(tapascocmd_matches: &ArgMatches, max_cycles: u64) -> DispatcherConfig {
+    let clock_mhz = tapascocmd_matches.value_of("pe_clock_mhz").unwrap().parse::<f64>().unwrap();
+    let budget_secs = max_cycles as f64 / (clock_mhz * 1e6);
+    let watchdog_secs = match tapascocmd_matches.value_of("pe_watchdog") {
+        Some(s) => {
+            let watchdog_secs = s.parse::<f64>().unwrap();
+            if watchdog_secs <= budget_secs {
+                eprintln!("The PE watchdog ({} s) must be longer than the cycle budget of {} cycles ({:.1} s at {} MHz).",
+                    watchdog_secs, max_cycles, budget_secs, clock_mhz);
+                std::process::exit(1);
+            }
+            watchdog_secs
+        },
+        None => f64::max(2.0 * budget_secs + 5.0, 10.0)
+    };
+    DispatcherConfig {
+        data_snapshot: tapascocmd_matches.is_present("data_snapshot"),
+        watchdog: std::time::Duration::from_secs_f64(watchdog_secs),
+        max_pe_errors: tapascocmd_matches.value_of("pe_max_errors").unwrap().parse::<u32>().unwrap(),
+    }
+}
+
+//Returns the PE split policy from the tapasco subcommand arguments.
+fn dispatcher_policy(tapascocmd_matches: &ArgMatches) -> DispatcherPolicy {
+    DispatcherPolicy::from_name(tapascocmd_matches.value_of("pe_policy").unwrap()).unwrap()
+}
+
+//Creates the TapascoDispatchers for a mode other than fuzzing, if its backend is TaPaSCo.
+// * `num_threads`: Number of threads the mode runs inputs from (1 for single-runner modes).
+fn mode_dispatchers(mode_matches: &ArgMatches, num_threads: usize) -> Vec<Arc<TapascoDispatcher>> {
+    mode_dispatchers_with_budget(mode_matches, num_threads, mode_matches.value_of("timeout").unwrap().parse::<u64>().unwrap())
+}
+
+//Creates the dispatchers for a mode, with the largest cycle budget of its runs given explicitly (e.g. the serve --max_timeout).
+fn mode_dispatchers_with_budget(mode_matches: &ArgMatches, num_threads: usize, max_cycles: u64) -> Vec<Arc<TapascoDispatcher>> {
+    match mode_matches.subcommand() {
+        Some(("tapasco", tapascocmd_matches)) => tapasco_runner_phys::create_dispatchers(
+            open_tapasco_pes(mode_matches),
+            dispatcher_config(tapascocmd_matches, max_cycles),
+            dispatcher_policy(tapascocmd_matches),
+            num_threads
+        ).unwrap(),
+        _ => Vec::new()
+    }
+}
+
+#[allow(clippy::similar_names)]
+pub fn main() {
+    let mut cmd = runner_args(Command::new("fuzzer_host_libafl"))
+        .about("Fuzzer host for TaPaSCo-based fuzzing accelerator")
+        //The mode subcommands (e.g. repro) have their own arguments.
+        .subcommand_negates_reqs(true)
+        .subcommand(runner_args(Command::new("repro"))
+            .about("Run a single input and print the result.")
+            .long_about("Run a single input and print the result.\n\
+                         Exit code: 0 if the program finished regularly, 1 on timeout, 2 on crash, 3 on error.")
+            .arg(
+                Arg::new("binary")
+                    .help("The program to run (ELF file, or flat binary created by objcopy)")
+                    .required(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::new("input")
+                    .help("The input file")
+                    .required(true)
+                    .takes_value(true),
+            )
+        )
+        .subcommand(runner_args(Command::new("showmap"))
+            .about("Run inputs and write their bitmaps in the afl-showmap format.")
+            .long_about("Run inputs and write their bitmaps in the afl-showmap format (one 'index:count' line per non-zero entry).\n\
+                         For a single input file, the output is written to the given file (or stdout for '-').\n\
+                         Otherwise, the output path is a directory and one file is written per input, named as the input.\n\
+                         Exit code (single input file only): 0 if the program finished regularly, 1 on timeout, 2 on crash, 3 on error.")
+            .arg(
+                Arg::new("binary")
+                    .help("The program to run (ELF file, or flat binary created by objcopy)")
+                    .required(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::new("inputs")
+                    .help("Input files or directories")
+                    .required(true)
+                    .multiple_values(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::new("output").long("output").short('o')
+                    .help("Output file or directory")
+                    .required(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::new("raw").long("raw").short('r')
+                    .help("Write the raw counter values instead of afl-showmap's count classes")
+            )
+            .arg(
+                Arg::new("binary_dump").long("binary_dump")
+                    .help("Write the bitmap as a binary dump instead of the text format")
+            )
+        )
+        .subcommand(runner_args(Command::new("cmin"))
+            .about("Minimize a corpus, keeping the smallest set of inputs that preserves all bitmap entries.")
+            .long_about("Minimize a corpus, keeping the smallest set of inputs that preserves all bitmap entries (as afl-cmin does).\n\
+                         Among inputs with the same entry, smaller and then faster inputs are preferred.\n\
+                         Crashing and timing out inputs are excluded.")
+            .arg(
+                Arg::new("binary")
+                    .help("The program to run (ELF file, or flat binary created by objcopy)")
+                    .required(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::new("input")
+                    .help("The corpus directory")
+                    .required(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::new("output").long("output").short('o')
+                    .help("Output directory for the minimized corpus")
+                    .required(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::new("edges_only").long("edges_only").short('e')
+                    .help("Only consider whether an entry is non-zero, ignoring the hit count classes")
+            )
+            .arg(
+                Arg::new("numthreads").long("numthreads")
+                    .help("Number of threads running inputs")
+                    .validator(|s| s.parse::<usize>().map_err(|e| e.to_string())
+                        .and_then(|n| if n > 0 {Ok(n)} else {Err("At least one thread required".to_string())}))
+                    .default_value("1")
+                    .takes_value(true),
+            )
+        )
+        .subcommand(runner_args(Command::new("replay"))
+            .about("Replay AFL++ queue entries by modification time, and write the bitmap coverage over time as CSV.")
+            .long_about("Replay AFL++ queue entries by modification time, and write the accumulated bitmap coverage over time as CSV\n\
+                         (columns: seconds since the campaign start, number of replayed inputs, non-zero bitmap entries, and entries by hit count class).\n\
+                         The campaign start is read from the fuzzer_stats file next to the queue directory,\n\
+                         or else taken from the first entry that is not a copy of a seed ('orig:'). Earlier entries are placed at time 0.\n\
+                         Crashing and timing out inputs do not contribute coverage.")
+            .arg(
+                Arg::new("binary")
+                    .help("The program to run (ELF file, or flat binary created by objcopy)")
+                    .required(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::new("inputs")
+                    .help("AFL++ queue directories (or single queue files)")
+                    .required(true)
+                    .multiple_values(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::new("output").long("output").short('o')
+                    .help("Output CSV file")
+                    .required(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::new("start_time").long("start_time")
+                    .help("Campaign start in seconds since the epoch, instead of the start in fuzzer_stats")
+                    .validator(|s| s.parse::<f64>())
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::new("interval").long("interval")
+                    .help("Write one line per interval of the given seconds with new coverage (e.g. 60), instead of one line per input with new coverage")
+                    .validator(|s| s.parse::<f64>().map_err(|e| e.to_string()).and_then(|v|
+                        if v > 0.0 { Ok(()) } else { Err("must be positive".to_string()) }))
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::new("numthreads").long("numthreads")
+                    .help("Number of threads running inputs")
+                    .validator(|s| s.parse::<usize>().map_err(|e| e.to_string())
+                        .and_then(|n| if n > 0 {Ok(n)} else {Err("At least one thread required".to_string())}))
+                    .default_value("1")
+                    .takes_value(true),
+            )
+        )
+        .subcommand(runner_args(Command::new("afl_forkserver"))
+            .about("Act as an AFL++ forkserver target, running each testcase on the backend.")
+            .long_about("Act as an AFL++ forkserver target, running each testcase on the backend.\n\
+                         The bitmap is written to AFL++'s coverage map (__AFL_SHM_ID), and crashes are reported as signals\n\
+                         (e.g. SIGSEGV for access faults, SIGILL for illegal instructions).\n\
+                         Timeouts (--timeout cycles) are only reported to AFL++ once its own time limit (-t) kills the placeholder child.\n\
+                         Example: afl-fuzz -i in -o out -- fuzzer_host_libafl afl_forkserver prog.elf @@ tapasco")
+            .arg(
+                Arg::new("binary")
+                    .help("The program to run (ELF file, or flat binary created by objcopy)")
+                    .required(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::new("input")
+                    .help("The testcase file (@@ for AFL++). Read from stdin if not given.")
+                    .takes_value(true),
+            )
+        )
+        .subcommand(runner_args(Command::new("serve"))
+            .about("Share the backend (e.g. the TaPaSCo PEs) with fuzzer processes that use the remote backend.")
+            .long_about("Share the backend (e.g. the TaPaSCo PEs) with fuzzer processes that use the remote backend.\n\
+                         Each client sends its program, timeout and ignore_min settings on connection, and then its inputs.\n\
+                         The inputs of all clients are distributed across the PEs, and the PE memories are reprogrammed when the program changes.")
+            .arg(
+                Arg::new("listen").long("listen")
+                    .help("Unix socket path or TCP host:port address to listen on")
+                    .required(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::new("allow_remote").long("allow_remote")
+                    .help("Allow listening on TCP addresses other than loopback.")
+                    .long_help("Allow listening on TCP addresses other than loopback (e.g. 0.0.0.0:port).\n\
+                                The protocol has no authentication, so any host that can connect can run programs on the backend.")
+            )
+            .arg(
+                Arg::new("max_timeout").long("max_timeout")
+                    .help("Largest timeout accepted from clients, in PE cycles")
+                    .long_help("Largest timeout accepted from clients, in PE cycles. Clients with a timeout of 0 (unbounded) or above are rejected.\n\
+                                The default covers the default --timeout and --hang_timeout of the fuzzer. Also sets the PE watchdog (see tapasco --pe_watchdog).")
+                    .validator(|s| s.parse::<u64>().map_err(|e| e.to_string()).and_then(|v|
+                        if v > 0 { Ok(()) } else { Err("must be at least 1".to_string()) }))
+                    .default_value("800000000")
+                    .takes_value(true)
+            )
+        )
+        .subcommand(runner_args(Command::new("tmin"))
+            .about("Minimize a crashing or timing out input, preserving the exception cause and epc or the timeout.")
+            .long_about("Minimize a crashing or timing out input, preserving the exception cause and epc or the timeout.\n\
+                         Uses block deletion and byte simplification passes (as afl-tmin does).\n\
+                         With TaPaSCo, the candidates are run in parallel on all PEs.")
+            .arg(
+                Arg::new("binary")
+                    .help("The program to run (ELF file, or flat binary created by objcopy)")
+                    .required(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::new("input")
+                    .help("The input file to minimize")
+                    .required(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::new("output").long("output").short('o')
+                    .help("Output file for the minimized input")
+                    .required(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::new("numthreads").long("numthreads")
+                    .help("Number of candidates to run in parallel (default: the number of TaPaSCo PEs, or 1)")
+                    .validator(|s| s.parse::<usize>().map_err(|e| e.to_string())
+                        .and_then(|n| if n > 0 {Ok(n)} else {Err("At least one thread required".to_string())}))
+                    .takes_value(true),
+            )
+        )
+        .arg(
+            Arg::new("binary")
+                .help("The program we want to fuzz (ELF file, or flat binary created by objcopy)")
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("in")
+                .help("The directory to read initial inputs from ('seeds')")
+                .required(true)
+                .takes_value(true),
+        )
         .arg(
             Arg::new("tokenfile").long("tokenfile") //Positional arg
                 .help("Token dictionary file path")
@@ -418,6 +1112,16 @@ pub fn main() {
                 .default_value("1")
                 .takes_value(true),
         )
+        .arg(
+            Arg::new("batch").long("batch")
+                .help("Number of mutated inputs each fuzzing thread runs at once")
+                .long_help("Number of mutated inputs each fuzzing thread runs at once.\n\
+                            With TaPaSCo, a batch is spread across the idle PEs, so fewer threads can keep all PEs busy.")
+                .validator(|s| s.parse::<usize>().map_err(|e| e.to_string())
+                    .and_then(|n| if n > 0 {Ok(n)} else {Err("Batch size must be at least 1".to_string())}))
+                .default_value("1")
+                .takes_value(true),
+        )
         .arg(
             Arg::new("cores").long("cores") //Positional arg
                 .help("Comma-separated list of cores to run the fuzzing threads on.")
@@ -426,17 +1130,125 @@ pub fn main() {
                 .takes_value(true),
         )
         .arg(
-            Arg::new("timeout").long("timeout")
-                .help("Timeout for each individual execution, in PE cycles")
+            Arg::new("hang_timeout").long("hang_timeout")
+                .help("Cycle budget to confirm timeouts with before storing them as hangs (default: twice the timeout)")
                 .validator(|s| s.parse::<u64>())
-                .default_value("400000000") //Equivalent to 4 seconds at 100 MHz
                 .takes_value(true),
         )
+        .arg(
+            Arg::new("crash_dedup").long("crash_dedup")
+                .help("Crash deduplication strategy")
+                .long_help("Crash deduplication strategy.\n\
+                            coverage: Keep crashes that trigger new coverage over all previous crashes (as AFL does).\n\
+                            exception: Keep one crash per exception cause, epc and masked tval (see --crash_tval_mask).")
+                .possible_values(["coverage", "exception"])
+                .default_value("coverage")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::new("crash_tval_mask").long("crash_tval_mask")
+                .help("Mask applied to tval for exception-based crash deduplication. 32bit hex without 0x")
+                .validator(|s| u32::from_str_radix(s, 16))
+                .default_value("0")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::new("out").long("out").short('o')
+                .help("Output directory, with the queue, crashes and hangs subdirectories")
+                .long_help("Output directory, with the queue, crashes and hangs subdirectories.\n\
+                            The fuzzer_stats and plot_data files are also written there.\n\
+                            If not set, ./runtimecorpus, ./crashes and ./hangs (and the stats files in the working directory) are used.")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::new("afl_sync").long("afl_sync")
+                .help("AFL++ output directory to import new queue entries from. Can be repeated.")
+                .long_help("AFL++ output directory (-o) to import new queue entries from, or the directory or queue of a single instance.\n\
+                            New entries are run periodically (see --afl_sync_interval), and added to the corpus if they are interesting.\n\
+                            Can be repeated.")
+                .multiple_occurrences(true)
+                .takes_value(true)
+        )
+        .arg(
+            Arg::new("afl_export").long("afl_export")
+                .help("AFL++ instance directory to export the corpus entries to (e.g. <AFL++ output directory>/tapafuzz)")
+                .long_help("AFL++ instance directory to export the corpus entries to, in AFL++ naming (queue/id:NNNNNN,...).\n\
+                            Placed in the output directory of AFL++ instances started with -M or -S (e.g. <AFL++ output directory>/tapafuzz),\n\
+                            the entries are picked up by their sync. Entries that AFL++ synced from this directory are not imported again.")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::new("afl_sync_interval").long("afl_sync_interval")
+                .help("Seconds between two AFL++ syncs")
+                .validator(|s| s.parse::<u64>())
+                .default_value("60")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::new("stats_interval").long("stats_interval")
+                .help("Seconds between two updates of the fuzzer_stats and plot_data files")
+                .validator(|s| s.parse::<u64>().map_err(|e| e.to_string())
+                    .and_then(|n| if n > 0 {Ok(n)} else {Err("Interval must be at least 1".to_string())}))
+                .default_value("60")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::new("resume").long("resume")
+                .help("Continue a previous campaign, importing its queue instead of the seeds")
+        )
         .arg(
             Arg::new("benchmark").long("benchmark")
                 .help("Run benchmark mode instead of fuzzer")
         )
-        .get_matches();
+        .arg(
+            Arg::new("random_inputs").long("random_inputs")
+                .help("Benchmark with random inputs of the given size in bytes if no input directory is given")
+                .validator(|s| s.parse::<usize>())
+                .requires("benchmark")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::new("random_count").long("random_count")
+                .help("Number of random inputs for --random_inputs")
+                .validator(|s| s.parse::<usize>().map_err(|e| e.to_string())
+                    .and_then(|n| if n > 0 {Ok(n)} else {Err("At least one input required".to_string())}))
+                .default_value("64")
+                .takes_value(true)
+        );
+    let cmd_matches = cmd.get_matches_mut();
+
+    //Modes that run in a single thread and exit afterwards.
+    //Thread count of the modes with a numthreads argument (only query it there, since clap rejects unknown argument names).
+    let mode_num_threads = |mode_matches: &ArgMatches| mode_matches.value_of("numthreads").map_or(1, |s| s.parse::<usize>().unwrap());
+    let mode_result = match cmd_matches.subcommand() {
+        Some(("repro", mode_matches)) => Some(repro::repro(mode_matches, mode_dispatchers(mode_matches, 1))),
+        Some(("showmap", mode_matches)) => Some(showmap::showmap(mode_matches, mode_dispatchers(mode_matches, 1))),
+        Some(("cmin", mode_matches)) => Some(cmin::cmin(mode_matches, mode_dispatchers(mode_matches, mode_num_threads(mode_matches)))),
+        Some(("tmin", mode_matches)) => Some(tmin::tmin(mode_matches, mode_dispatchers(mode_matches, mode_num_threads(mode_matches)))),
+        Some(("replay", mode_matches)) => Some(replay::replay(mode_matches, mode_dispatchers(mode_matches, mode_num_threads(mode_matches)))),
+        Some(("serve", mode_matches)) => {
+            let max_cycles = std::cmp::max(mode_matches.value_of("timeout").unwrap().parse::<u64>().unwrap(),
+                mode_matches.value_of("max_timeout").unwrap().parse::<u64>().unwrap());
+            Some(serve::serve(mode_matches, mode_dispatchers_with_budget(mode_matches, 1, max_cycles)))
+        },
+        Some(("afl_forkserver", mode_matches)) => Some(afl_forkserver::afl_forkserver(mode_matches, mode_dispatchers(mode_matches, 1))),
+        _ => None
+    };
+    if let Some(res) = mode_result {
+        let exit_code = match res {
+            Ok(exit_kind) => repro::exit_code(&exit_kind),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                repro::EXIT_CODE_ERROR
+            }
+        };
+        std::process::exit(exit_code);
+    }
+    let random_benchmark = cmd_matches.is_present("benchmark") && cmd_matches.is_present("random_inputs");
+    if !cmd_matches.is_present("binary") || (!cmd_matches.is_present("in") && !random_benchmark) {
+        cmd.error(clap::ErrorKind::MissingRequiredArgument,
+            "The following required arguments were not provided: <binary> <in>").exit();
+    }
 
     let num_threads = cmd_matches.value_of("numthreads").unwrap().parse::<usize>().unwrap();
     let mut cores = Cores::from_cmdline(cmd_matches.value_of("cores").unwrap()).map(|coress| coress.ids).unwrap_or_default();
@@ -452,18 +1264,25 @@ pub fn main() {
     let mut broker = LlmpBroker::new(shmem_provider.clone()).unwrap();
 
     let mut tapasco_dispatchers: Vec<Arc<TapascoDispatcher>> = Vec::new();
+    let mut work_stealing = true;
 
-    if let Some(("tapasco", _tapascocmd_matches)) = cmd_matches.subcommand() {
+    if let Some(("tapasco", tapascocmd_matches)) = cmd_matches.subcommand() {
         //For TaPaSCo mode, fetch the available PEs and create TapascoDispatchers to distribute to the threads.
-        let tlkm = tapasco::tlkm::TLKM::new().unwrap();
-        let mut devices = tlkm.device_enum(&std::collections::HashMap::new()).unwrap();
-        let pes = tapasco_runner_phys::get_pes(&mut devices[..]).unwrap();
+        let pes = open_tapasco_pes(&cmd_matches);
+        let timeout_cycles = cmd_matches.value_of("timeout").unwrap().parse::<u64>().unwrap();
+        let hang_timeout_cycles = cmd_matches.value_of("hang_timeout").map_or(timeout_cycles.saturating_mul(2), |s| s.parse::<u64>().unwrap());
+        let config = dispatcher_config(tapascocmd_matches, std::cmp::max(timeout_cycles, hang_timeout_cycles));
         if cmd_matches.is_present("benchmark") && pes.len() >= num_threads {
-            tapasco_dispatchers = pes.into_iter().map(|pedesc| TapascoDispatcher::new(vec![pedesc]).unwrap()).collect();
+            tapasco_dispatchers = pes.into_iter().map(|pedesc| TapascoDispatcher::new(vec![pedesc], config).unwrap()).collect();
         }
         else {
-            tapasco_dispatchers = tapasco_runner_phys::create_dispatchers(pes).unwrap();
+            tapasco_dispatchers = tapasco_runner_phys::create_dispatchers(pes, config, dispatcher_policy(tapascocmd_matches), num_threads).unwrap();
+            if tapasco_dispatchers.len() > 1 {
+                println!("Split the PEs into {} pools of {:?} PEs.", tapasco_dispatchers.len(),
+                    tapasco_dispatchers.iter().map(|dispatcher| dispatcher.num_pes()).collect::<Vec<_>>());
+            }
         }
+        work_stealing = !tapascocmd_matches.is_present("no_work_stealing");
     }
 
     struct ThreadEntry {
@@ -472,9 +1291,8 @@ pub fn main() {
     let mut threads: Vec<ThreadEntry> = Vec::with_capacity(num_threads);
 
     if cmd_matches.is_present("benchmark") {
-        let corpus_path = cmd_matches.value_of("in").unwrap().to_string();
-        let inputs: Arc<Vec<BytesInput>> = Arc::new(
-            std::fs::read_dir(corpus_path).unwrap()
+        let inputs: Arc<Vec<BytesInput>> = Arc::new(match cmd_matches.value_of("in") {
+            Some(corpus_path) => std::fs::read_dir(corpus_path).unwrap()
                 .map(|res| res.unwrap().path())
                 .filter(|path| path.is_file() //Is a file ...
                     && path.file_name()                //... that does not start with "."
@@ -485,11 +1303,21 @@ pub fn main() {
                     let mut buf = Vec::new();
                     std::fs::File::open(path).unwrap().read_to_end(&mut buf).unwrap();
                     Some(BytesInput::from(buf))
-                }).collect()
-        );
+                }).collect(),
+            None => {
+                //No corpus, generate random inputs.
+                let input_size = cmd_matches.value_of("random_inputs").unwrap().parse::<usize>().unwrap();
+                let input_count = cmd_matches.value_of("random_count").unwrap().parse::<usize>().unwrap();
+                let mut rand = StdRand::with_seed(current_nanos());
+                println!("Generated {} random inputs of {} bytes.", input_count, input_size);
+                (0..input_count).map(|_| BytesInput::from((0..input_size).map(|_| rand.below(256) as u8).collect::<Vec<u8>>())).collect()
+            }
+        });
 
         let barrier_threadstart = Arc::new(Barrier::new(num_threads + 1));
-        let total_executions = Arc::new(AtomicU64::new(0));
+        let total_stats = Arc::new(std::sync::Mutex::new(BenchmarkStats::default()));
+        //Keep a reference to each dispatcher for the upload statistics.
+        let all_dispatchers = tapasco_dispatchers.clone();
         unsafe { GLOBAL_SIGINT_HOOK.install_nohook(); }
         let pre_start_time = std::time::Instant::now();
         for i in 0..num_threads {
@@ -502,7 +1330,7 @@ pub fn main() {
             }
             let thr_cmd_matches = cmd_matches.clone();
             let thr_barrier_threadstart = barrier_threadstart.clone();
-            let thr_total_executions = total_executions.clone();
+            let thr_total_stats = total_stats.clone();
             let thr_inputs = inputs.clone();
             let mut thr_core: Option<CoreId> = None;
             if cores.len() > i {
@@ -523,8 +1351,8 @@ pub fn main() {
                     stop_signal_arrived);
                 
                 match res {
-                    Ok(n_exec) => {
-                        thr_total_executions.fetch_add(n_exec, std::sync::atomic::Ordering::SeqCst);
+                    Ok(stats) => {
+                        thr_total_stats.lock().unwrap().merge(&stats);
                     }
                     Err(_) => {
                         println!("Thread {} shutting down due to an error.",  i);
@@ -544,17 +1372,51 @@ pub fn main() {
         }
         let elapsed_run = run_start_time.elapsed().as_secs_f64();
         println!("Benchmark finished after {:.3e} seconds.", elapsed_run);
+        let stats = *total_stats.lock().unwrap();
         if elapsed_run > 1e-9 {
-            let num_total_executions = total_executions.load(std::sync::atomic::Ordering::SeqCst);
             println!("Total executions per second: {:.2}",
-                (num_total_executions as f64) / elapsed_run);
+                (stats.executions as f64) / elapsed_run);
             println!("Executions per thread and second: {:.2}",
-                (num_total_executions as f64) / elapsed_run / (num_threads as f64));
+                (stats.executions as f64) / elapsed_run / (num_threads as f64));
+        }
+        println!("Outcomes: {} ok, {} crashes, {} timeouts.", stats.ok, stats.crashes, stats.timeouts);
+        if stats.cycles_runs > 0 {
+            println!("PE cycles per execution: min {}, mean {:.1}, max {}.",
+                stats.cycles_min, stats.cycles_sum as f64 / stats.cycles_runs as f64, stats.cycles_max);
+        }
+        //Compare the data memory uploads (including the initial runs) to uploading the full data section each run.
+        let upload_stats = all_dispatchers.iter().map(|dispatcher| dispatcher.data_upload_stats())
+            .fold(DataUploadStats::default(), |acc, stats| DataUploadStats {
+                runs: acc.runs + stats.runs,
+                bytes: acc.bytes + stats.bytes,
+                bytes_full: acc.bytes_full + stats.bytes_full
+            });
+        if upload_stats.runs > 0 {
+            println!("Data memory upload per execution: {:.1} bytes (full upload: {:.1} bytes, {:.1}% saved).",
+                upload_stats.bytes as f64 / upload_stats.runs as f64,
+                upload_stats.bytes_full as f64 / upload_stats.runs as f64,
+                100.0 * (1.0 - upload_stats.bytes as f64 / std::cmp::max(upload_stats.bytes_full, 1) as f64));
         }
+        report_latency(&all_dispatchers);
+        report_pe_health(&all_dispatchers);
 
         return;
     }
 
+    //Prepare the output directories.
+    let out_dirs = OutputDirs::from_matches(&cmd_matches);
+    out_dirs.create_all().unwrap();
+    if cmd_matches.is_present("resume") {
+        let num_entries = out_dirs.prepare_resume().unwrap();
+        println!("Resuming with {} entries of the previous queue.", num_entries);
+    }
+    else if !OutputDirs::list_entries(&out_dirs.queue).unwrap().is_empty() {
+        println!("Warning: The queue directory {:?} is not empty. Use --resume to continue the previous campaign.", &out_dirs.queue);
+    }
+    //Crash buckets of all threads, since they share the crashes directory.
+    let crash_buckets: SharedCrashBuckets = Arc::new(std::sync::Mutex::new(
+        CrashBuckets::new(Some(out_dirs.stats.join("crash_buckets.json")), cmd_matches.is_present("resume")).unwrap()));
+
     //Initialize synchronization barriers.
     let barrier_sighookstart = Arc::new(Barrier::new(num_threads + 1));
     //Access to GLOBAL_SIGINT_HOOK requires unsafe, as it is a static mut.
@@ -562,7 +1424,7 @@ pub fn main() {
     let barrier_threadend = Arc::new(Barrier::new(num_threads));
     let once_threadend = Arc::new(Once::new());
     for i in 0..num_threads {
-        let thr_dispatchers = tapasco_dispatchers.clone();
+        let thr_dispatchers = tapasco_runner_phys::thread_dispatchers(&tapasco_dispatchers, i, work_stealing);
         //Create an LlmpClient for the thread and register it with the LlmpBroker.
         let mut llmp_client = LlmpClient::new(
             shmem_provider.clone(),
@@ -587,6 +1449,7 @@ pub fn main() {
         let llmp_client_share = unsafe_send_sync::UnsafeSend::new(std::sync::Mutex::new(Some(llmp_client)));
         //Clone objects to pass to the thread.
         let thr_cmd_matches = cmd_matches.clone();
+        let thr_crash_buckets = crash_buckets.clone();
         let thr_barrier_sighookstart = barrier_sighookstart.clone();
         let thr_barrier_threadend = barrier_threadend.clone();
         let thr_once_threadend = once_threadend.clone();
@@ -609,7 +1472,7 @@ pub fn main() {
 
             let stop_signal_arrived = unsafe {&GLOBAL_SIGINT_HOOK.signal_arrived};
             //Run the fuzzer on this thread.
-            let res = fuzz(i, thr_cmd_matches, llmp_client_opt.unwrap(), thr_dispatchers, stop_signal_arrived);
+            let res = fuzz(i, thr_cmd_matches, llmp_client_opt.unwrap(), thr_dispatchers, thr_crash_buckets, stop_signal_arrived);
             //Wait for the other threads to finish/error out.
             thr_barrier_threadend.wait();
             //Notify the main thread through LlmpBroker's signal handler.
@@ -639,6 +1502,9 @@ pub fn main() {
             GLOBAL_SIGINT_HOOK.install();
         }
     });
+    // Also write the stats to fuzzer_stats and plot_data.
+    let stats_interval = std::time::Duration::from_secs(cmd_matches.value_of("stats_interval").unwrap().parse::<u64>().unwrap());
+    let monitor = StatsFileMonitor::new(monitor, out_dirs.stats.clone(), stats_interval, MAP_OBSERVER_NAME, tapasco_dispatchers.clone()).unwrap();
     //Create the event broker out of the generic LlmpBroker.
     let mut event_broker : LlmpEventBroker<BytesInput,_,_> = LlmpEventBroker::new(broker, monitor).unwrap();
     //Run the broker.
@@ -649,6 +1515,59 @@ pub fn main() {
     for thread in threads {
         thread.handle.join().unwrap();
     }
+    if cmd_matches.is_present("resume") {
+        //All threads have added the resumed entries to the queue, so they are not replayed on the next resume.
+        if let Err(e) = std::fs::remove_dir_all(&out_dirs.resume) {
+            println!("Failed to remove the resume directory {:?}: {}", &out_dirs.resume, e);
+        }
+    }
+    report_crash_buckets(&crash_buckets);
+    report_latency(&tapasco_dispatchers);
+    report_pe_health(&tapasco_dispatchers);
+}
+
+//Prints the hit counts of the crash buckets over all threads, if any (with --crash_dedup exception).
+fn report_crash_buckets(crash_buckets: &SharedCrashBuckets) {
+    let buckets = crash_buckets.lock().unwrap();
+    if buckets.hits().is_empty() {
+        return;
+    }
+    println!("Crash buckets:");
+    for (bucket, hits) in buckets.hits() {
+        println!(" Cause {}, epc 0x{:08x}, tval 0x{:08x}: {} hits", bucket.cause, bucket.epc, bucket.tval, hits);
+    }
+}
+
+//Prints the mean phase times per job of each PE and over all PEs, if any jobs ran on TaPaSCo PEs.
+fn report_latency(tapasco_dispatchers: &[Arc<TapascoDispatcher>]) {
+    let mut pe_latency: Vec<(usize, LatencyStats)> = tapasco_dispatchers.iter().flat_map(|dispatcher| dispatcher.pe_latency_stats()).collect();
+    let mut total = LatencyStats::default();
+    for (_, latency) in &pe_latency {
+        total.add(latency);
+    }
+    if total.runs == 0 {
+        return;
+    }
+    pe_latency.sort_by_key(|(index, _)| *index);
+    println!("Job phases (mean per job):");
+    for (index, latency) in &pe_latency {
+        println!(" PE {} ({} jobs): {}", index, latency.runs, latency.mean_summary());
+    }
+    println!(" All PEs ({} jobs): {}", total.runs, total.mean_summary());
+}
+
+//Prints the number of failed runs and of quarantined PEs, if any.
+fn report_pe_health(tapasco_dispatchers: &[Arc<TapascoDispatcher>]) {
+    let (mut num_errors, mut num_quarantined, mut num_pes) = (0, 0, 0);
+    for dispatcher in tapasco_dispatchers {
+        let health = dispatcher.pe_health();
+        num_errors += health.errors;
+        num_quarantined += health.quarantined;
+        num_pes += dispatcher.num_pes();
+    }
+    if num_errors > 0 || num_quarantined > 0 {
+        println!("PE failures: {} failed runs, {} of {} PEs quarantined.", num_errors, num_quarantined, num_pes);
+    }
 }
 
 struct SignalHandlerHook {
@@ -0,0 +1,186 @@
+//! Reactor that turns the TLKM completion interrupts of PEs into futures.
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::os::unix::io::RawFd;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Instant;
+
+//Wait state of an interrupt fd.
+enum InterruptState {
+    //The fd is armed, but no task has polled its future yet.
+    Armed,
+    //A task waits for the fd to become readable.
+    Waiting(Waker),
+    //The fd has become readable at the given time, but the waiting task has not seen it yet.
+    Ready(Instant),
+}
+
+struct ReactorShared {
+    epoll_fd: RawFd,
+    //eventfd to stop the reactor thread.
+    shutdown_fd: RawFd,
+    states: Mutex<HashMap<RawFd, InterruptState>>,
+    //Interrupt fds already added to the epoll instance.
+    registered: Mutex<HashSet<RawFd>>,
+}
+
+/// Waits for the interrupt eventfds of any number of PEs in a single background thread (using epoll).
+/// Each PE interrupt fd signals that the current job is done, and is read (acknowledged) by `PE::wait_for_completion`.
+/// Since the fds are level-triggered, `wait_for_completion` then returns without blocking.
+pub struct CompletionReactor {
+    shared: Arc<ReactorShared>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl CompletionReactor {
+    /// Creates the epoll instance and starts the reactor thread.
+    pub fn new() -> Result<Self, std::io::Error> {
+        let epoll_fd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        if epoll_fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let shutdown_fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) };
+        if shutdown_fd < 0 {
+            let err = std::io::Error::last_os_error();
+            unsafe { libc::close(epoll_fd); }
+            return Err(err);
+        }
+        let shared = Arc::new(ReactorShared {
+            epoll_fd: epoll_fd,
+            shutdown_fd: shutdown_fd,
+            states: Mutex::new(HashMap::new()),
+            registered: Mutex::new(HashSet::new()),
+        });
+        let mut shutdown_event = libc::epoll_event { events: libc::EPOLLIN as u32, u64: shutdown_fd as u64 };
+        if unsafe { libc::epoll_ctl(epoll_fd, libc::EPOLL_CTL_ADD, shutdown_fd, &mut shutdown_event) } < 0 {
+            let err = std::io::Error::last_os_error();
+            unsafe { libc::close(shutdown_fd); libc::close(epoll_fd); }
+            return Err(err);
+        }
+        let thr_shared = shared.clone();
+        let thread = std::thread::Builder::new()
+            .name("pe_completion".to_string())
+            .spawn(move || Self::reactor_loop(&thr_shared))?;
+        Ok(Self { shared: shared, thread: Some(thread) })
+    }
+
+    /// Returns a future that resolves once the interrupt fd is readable, i.e. once the PE has signalled completion.
+    /// The fd is armed right away, so the future yields the time the reactor saw the interrupt, even if it is polled later.
+    pub fn wait_readable(&self, fd: RawFd) -> InterruptFuture {
+        let mut future = InterruptFuture { shared: self.shared.clone(), fd: fd, armed: false };
+        //Replace a stale state from a previous job before arming. On failure, the first poll retries and reports the error.
+        self.shared.states.lock().unwrap().insert(fd, InterruptState::Armed);
+        if future.arm().is_ok() {
+            future.armed = true;
+        }
+        else {
+            self.shared.states.lock().unwrap().remove(&fd);
+        }
+        future
+    }
+
+    fn reactor_loop(shared: &ReactorShared) {
+        let mut events = vec![libc::epoll_event { events: 0, u64: 0 }; 64];
+        loop {
+            let num_events = unsafe { libc::epoll_wait(shared.epoll_fd, events.as_mut_ptr(), events.len() as i32, -1) };
+            if num_events < 0 {
+                if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+                    continue;
+                }
+                panic!("epoll_wait failed: {}", std::io::Error::last_os_error());
+            }
+            let mut states = shared.states.lock().unwrap();
+            let ready_time = Instant::now();
+            for event in &events[..num_events as usize] {
+                let fd = event.u64 as RawFd;
+                if fd == shared.shutdown_fd {
+                    return;
+                }
+                //The fd is registered with EPOLLONESHOT, and is re-armed by the next waiting future.
+                if let Some(InterruptState::Waiting(waker)) = states.insert(fd, InterruptState::Ready(ready_time)) {
+                    waker.wake();
+                }
+            }
+        }
+    }
+}
+
+impl Drop for CompletionReactor {
+    fn drop(&mut self) {
+        let one: u64 = 1;
+        unsafe { libc::write(self.shared.shutdown_fd, &one as *const u64 as *const libc::c_void, 8); }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        unsafe {
+            libc::close(self.shared.shutdown_fd);
+            libc::close(self.shared.epoll_fd);
+        }
+    }
+}
+
+/// Future returned by [`CompletionReactor::wait_readable`].
+pub struct InterruptFuture {
+    shared: Arc<ReactorShared>,
+    fd: RawFd,
+    armed: bool,
+}
+
+impl InterruptFuture {
+    //Adds the fd to the epoll instance, or re-arms it (EPOLLONESHOT).
+    fn arm(&self) -> Result<(), std::io::Error> {
+        let mut event = libc::epoll_event { events: (libc::EPOLLIN | libc::EPOLLONESHOT) as u32, u64: self.fd as u64 };
+        let mut registered = self.shared.registered.lock().unwrap();
+        let op = if registered.contains(&self.fd) { libc::EPOLL_CTL_MOD } else { libc::EPOLL_CTL_ADD };
+        if unsafe { libc::epoll_ctl(self.shared.epoll_fd, op, self.fd, &mut event) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        registered.insert(self.fd);
+        Ok(())
+    }
+}
+
+impl Future for InterruptFuture {
+    ///Time at which the reactor saw the interrupt.
+    type Output = Result<Instant, std::io::Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        {
+            let mut states = self.shared.states.lock().unwrap();
+            match states.get(&self.fd) {
+                Some(&InterruptState::Ready(ready_time)) if self.armed => {
+                    states.remove(&self.fd);
+                    return Poll::Ready(Ok(ready_time));
+                }
+                _ => {
+                    //A stale Ready state from a previous job would be replaced here, before the fd is armed again.
+                    states.insert(self.fd, InterruptState::Waiting(cx.waker().clone()));
+                }
+            }
+        }
+        if !self.armed {
+            //Arm after registering the waker, so the reactor can not miss the wakeup.
+            //If the PE is already done, the level-triggered fd is reported right away.
+            if let Err(e) = self.arm() {
+                self.shared.states.lock().unwrap().remove(&self.fd);
+                return Poll::Ready(Err(e));
+            }
+            self.armed = true;
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for InterruptFuture {
+    fn drop(&mut self) {
+        //Don't leave a waker behind if the future is dropped before completion.
+        if let Ok(mut states) = self.shared.states.lock() {
+            if let Some(InterruptState::Waiting(_) | InterruptState::Armed) = states.get(&self.fd) {
+                states.remove(&self.fd);
+            }
+        }
+    }
+}
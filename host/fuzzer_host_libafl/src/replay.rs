@@ -0,0 +1,101 @@
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use clap::ArgMatches;
+use libafl::executors::ExitKind;
+use snafu::ResultExt;
+
+use crate::{FuzzErr, TapascoRunSnafu};
+use crate::cmin::run_all_inputs;
+use crate::showmap::collect_input_files;
+use crate::tapasco_host::TapascoRunError;
+use crate::tapasco_runner_phys::TapascoDispatcher;
+
+//Seconds since the epoch of the file modification time.
+fn modified_secs(path: &Path) -> Result<f64, std::io::Error> {
+    let modified = std::fs::metadata(path)?.modified()?;
+    Ok(modified.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64())
+}
+
+//Reads the campaign start time from the fuzzer_stats file of the AFL++ instance owning the queue directory.
+fn afl_start_time(queue_dir: &Path) -> Option<f64> {
+    let stats = std::fs::read_to_string(queue_dir.parent()?.join("fuzzer_stats")).ok()?;
+    stats.lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(key, _)| key.trim() == "start_time")
+        .and_then(|(_, value)| value.trim().parse::<f64>().ok())
+}
+
+//Replays AFL++ queue entries by modification time, writing the accumulated bitmap coverage over time as CSV.
+pub fn replay(cmd_matches: &ArgMatches, tapasco_dispatchers: Vec<Arc<TapascoDispatcher>>) -> Result<ExitKind, FuzzErr> {
+    let inputs = cmd_matches.values_of("inputs").ok_or(FuzzErr::ArgError{s:"inputs argument missing".to_string()})?;
+    let output = cmd_matches.value_of("output").ok_or(FuzzErr::ArgError{s:"output argument missing".to_string()})?;
+    let num_threads = cmd_matches.value_of("numthreads").map_or(1, |s| s.parse::<usize>().unwrap());
+    let interval = cmd_matches.value_of("interval").map_or(0.0, |s| s.parse::<f64>().unwrap());
+
+    let input_paths: Vec<&str> = inputs.collect();
+    let mut timed_files: Vec<(f64, PathBuf)> = Vec::new();
+    for path in collect_input_files(input_paths.iter().copied()).map_err(TapascoRunError::from).context(TapascoRunSnafu)? {
+        let mtime = modified_secs(&path).map_err(TapascoRunError::from).context(TapascoRunSnafu)?;
+        timed_files.push((mtime, path));
+    }
+    timed_files.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap().then_with(|| a.1.cmp(&b.1)));
+    if timed_files.is_empty() {
+        return Err(FuzzErr::ArgError{s:"No inputs found".to_string()});
+    }
+
+    //Time origin: Given explicitly, the AFL++ campaign start, or the first entry not copied from the seeds ('orig:').
+    // Earlier entries (i.e. the seeds) are placed at time 0.
+    let start_time = match cmd_matches.value_of("start_time") {
+        Some(s) => s.parse::<f64>().unwrap(),
+        None => input_paths.iter()
+            .filter_map(|path| afl_start_time(Path::new(path)))
+            .reduce(f64::min)
+            .or_else(|| timed_files.iter()
+                .find(|(_, path)| !path.file_name().map_or(false, |name| name.to_string_lossy().contains(",orig:")))
+                .map(|(mtime, _)| *mtime))
+            .unwrap_or(timed_files[0].0)
+    };
+
+    let input_files = Arc::new(timed_files.iter().map(|(_, path)| path.clone()).collect::<Vec<PathBuf>>());
+    println!("Replaying {} inputs on {} threads.", input_files.len(), num_threads);
+    let entries = run_all_inputs(cmd_matches, tapasco_dispatchers, input_files.clone(), num_threads, false)?;
+
+    let mut out = std::fs::File::create(output).map_err(TapascoRunError::from).context(TapascoRunSnafu)?;
+    let mut csv = String::from("time_s,inputs,edges,tuples\n");
+    let mut edges: HashSet<u32> = HashSet::new();
+    let mut tuples: HashSet<u32> = HashSet::new();
+    //With an interval, one line per interval with new coverage (at its end). Otherwise, one line per input with new coverage.
+    let mut pending_line: Option<f64> = None;
+    for (i_input, ((mtime, _), entry)) in timed_files.iter().zip(&entries).enumerate() {
+        let time = (mtime - start_time).max(0.0);
+        let line_time = if interval > 0.0 { ((time / interval).floor() + 1.0) * interval } else { time };
+        if let Some(pending_time) = pending_line {
+            if pending_time != line_time {
+                csv += &format!("{:.3},{},{},{}\n", pending_time, i_input, edges.len(), tuples.len());
+                pending_line = None;
+            }
+        }
+        let entry = match entry { Some(entry) => entry, None => continue };
+        let mut new_coverage = false;
+        for &tuple in &entry.tuples {
+            new_coverage |= tuples.insert(tuple);
+            edges.insert(tuple >> 4);
+        }
+        if new_coverage {
+            pending_line = Some(line_time);
+        }
+    }
+    if let Some(pending_time) = pending_line {
+        csv += &format!("{:.3},{},{},{}\n", pending_time, timed_files.len(), edges.len(), tuples.len());
+    }
+    out.write_all(csv.as_bytes()).map_err(TapascoRunError::from).context(TapascoRunSnafu)?;
+
+    let last_time = timed_files.last().map_or(0.0, |(mtime, _)| (mtime - start_time).max(0.0));
+    println!("Replayed {:.1} seconds of the campaign (starting at {:.0}): {} edges, {} tuples.",
+        last_time, start_time, edges.len(), tuples.len());
+    Ok(ExitKind::Ok)
+}
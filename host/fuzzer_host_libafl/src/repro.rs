@@ -0,0 +1,124 @@
+use std::sync::{Arc, atomic::AtomicBool};
+
+use clap::ArgMatches;
+use libafl::{
+    bolts::tuples::{tuple_list, MatchName},
+    executors::{ExitKind, HasObservers},
+    inputs::BytesInput,
+    observers::{HitcountsMapObserver, MapObserver, VariableMapObserver},
+};
+use snafu::ResultExt;
+
+use crate::{FuzzErr, AFLSnafu, AFLDescSnafu, TapascoRunSnafu};
+use crate::{MAP_OBSERVER_NAME, TIME_OBSERVER_NAME, EXCEPTION_OBSERVER_NAME};
+use crate::exception_feedback::{ExceptionObserver, ExceptionMetadata};
+use crate::tapasco_host::{TapascoHostExecutor, TapascoHostRun, TapascoRunError, TapascoRunner, TapascoRunStatusFlags};
+use crate::tapasco_runner_phys::TapascoDispatcher;
+use crate::time_feedback::ManualTimeObserver;
+
+//Exit codes of the repro mode, following afl-showmap.
+pub const EXIT_CODE_OK: i32 = 0;
+pub const EXIT_CODE_TIMEOUT: i32 = 1;
+pub const EXIT_CODE_CRASH: i32 = 2;
+pub const EXIT_CODE_ERROR: i32 = 3;
+
+/// Returns the process exit code for the result of an execution.
+pub fn exit_code(exit_kind: &ExitKind) -> i32 {
+    match exit_kind {
+        ExitKind::Ok => EXIT_CODE_OK,
+        ExitKind::Timeout => EXIT_CODE_TIMEOUT,
+        ExitKind::Crash => EXIT_CODE_CRASH,
+        _ => EXIT_CODE_ERROR
+    }
+}
+
+/// Decodes [`TapascoRunStatusFlags`] into a human-readable list.
+pub fn describe_status(status: u32) -> String {
+    let mut flags: Vec<String> = Vec::new();
+    if (status & TapascoRunStatusFlags::PROGRAM_CRASH_FLAG) != 0 {
+        let cause = (status & TapascoRunStatusFlags::PROGRAM_CRASH_CAUSE_MASK) >> TapascoRunStatusFlags::PROGRAM_CRASH_CAUSE_SHIFT;
+        flags.push(format!("crash (cause {})", cause));
+    }
+    if (status & TapascoRunStatusFlags::INVALID_BITMAP_SIZE_FLAG) != 0 {
+        flags.push("invalid bitmap size".to_string());
+    }
+    if (status & TapascoRunStatusFlags::TIMEOUT_FLAG) != 0 {
+        flags.push("timeout".to_string());
+    }
+    if (status & !TapascoRunStatusFlags::ALL_MASK) != 0 {
+        flags.push("unknown flags".to_string());
+    }
+    if flags.is_empty() {
+        flags.push("ok".to_string());
+    }
+    flags.join(", ")
+}
+
+//Runs a single input and prints the full result.
+pub fn repro(cmd_matches: &ArgMatches, tapasco_dispatchers: Vec<Arc<TapascoDispatcher>>) -> Result<ExitKind, FuzzErr> {
+    let input_path = cmd_matches.value_of("input").ok_or(FuzzErr::ArgError{s:"input argument missing".to_string()})?;
+    let input = BytesInput::new(std::fs::read(input_path).map_err(TapascoRunError::from).context(TapascoRunSnafu)?);
+
+    let bitmap_size = cmd_matches.value_of("bitmapsize").ok_or(FuzzErr::ArgError{s:"bitmapsize argument missing".to_string()})?.parse::<usize>().unwrap();
+    let mut bitmap_vec_tmp : Vec<u8> = Vec::new();
+    bitmap_vec_tmp.resize(bitmap_size, 0);
+    let mut bitmap_buf = bitmap_vec_tmp.into_boxed_slice();
+
+    let mut _tmp_bitmap_size = bitmap_size;
+    let edges_observer = HitcountsMapObserver::new(VariableMapObserver::new(
+        MAP_OBSERVER_NAME,
+        &mut *bitmap_buf,
+        &mut _tmp_bitmap_size
+    ));
+    let time_observer = ManualTimeObserver::new(TIME_OBSERVER_NAME);
+    let exception_observer = ExceptionObserver::new(EXCEPTION_OBSERVER_NAME);
+
+    let stop_req = AtomicBool::new(false);
+    let image = crate::load_target_image(cmd_matches)?;
+    let mut tapasco_host: Box<dyn TapascoRunner> = crate::create_tapascorunner(cmd_matches, image.clone(), tapasco_dispatchers, &stop_req)?;
+
+    let mut executor = TapascoHostExecutor::<BytesInput,HitcountsMapObserver<VariableMapObserver<u8>>,_,u8>::new(
+        cmd_matches.value_of("binary").ok_or(FuzzErr::ArgError{s:"binary argument missing".to_string()})?.to_string(),
+        tapasco_host.as_mut(),
+        tuple_list!(edges_observer, time_observer, exception_observer),
+        MAP_OBSERVER_NAME,
+        bitmap_size,
+        TIME_OBSERVER_NAME,
+        EXCEPTION_OBSERVER_NAME
+    )
+    .context(AFLDescSnafu {desc: "Failed to create the executor.".to_string() })?;
+
+    let result = executor.run_target(&input);
+
+    //Print what is known about the run, even if the executor returned an error (e.g. for an invalid bitmap size).
+    match executor.last_status() {
+        Some(status) => println!("Status: 0x{:02x} ({})", status, describe_status(status)),
+        None => println!("Status: none (no result from the runner)")
+    }
+    let exit_kind = result.context(AFLSnafu)?;
+
+    let observers = executor.observers();
+    if let Some(exception) = observers.match_name::<ExceptionObserver>(EXCEPTION_OBSERVER_NAME).and_then(|o| o.last_exception().as_ref()) {
+        println!("Exception: {}", ExceptionMetadata::new(exception, Some(&*image)).description);
+        println!(" cause: {}", exception.cause);
+        println!(" epc:   0x{:08x}", exception.epc);
+        println!(" tval:  0x{:08x}", exception.tval);
+    }
+    if let Some(duration) = observers.match_name::<ManualTimeObserver>(TIME_OBSERVER_NAME).and_then(|o| o.last_runtime().as_ref()) {
+        println!("Cycles: {}", TapascoHostRun::cycles_from_duration(duration));
+    }
+    let bitmap = observers.match_name::<HitcountsMapObserver<VariableMapObserver<u8>>>(MAP_OBSERVER_NAME)
+        .and_then(|o| o.map())
+        .ok_or_else(|| libafl::Error::KeyNotFound("MapObserver not found".to_string()))
+        .context(AFLSnafu)?;
+    let nonzero: Vec<(usize, u8)> = bitmap.iter().enumerate()
+        .filter(|(_, &count)| count != 0)
+        .map(|(i, &count)| (i, count))
+        .collect();
+    println!("Bitmap: {} of {} entries non-zero", nonzero.len(), bitmap.len());
+    for (i, count) in nonzero {
+        println!(" {}: {}", i, count);
+    }
+
+    Ok(exit_kind)
+}
@@ -0,0 +1,167 @@
+use std::io::{BufReader, Write};
+use std::net::TcpListener;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixListener;
+use std::sync::{Arc, atomic::AtomicBool};
+
+use clap::ArgMatches;
+use futures::executor;
+use libafl::executors::ExitKind;
+use snafu::ResultExt;
+
+use crate::{FuzzErr, TapascoRunSnafu};
+use crate::tapasco_host::TapascoRunError;
+use crate::tapasco_runner_phys::TapascoDispatcher;
+use crate::tapasco_runner_remote::{RemoteStream, is_tcp_address, put_hello_reply, put_run_result, read_bytes, read_u32, read_u64};
+use crate::tapasco_runner_remote::{PROTOCOL_MAGIC, PROTOCOL_VERSION, MAX_BATCH_BYTES, MAX_BATCH_LEN, MAX_BITMAP_LEN, MAX_IMAGE_LEN, MAX_INPUT_LEN};
+use crate::target_image::TargetImage;
+
+//The serve mode runs until it is terminated.
+static SERVE_STOP_REQ: AtomicBool = AtomicBool::new(false);
+
+enum RemoteListener {
+    Unix(UnixListener),
+    Tcp(TcpListener),
+}
+impl RemoteListener {
+    //Binds to a Unix socket path or a TCP address. TCP addresses other than loopback require `allow_remote`,
+    // since the protocol has no authentication.
+    fn bind(address: &str, allow_remote: bool) -> Result<Self, std::io::Error> {
+        if is_tcp_address(address) {
+            let listener = TcpListener::bind(address)?;
+            let local_addr = listener.local_addr()?;
+            if !local_addr.ip().is_loopback() && !allow_remote {
+                return Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied,
+                    format!("Refusing to listen on the non-loopback address {} without --allow_remote", local_addr)));
+            }
+            eprintln!("Warning: Listening on TCP address {} without authentication. Any client that can connect can run programs on the backend.",
+                local_addr);
+            return Ok(Self::Tcp(listener));
+        }
+        //Remove a stale socket of a previous serve process.
+        if let Ok(metadata) = std::fs::symlink_metadata(address) {
+            if metadata.file_type().is_socket() {
+                std::fs::remove_file(address)?;
+            }
+        }
+        Ok(Self::Unix(UnixListener::bind(address)?))
+    }
+
+    fn accept(&self) -> Result<(RemoteStream, String), std::io::Error> {
+        match self {
+            Self::Unix(listener) => {
+                let (stream, _) = listener.accept()?;
+                Ok((RemoteStream::Unix(stream), "unix socket".to_string()))
+            },
+            Self::Tcp(listener) => {
+                let (stream, peer) = listener.accept()?;
+                stream.set_nodelay(true)?;
+                Ok((RemoteStream::Tcp(stream), peer.to_string()))
+            }
+        }
+    }
+}
+
+//Serves a client connection until the client disconnects.
+fn serve_client(cmd_matches: &ArgMatches, tapasco_dispatchers: Vec<Arc<TapascoDispatcher>>, stream: RemoteStream) -> Result<(), TapascoRunError> {
+    let mut stream = BufReader::new(stream);
+    //Set up the runner for the program of the client.
+    let mut magic = [0u8; 4];
+    std::io::Read::read_exact(&mut stream, &mut magic)?;
+    let version = read_u32(&mut stream)?;
+    if magic != PROTOCOL_MAGIC || version != PROTOCOL_VERSION {
+        let mut reply = Vec::new();
+        put_hello_reply(&mut reply, Err(format!("Unsupported protocol (version {}, expected {})", version, PROTOCOL_VERSION)));
+        stream.get_mut().write_all(&reply)?;
+        return Err(TapascoRunError::Remote { s: "Unsupported protocol".to_string() });
+    }
+    let ignore_addresses_min = read_u32(&mut stream)?;
+    let timeout_cycles = read_u64(&mut stream)?;
+    let program = read_bytes(&mut stream, MAX_IMAGE_LEN)?;
+    //A timeout of 0 would let a run occupy a PE indefinitely.
+    let max_timeout_cycles = cmd_matches.value_of("max_timeout").unwrap().parse::<u64>().unwrap();
+    let runner_result = if timeout_cycles == 0 || timeout_cycles > max_timeout_cycles {
+        Err(format!("Timeout of {} cycles is outside of the accepted range [1, {}]", timeout_cycles, max_timeout_cycles))
+    }
+    else {
+        TargetImage::from_bytes(&program)
+            .map_err(|e| e.to_string())
+            .and_then(|image| crate::create_tapascorunner_with_params(cmd_matches, Arc::new(image), tapasco_dispatchers,
+                &SERVE_STOP_REQ, ignore_addresses_min, timeout_cycles).map_err(|e| e.to_string()))
+    };
+    let mut reply = Vec::new();
+    let mut runner = match runner_result {
+        Ok(runner) => {
+            put_hello_reply(&mut reply, Ok(()));
+            stream.get_mut().write_all(&reply)?;
+            runner
+        },
+        Err(s) => {
+            put_hello_reply(&mut reply, Err(s.clone()));
+            stream.get_mut().write_all(&reply)?;
+            return Err(TapascoRunError::Remote { s: s });
+        }
+    };
+
+    loop {
+        let num_inputs = match read_u32(&mut stream) {
+            Ok(num_inputs) => num_inputs as usize,
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()), //Client disconnected.
+            Err(e) => return Err(e.into())
+        };
+        if num_inputs > MAX_BATCH_LEN {
+            return Err(TapascoRunError::Remote { s: format!("Batch of {} inputs exceeds the limit of {}", num_inputs, MAX_BATCH_LEN) });
+        }
+        let mut inputs: Vec<Vec<u8>> = Vec::with_capacity(num_inputs);
+        let mut bitmaps: Vec<Box<[u8]>> = Vec::with_capacity(num_inputs);
+        let mut batch_bytes: usize = 0;
+        for _ in 0..num_inputs {
+            let bitmap_len = read_u32(&mut stream)? as usize;
+            if bitmap_len > MAX_BITMAP_LEN {
+                return Err(TapascoRunError::Remote { s: format!("Bitmap length {} exceeds the limit of {}", bitmap_len, MAX_BITMAP_LEN) });
+            }
+            if batch_bytes + bitmap_len > MAX_BATCH_BYTES {
+                return Err(TapascoRunError::Remote { s: format!("Batch exceeds the limit of {} bytes", MAX_BATCH_BYTES) });
+            }
+            batch_bytes += bitmap_len;
+            bitmaps.push(vec![0u8; bitmap_len].into_boxed_slice());
+            //Limit the input to the remaining batch size as well, before allocating it.
+            let input = read_bytes(&mut stream, std::cmp::min(MAX_INPUT_LEN, MAX_BATCH_BYTES - batch_bytes))?;
+            batch_bytes += input.len();
+            inputs.push(input);
+        }
+        let input_slices: Vec<&[u8]> = inputs.iter().map(|input| input.as_slice()).collect();
+        let results = executor::block_on(runner.run_batch(&input_slices, bitmaps.iter_mut().map(|bitmap| &mut **bitmap).collect()));
+        reply.clear();
+        for (result, bitmap) in results.iter().zip(&bitmaps) {
+            put_run_result(&mut reply, result, bitmap);
+        }
+        stream.get_mut().write_all(&reply)?;
+    }
+}
+
+//Accepts runner connections (see TapascoRunnerRemote), and runs their inputs on the backend.
+pub fn serve(cmd_matches: &ArgMatches, tapasco_dispatchers: Vec<Arc<TapascoDispatcher>>) -> Result<ExitKind, FuzzErr> {
+    let address = cmd_matches.value_of("listen").ok_or(FuzzErr::ArgError{s:"listen argument missing".to_string()})?;
+    let listener = RemoteListener::bind(address, cmd_matches.is_present("allow_remote")).map_err(TapascoRunError::from).context(TapascoRunSnafu)?;
+    println!("Serving {} PEs on {}.", tapasco_dispatchers.iter().map(|dispatcher| dispatcher.num_pes()).sum::<usize>(), address);
+    loop {
+        let (stream, peer) = match listener.accept() {
+            Ok(connection) => connection,
+            Err(e) => {
+                println!("Failed to accept a connection: {}", e);
+                continue;
+            }
+        };
+        println!("Client connected ({}).", peer);
+        //Each client gets its own thread and runner, sharing the dispatchers.
+        let thr_cmd_matches = cmd_matches.clone();
+        let thr_dispatchers = tapasco_dispatchers.clone();
+        std::thread::spawn(move || {
+            match serve_client(&thr_cmd_matches, thr_dispatchers, stream) {
+                Ok(()) => println!("Client disconnected ({}).", peer),
+                Err(e) => println!("Client connection closed ({}): {}", peer, e)
+            }
+        });
+    }
+}
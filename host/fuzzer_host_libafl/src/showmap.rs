@@ -0,0 +1,150 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, atomic::AtomicBool};
+
+use clap::ArgMatches;
+use libafl::{
+    bolts::tuples::{tuple_list, MatchName},
+    executors::{ExitKind, HasObservers},
+    inputs::BytesInput,
+    observers::{HitcountsMapObserver, MapObserver, VariableMapObserver},
+};
+use snafu::ResultExt;
+
+use crate::{FuzzErr, AFLSnafu, AFLDescSnafu, TapascoRunSnafu};
+use crate::{MAP_OBSERVER_NAME, TIME_OBSERVER_NAME, EXCEPTION_OBSERVER_NAME};
+use crate::exception_feedback::ExceptionObserver;
+use crate::tapasco_host::{TapascoHostExecutor, TapascoRunError, TapascoRunner};
+use crate::tapasco_runner_phys::TapascoDispatcher;
+use crate::time_feedback::ManualTimeObserver;
+
+/// Maps a hit count to the count class printed by afl-showmap (count_class_human in afl-showmap.c).
+pub fn count_class_human(count: u8) -> u8 {
+    match count {
+        0..=3 => count,
+        4..=7 => 4,
+        8..=15 => 5,
+        16..=31 => 6,
+        32..=127 => 7,
+        _ => 8
+    }
+}
+
+/// Lists the input files for the given paths. Directories are expanded to the files they contain (excluding hidden files).
+pub fn collect_input_files<'a>(paths: impl Iterator<Item = &'a str>) -> Result<Vec<PathBuf>, std::io::Error> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    for path in paths.map(PathBuf::from) {
+        if path.is_dir() {
+            let mut dir_files: Vec<PathBuf> = std::fs::read_dir(&path)?
+                .filter_map(|res| res.ok().map(|entry| entry.path()))
+                .filter(|path| path.is_file() //Is a file ...
+                    && path.file_name()                //... that does not start with "."
+                       .and_then(|os_fname| os_fname.to_str())
+                       .map(|fname| !fname.starts_with(".")).unwrap_or(false))
+                .collect();
+            dir_files.sort();
+            files.append(&mut dir_files);
+        }
+        else {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+//Writes a bitmap in the afl-showmap text format, or as a binary dump.
+fn write_map(out: &mut dyn Write, bitmap: &[u8], raw: bool, binary_dump: bool) -> Result<(), std::io::Error> {
+    let classify = |count: u8| if raw { count } else { count_class_human(count) };
+    if binary_dump {
+        let classified: Vec<u8> = bitmap.iter().map(|&count| classify(count)).collect();
+        out.write_all(&classified)?;
+    }
+    else {
+        for (i, &count) in bitmap.iter().enumerate() {
+            if count != 0 {
+                writeln!(out, "{:06}:{}", i, classify(count))?;
+            }
+        }
+    }
+    out.flush()
+}
+
+//Runs the inputs and writes the bitmaps in the afl-showmap format.
+pub fn showmap(cmd_matches: &ArgMatches, tapasco_dispatchers: Vec<Arc<TapascoDispatcher>>) -> Result<ExitKind, FuzzErr> {
+    let input_args: Vec<&str> = cmd_matches.values_of("inputs").ok_or(FuzzErr::ArgError{s:"inputs argument missing".to_string()})?.collect();
+    let output = cmd_matches.value_of("output").ok_or(FuzzErr::ArgError{s:"output argument missing".to_string()})?;
+    let raw = cmd_matches.is_present("raw");
+    let binary_dump = cmd_matches.is_present("binary_dump");
+
+    let input_files = collect_input_files(input_args.iter().copied()).map_err(TapascoRunError::from).context(TapascoRunSnafu)?;
+    //A single input file is written to the output file directly, otherwise the output is a directory.
+    let single_file = input_args.len() == 1 && !Path::new(input_args[0]).is_dir();
+    if !single_file {
+        std::fs::create_dir_all(output).map_err(TapascoRunError::from).context(TapascoRunSnafu)?;
+    }
+
+    let bitmap_size = cmd_matches.value_of("bitmapsize").ok_or(FuzzErr::ArgError{s:"bitmapsize argument missing".to_string()})?.parse::<usize>().unwrap();
+    let mut bitmap_vec_tmp : Vec<u8> = Vec::new();
+    bitmap_vec_tmp.resize(bitmap_size, 0);
+    let mut bitmap_buf = bitmap_vec_tmp.into_boxed_slice();
+
+    let mut _tmp_bitmap_size = bitmap_size;
+    let edges_observer = HitcountsMapObserver::new(VariableMapObserver::new(
+        MAP_OBSERVER_NAME,
+        &mut *bitmap_buf,
+        &mut _tmp_bitmap_size
+    ));
+    let time_observer = ManualTimeObserver::new(TIME_OBSERVER_NAME);
+    let exception_observer = ExceptionObserver::new(EXCEPTION_OBSERVER_NAME);
+
+    let stop_req = AtomicBool::new(false);
+    let image = crate::load_target_image(cmd_matches)?;
+    let mut tapasco_host: Box<dyn TapascoRunner> = crate::create_tapascorunner(cmd_matches, image, tapasco_dispatchers, &stop_req)?;
+
+    let mut executor = TapascoHostExecutor::<BytesInput,HitcountsMapObserver<VariableMapObserver<u8>>,_,u8>::new(
+        cmd_matches.value_of("binary").ok_or(FuzzErr::ArgError{s:"binary argument missing".to_string()})?.to_string(),
+        tapasco_host.as_mut(),
+        tuple_list!(edges_observer, time_observer, exception_observer),
+        MAP_OBSERVER_NAME,
+        bitmap_size,
+        TIME_OBSERVER_NAME,
+        EXCEPTION_OBSERVER_NAME
+    )
+    .context(AFLDescSnafu {desc: "Failed to create the executor.".to_string() })?;
+
+    let mut last_exit_kind = ExitKind::Ok;
+    let (mut num_crashes, mut num_timeouts) = (0usize, 0usize);
+    for input_file in &input_files {
+        let input = BytesInput::new(std::fs::read(input_file).map_err(TapascoRunError::from).context(TapascoRunSnafu)?);
+        last_exit_kind = executor.run_target(&input).context(AFLSnafu)?;
+        match last_exit_kind {
+            ExitKind::Crash => num_crashes += 1,
+            ExitKind::Timeout => num_timeouts += 1,
+            _ => ()
+        }
+        let bitmap = executor.observers().match_name::<HitcountsMapObserver<VariableMapObserver<u8>>>(MAP_OBSERVER_NAME)
+            .and_then(|o| o.map())
+            .ok_or_else(|| libafl::Error::KeyNotFound("MapObserver not found".to_string()))
+            .context(AFLSnafu)?;
+
+        let mut out: Box<dyn Write> = if single_file && output == "-" {
+            Box::new(std::io::stdout())
+        }
+        else {
+            let out_path = if single_file {
+                PathBuf::from(output)
+            }
+            else {
+                Path::new(output).join(input_file.file_name().unwrap_or_default())
+            };
+            Box::new(std::fs::File::create(out_path).map_err(TapascoRunError::from).context(TapascoRunSnafu)?)
+        };
+        write_map(&mut *out, bitmap, raw, binary_dump).map_err(TapascoRunError::from).context(TapascoRunSnafu)?;
+    }
+
+    if single_file {
+        return Ok(last_exit_kind);
+    }
+    println!("Processed {} inputs ({} crashes, {} timeouts).", input_files.len(), num_crashes, num_timeouts);
+    Ok(ExitKind::Ok)
+}
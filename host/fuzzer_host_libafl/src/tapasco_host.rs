@@ -5,7 +5,7 @@ use core::{
     fmt::{self, Debug, Formatter},
     marker::PhantomData
 };
-use std::{sync::Arc, time::Duration};
+use std::time::Duration;
 use futures::executor;
 
 use libafl::{
@@ -14,8 +14,10 @@ use libafl::{
     observers::{ObserversTuple, MapObserver}
 };
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use snafu::Snafu;
 
+use crate::exception_feedback::ExceptionObserver;
 use crate::time_feedback::ManualTimeObserver;
 
 pub mod TapascoRunStatusFlags {
@@ -28,12 +30,59 @@ pub mod TapascoRunStatusFlags {
     pub const ALL_MASK: u32 = 0xFF;
 }
 
+/// RISC-V exception details reported by the PE if a program crashed (arg4 and the cause bits of the return value).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TapascoRunException {
+    pub cause: u32,
+    pub epc: u32,
+    pub tval: u32
+}
+impl TapascoRunException {
+    /// Returns the name of a RISC-V exception cause.
+    pub fn cause_name(cause: u32) -> &'static str {
+        match cause {
+            0 => "instruction address misaligned",
+            1 => "instruction access fault",
+            2 => "illegal instruction",
+            3 => "breakpoint",
+            4 => "load address misaligned",
+            5 => "load access fault",
+            6 => "store/AMO address misaligned",
+            7 => "store/AMO access fault",
+            8 => "environment call from U-mode",
+            9 => "environment call from S-mode",
+            11 => "environment call from M-mode",
+            12 => "instruction page fault",
+            13 => "load page fault",
+            15 => "store/AMO page fault",
+            _ => "unknown",
+        }
+    }
+}
+impl fmt::Display for TapascoRunException {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Cause {} ({}), epc 0x{:08x}, tval 0x{:08x}",
+            self.cause, Self::cause_name(self.cause), self.epc, self.tval)
+    }
+}
+
 /// Run result data of a [`TapascoHost`].
 #[derive(Debug)]
 pub struct TapascoHostRun {
     pub status: u32,
-    pub bitmap: Arc<std::sync::Mutex<Box<[u8]>>>,
-    pub duration: Option<Duration>
+    pub duration: Option<Duration>,
+    /// Set if `status` has the PROGRAM_CRASH_FLAG.
+    pub exception: Option<TapascoRunException>
+}
+impl TapascoHostRun {
+    /// Creates a duration from a PE cycle counter with a cycle time of 1ns (i.e. 1GHz), regardless of actual clock speed.
+    pub fn duration_from_cycles(counter: u64) -> Duration {
+        Duration::from_nanos(counter)
+    }
+    /// Converts a duration created by `duration_from_cycles` back to the PE cycle count.
+    pub fn cycles_from_duration(duration: &Duration) -> u64 {
+        duration.as_nanos() as u64
+    }
 }
 
 #[derive(Debug, Snafu)]
@@ -51,12 +100,23 @@ pub enum TapascoRunError {
     ProgramOrDataTooLarge { },
     #[snafu(display("Input does not fit in free data memory"))]
     InputTooLarge { },
+    #[snafu(display("Target binary error: {}", s))]
+    TargetBinary { s: String },
+    #[snafu(display("Campaign config error: {}", s))]
+    Config { s: String },
     #[snafu(display("A DRAM allocation does not fit the required {}K ({:x}) alignment: Address 0x{:x}", alignment_num/1024, alignment_num, addr))]
     DRAMAllocationMisaligned { addr: u64, alignment_num: u64 },
 
     #[snafu(display("Poisened mutex occured"))]
     MutexPoisoned {},
 
+    #[snafu(display("PE did not complete within the watchdog period of {:.1} s", seconds))]
+    PEWatchdog { seconds: f64 },
+    #[snafu(display("All PEs have been quarantined"))]
+    NoPEsLeft { },
+    #[snafu(display("Remote runner error: {}", s))]
+    Remote { s: String },
+
     #[snafu(display("TaPaSCo Allocator Error: {}", source))]
     TapascoAllocator { source: tapasco::allocator::Error },
     #[snafu(display("TaPaSCo DMA Error: {}", source))]
@@ -67,11 +127,17 @@ pub enum TapascoRunError {
     TapascoDevice { source: tapasco::device::Error },
     #[snafu(display("TaPaSCo PE Error: {}", source))]
     TapascoPE { source: tapasco::pe::Error },
-    #[snafu(display("TaPaSCo PE not found. Last Error: {:?}", last))]
-    TapascoPENotFound { last: Option<tapasco::device::Error> },
+    #[snafu(display("TaPaSCo PE not found.\n{}", details))]
+    TapascoPENotFound { details: String },
     #[snafu(display("TaPaSCo Job Error: {}", source))]
     TapascoJob { source: tapasco::job::Error },
 }
+impl TapascoRunError {
+    /// Whether the error indicates a faulty PE (hang, DMA or PE access failure), rather than an issue with the input or configuration.
+    pub fn is_pe_failure(&self) -> bool {
+        matches!(self, Self::PEWatchdog { .. } | Self::TapascoDMA { .. } | Self::TapascoPE { .. } | Self::TapascoJob { .. })
+    }
+}
 impl From<std::io::Error> for TapascoRunError {
     fn from(err: std::io::Error) -> Self {
         Self::IO {source: err}
@@ -91,7 +157,27 @@ impl<T> From<std::sync::PoisonError<T>> for TapascoRunError {
 /// Trait for program execution backends.
 #[async_trait(?Send)]
 pub trait TapascoRunner {
-    async fn run(&mut self, input: &[u8], bitmap_mem: Arc<std::sync::Mutex<Box<[u8]>>>) -> Result<TapascoHostRun,TapascoRunError>;
+    /// Runs an input, writing the control flow bitmap to `bitmap` (also on program crashes and timeouts).
+    async fn run(&mut self, input: &[u8], bitmap: &mut [u8]) -> Result<TapascoHostRun,TapascoRunError>;
+
+    /// Runs several inputs, each with its own bitmap buffer, and returns the results in input order.
+    /// Backends that can execute on several PEs at once keep them busy concurrently; by default, the inputs run one after another.
+    async fn run_batch(&mut self, inputs: &[&[u8]], bitmaps: Vec<&mut [u8]>) -> Vec<Result<TapascoHostRun,TapascoRunError>> {
+        let mut results = Vec::with_capacity(inputs.len());
+        for (input, bitmap) in inputs.iter().zip(bitmaps) {
+            results.push(self.run(input, bitmap).await);
+        }
+        results
+    }
+}
+
+/// An executor that can run a batch of inputs at once and then apply the results to its observers one by one.
+pub trait HasBatchRun<I> {
+    /// Runs the inputs through [`TapascoRunner::run_batch`], without updating the observers.
+    fn run_batch(&mut self, inputs: &[I]) -> Vec<Result<TapascoHostRun, TapascoRunError>>;
+
+    /// Applies the result of the input at `index` of the last `run_batch` to the observers, in the same way as a regular execution.
+    fn apply_batch_run(&mut self, index: usize, result: Result<TapascoHostRun, TapascoRunError>) -> Result<ExitKind, libafl::Error>;
 }
 
 /// A struct that has a [`TapascoRunner`]
@@ -112,10 +198,15 @@ where
 {
     target: String,
     tapascorunner: &'host mut dyn TapascoRunner,
-    bitmap_internal: Arc<std::sync::Mutex<Box<[u8]>>>,
+    bitmap_len: usize,
+    //Bitmap buffers for the inputs of a batch, allocated on demand.
+    //(Single runs write to the map observer directly.)
+    batch_bitmaps: Vec<Box<[u8]>>,
     observers: OT,
     map_observer_name: &'static str,
     time_observer_name: &'static str,
+    exception_observer_name: &'static str,
+    last_status: Option<u32>,
     phantom: PhantomData<(I, O, S)>,
 }
 
@@ -149,8 +240,9 @@ where
         map_observer_name: &'static str,
         bitmap_len: usize,
         time_observer_name: &'static str,
+        exception_observer_name: &'static str,
     ) -> Result<Self, libafl::Error> {
-        Self::with_debug(target, tapascorunner, observers, map_observer_name, bitmap_len, time_observer_name, false)
+        Self::with_debug(target, tapascorunner, observers, map_observer_name, bitmap_len, time_observer_name, exception_observer_name, false)
     }
 
     /// Creates a new [`TapascoHostExecutor`] with the given target, arguments and observers, with debug mode
@@ -161,22 +253,28 @@ where
         map_observer_name: &'static str,
         bitmap_len: usize,
         time_observer_name: &'static str,
+        exception_observer_name: &'static str,
         _debug_output: bool,
     ) -> Result<Self, libafl::Error> {        
-        let mut bitmap_internal_vec : Vec<u8> = Vec::new();
-        bitmap_internal_vec.resize(bitmap_len, 0);
-        let bitmap_internal = std::sync::Arc::new(std::sync::Mutex::new(bitmap_internal_vec.into_boxed_slice()));
-
         Ok(Self {
             target: target,
             tapascorunner: tapascorunner,
-            bitmap_internal: bitmap_internal,
+            bitmap_len: bitmap_len,
+            batch_bitmaps: Vec::new(),
             observers: observers,
             map_observer_name: map_observer_name,
             time_observer_name: time_observer_name,
+            exception_observer_name: exception_observer_name,
+            last_status: None,
             phantom: PhantomData,
         })
     }
+
+    /// Returns the [`TapascoRunStatusFlags`] of the last execution, if the runner returned a result.
+    #[must_use]
+    pub fn last_status(&self) -> Option<u32> {
+        self.last_status
+    }
 }
 
 impl<'host, I, O, OT, S> TapascoHostExecutor<'host, I, O, OT, S>
@@ -190,20 +288,35 @@ where
         &mut self,
         input: &I,
     ) -> Result<ExitKind, libafl::Error> {
-        let exit_kind;
-
         let input_slice_owner = input.target_bytes();
         let input_slice = input_slice_owner.as_slice();
-        let bitmap_interal_cl1 = self.bitmap_internal.clone();
-        // Invoke the [`TapascoRunner`].
-        let result = executor::block_on(self.runner_mut().run(input_slice, bitmap_interal_cl1));
+        // Invoke the [`TapascoRunner`], which writes the bitmap into the [`MapObserver`] directly.
+        //Based on LibAFL: libafl/src/stages/calibrate.rs
+        let map_observer_name = self.map_observer_name;
+        let bitmap = self.observers.match_name_mut::<O>(map_observer_name)
+            .ok_or_else(|| libafl::Error::KeyNotFound("MapObserver not found".to_string()))?
+            .map_mut().unwrap();
+        let result = executor::block_on(self.tapascorunner.run(input_slice, bitmap));
+        self.apply_result(result)
+    }
+
+    //Sets the exit kind and the time and exception observers from a run result.
+    // The bitmap is expected to be in the [`MapObserver`] already.
+    fn apply_result(&mut self, result: Result<TapascoHostRun, TapascoRunError>) -> Result<ExitKind, libafl::Error> {
+        let exit_kind;
+
         let time_observer_name = self.time_observer_name;
+        let exception_observer_name = self.exception_observer_name;
+        self.last_status = result.as_ref().ok().map(|run| run.status);
         match result {
             Err(e) => {
                 //Set the duration in the [`ManualTimeOberver`] to None.
                 self.observers_mut().match_name_mut::<ManualTimeObserver>(time_observer_name)
                     .ok_or_else(|| libafl::Error::KeyNotFound("ManualTimeObserver not found".to_string()))?
                     .set_last_runtime(None);
+                self.observers_mut().match_name_mut::<ExceptionObserver>(exception_observer_name)
+                    .ok_or_else(|| libafl::Error::KeyNotFound("ExceptionObserver not found".to_string()))?
+                    .set_last_exception(None);
                 // Convert errors to a libafl::Error type.
                 return Err(match e {
                     TapascoRunError::Unknown { s } => libafl::Error::Unknown(s),
@@ -221,33 +334,27 @@ where
                 }
                 else if (run.status & TapascoRunStatusFlags::TIMEOUT_FLAG) != 0 {
                     exit_kind = ExitKind::Timeout;
-                    println!("Run result: Timeout.");
+                    eprintln!("Run result: Timeout.");
                 }
                 else if (run.status & TapascoRunStatusFlags::PROGRAM_CRASH_FLAG) != 0 {
                     exit_kind = ExitKind::Crash;
+                    if let Some(exception) = run.exception.as_ref() {
+                        eprintln!("Exception: {}", exception);
+                    }
                 }
                 else {
                     exit_kind = ExitKind::Ok;
                 }
-                //Copy the bitmap regardless of how execution ended.
-                // -> LibAFL doesn't register crashes if the bitmap only has zeroes.
-                let bitmap_interal_cl2 = self.bitmap_internal.clone();
-                let lock_res = (*bitmap_interal_cl2).try_lock();
-                assert!(match lock_res {Ok(_) => {true} Err(_) => {false}},
-                    "Unable to lock mutex that should have been unlocked: {}", lock_res.unwrap_err());
-                //Based on LibAFL: libafl/src/stages/calibrate.rs
-                let map_observer_name = self.map_observer_name;
-
-                //Copy the bitmap into the [`MapOberver`].
-                self.observers_mut().match_name_mut::<O>(map_observer_name)
-                    .ok_or_else(|| libafl::Error::KeyNotFound("MapObserver not found".to_string()))?
-                    .map_mut().unwrap()
-                    .copy_from_slice(&*lock_res.unwrap());
 
                 //Set the duration in the [`ManualTimeOberver`].
                 self.observers_mut().match_name_mut::<ManualTimeObserver>(time_observer_name)
                     .ok_or_else(|| libafl::Error::KeyNotFound("ManualTimeObserver not found".to_string()))?
                     .set_last_runtime(run.duration);
+
+                //Set the exception details in the [`ExceptionObserver`].
+                self.observers_mut().match_name_mut::<ExceptionObserver>(exception_observer_name)
+                    .ok_or_else(|| libafl::Error::KeyNotFound("ExceptionObserver not found".to_string()))?
+                    .set_last_exception(run.exception);
             }
         };
 
@@ -255,6 +362,36 @@ where
     }
 }
 
+impl<'host, I, O, OT, S> HasBatchRun<I> for TapascoHostExecutor<'host, I, O, OT, S>
+where
+    I: Input + HasTargetBytes,
+    OT: ObserversTuple<I, S>,
+    O: MapObserver<u8>,
+{
+    fn run_batch(&mut self, inputs: &[I]) -> Vec<Result<TapascoHostRun, TapascoRunError>> {
+        while self.batch_bitmaps.len() < inputs.len() {
+            self.batch_bitmaps.push(vec![0u8; self.bitmap_len].into_boxed_slice());
+        }
+        let input_slice_owners: Vec<_> = inputs.iter().map(|input| input.target_bytes()).collect();
+        let input_slices: Vec<&[u8]> = input_slice_owners.iter().map(|owner| owner.as_slice()).collect();
+        let bitmaps: Vec<&mut [u8]> = self.batch_bitmaps[..inputs.len()].iter_mut().map(|bitmap| &mut **bitmap).collect();
+        // Invoke the [`TapascoRunner`].
+        executor::block_on(self.tapascorunner.run_batch(&input_slices, bitmaps))
+    }
+
+    fn apply_batch_run(&mut self, index: usize, result: Result<TapascoHostRun, TapascoRunError>) -> Result<ExitKind, libafl::Error> {
+        if result.is_ok() {
+            //Copy the bitmap into the [`MapOberver`], since a batch needs a separate buffer for each input.
+            let map_observer_name = self.map_observer_name;
+            self.observers.match_name_mut::<O>(map_observer_name)
+                .ok_or_else(|| libafl::Error::KeyNotFound("MapObserver not found".to_string()))?
+                .map_mut().unwrap()
+                .copy_from_slice(&self.batch_bitmaps[index]);
+        }
+        self.apply_result(result)
+    }
+}
+
 impl<'host, EM, I, O, OT, S, Z> Executor<EM, I, S, Z> for TapascoHostExecutor<'host, I, O, OT, S>
 where
     I: Input + HasTargetBytes,
@@ -0,0 +1,498 @@
+use std::sync::atomic::AtomicBool;
+
+use async_trait::async_trait;
+
+use crate::tapasco_host::{TapascoRunStatusFlags, TapascoHostRun, TapascoRunException, TapascoRunner, TapascoRunError};
+use crate::tapasco_runner_phys::PEMemConfig;
+use crate::target_image::TargetImage;
+
+//FuzzerCore register window as seen from the program (see core/FuzzerCore.bsv and testPrograms/platform/start.S).
+const FUZZCORE_MMIO_BASE: u32 = 0x11000000;
+const FUZZCORE_MMIO_SIZE: u32 = 0x100;
+const FUZZCORE_REG_ARG0: u32 = 0x20;
+const FUZZCORE_REG_ARG1: u32 = 0x30;
+const FUZZCORE_REG_ARG2: u32 = 0x40;
+const FUZZCORE_REG_ARG3: u32 = 0x50;
+const FUZZCORE_REG_TIMEOUT_LO: u32 = 0x70;
+const FUZZCORE_REG_TIMEOUT_HI: u32 = 0x74;
+const FUZZCORE_REG_SETINTR: u32 = 0x80;
+const FUZZCORE_REG_COUNTER_LO: u32 = 0x90;
+const FUZZCORE_REG_COUNTER_HI: u32 = 0x94;
+const FUZZCORE_REG_IGNORE_MIN: u32 = 0xC0;
+
+//Largest bitmap supported by the FuzzerCore bitmap handler.
+const BITMAP_SIZE_MAX: usize = 0x2000;
+
+//Granularity for restoring the data memory between runs.
+const DATAMEM_PAGE_SHIFT: u32 = 12;
+
+//Number of instructions between two checks of the stop request, so runs without a timeout can be interrupted.
+const STOP_POLL_INTERVAL: u64 = 1 << 20;
+
+//RISC-V exception causes raised by the emulator.
+mod ExcCause {
+    pub const INSTR_ADDR_MISALIGNED: u32 = 0;
+    pub const INSTR_ACCESS_FAULT: u32 = 1;
+    pub const ILLEGAL_INSTR: u32 = 2;
+    pub const BREAKPOINT: u32 = 3;
+    pub const LOAD_ADDR_MISALIGNED: u32 = 4;
+    pub const LOAD_ACCESS_FAULT: u32 = 5;
+    pub const STORE_ADDR_MISALIGNED: u32 = 6;
+    pub const STORE_ACCESS_FAULT: u32 = 7;
+    pub const ECALL_M: u32 = 11;
+}
+
+/// Replicates the hash pipeline of core/cf/FuzzerCF_Hasher.bsv.
+#[inline]
+pub fn cf_hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846ca68b);
+    x ^= x >> 16;
+    x
+}
+
+/// Returns true if the instruction is registered as a CF edge by the FuzzerCore
+/// (conditional branch, JAL/J, JALR; see core/cf/FuzzerCF_CfDetectors.bsv).
+#[inline]
+fn is_cf_instr(instr: u32) -> bool {
+    let opcode = instr & 0x7F;
+    let funct3 = (instr >> 12) & 7;
+    opcode == 0b1100011 || opcode == 0b1101111 || (opcode == 0b1100111 && funct3 == 0)
+}
+
+//Reasons for the emulated program to stop.
+enum EmuStop {
+    Done,
+    Timeout,
+    Exception { cause: u32, tval: u32 },
+    //The stop request was set during the run.
+    ShuttingDown,
+}
+
+//Machine-mode trap CSRs, used for environment calls (which the FuzzerCore does not report as crashes).
+#[derive(Default)]
+struct TrapCsrs {
+    mtvec: u32,
+    mscratch: u32,
+    mepc: u32,
+    mcause: u32,
+    mtval: u32,
+}
+
+impl TrapCsrs {
+    fn get_mut(&mut self, csr: u32) -> Option<&mut u32> {
+        match csr {
+            0x305 => Some(&mut self.mtvec),
+            0x340 => Some(&mut self.mscratch),
+            0x341 => Some(&mut self.mepc),
+            0x342 => Some(&mut self.mcause),
+            0x343 => Some(&mut self.mtval),
+            _ => None,
+        }
+    }
+}
+
+//Per-run PE parameters visible to the program through the FuzzerCore registers.
+struct EmuArgs {
+    input_len: u32,
+    input_addr: u32,
+    bitmap_len: u32,
+    timeout_cycles: u64,
+    ignore_addresses_min: u32,
+}
+
+/// The [`TapascoRunnerEmu`] executes programs on a software RV32IM model of the fuzzer PE.
+/// Loads and places the program and input like [`crate::tapasco_runner_phys::TapascoDispatcher`],
+/// and creates the bitmap with the same CF hash as the FuzzerCore.
+/// The cycle counter is approximated as one cycle per instruction.
+pub struct TapascoRunnerEmu<'a> {
+    memconf: PEMemConfig,
+    instmem: Box<[u8]>,
+    datamem_init: Box<[u8]>,
+    datamem_init_len: usize,
+    datamem: Box<[u8]>,
+    datamem_dirty: Vec<bool>,
+    regs: [u32; 32],
+    pc: u32,
+    trap_csrs: TrapCsrs,
+    counter: u64,
+    ignore_addresses_min: u32,
+    timeout_cycles: u64,
+    stop_req: &'a AtomicBool
+}
+
+impl<'a> TapascoRunnerEmu<'a> {
+    /// Create a new [`TapascoRunnerEmu`] with the memory layout `memconf` of the emulated PE type
+    pub fn new(
+        image: &TargetImage,
+        memconf: PEMemConfig,
+        ignore_addresses_min: u32,
+        timeout_cycles: u64,
+        stop_req: &'a AtomicBool
+    ) -> Result<Self, libafl::Error> {
+        image.check_memconfig(&memconf).map_err(|e| libafl::Error::IllegalArgument(e.to_string()))?;
+
+        let (image_instmem, image_datamem) = image.sections(&memconf);
+        let mut instmem = vec![0u8; memconf.instmem_size_phys].into_boxed_slice();
+        instmem[..image_instmem.len()].copy_from_slice(image_instmem);
+        let mut datamem_init = vec![0u8; memconf.datamem_size_phys].into_boxed_slice();
+        datamem_init[..image_datamem.len()].copy_from_slice(image_datamem);
+        let num_pages = (memconf.datamem_size_phys + (1 << DATAMEM_PAGE_SHIFT) - 1) >> DATAMEM_PAGE_SHIFT;
+
+        Ok(Self {
+            memconf: memconf,
+            instmem: instmem,
+            datamem: datamem_init.clone(),
+            datamem_init: datamem_init,
+            datamem_init_len: image_datamem.len(),
+            datamem_dirty: vec![false; num_pages],
+            regs: [0; 32],
+            pc: 0,
+            trap_csrs: TrapCsrs::default(),
+            counter: 0,
+            ignore_addresses_min: ignore_addresses_min,
+            timeout_cycles: timeout_cycles,
+            stop_req: stop_req
+        })
+    }
+
+    //Restores all data memory pages written to by the previous run.
+    fn reset_datamem(&mut self) {
+        for (i_page, dirty) in self.datamem_dirty.iter_mut().enumerate() {
+            if *dirty {
+                let begin = i_page << DATAMEM_PAGE_SHIFT;
+                let end = std::cmp::min(begin + (1 << DATAMEM_PAGE_SHIFT), self.datamem.len());
+                self.datamem[begin..end].copy_from_slice(&self.datamem_init[begin..end]);
+                *dirty = false;
+            }
+        }
+    }
+
+    fn mmio_read(&self, offs: u32, args: &EmuArgs) -> u32 {
+        match offs {
+            FUZZCORE_REG_ARG0 => 0,
+            FUZZCORE_REG_ARG1 => args.input_len,
+            FUZZCORE_REG_ARG2 => args.input_addr,
+            FUZZCORE_REG_ARG3 => args.bitmap_len,
+            FUZZCORE_REG_TIMEOUT_LO => args.timeout_cycles as u32,
+            FUZZCORE_REG_TIMEOUT_HI => (args.timeout_cycles >> 32) as u32,
+            FUZZCORE_REG_COUNTER_LO => self.counter as u32,
+            FUZZCORE_REG_COUNTER_HI => (self.counter >> 32) as u32,
+            FUZZCORE_REG_IGNORE_MIN => args.ignore_addresses_min,
+            _ => 0,
+        }
+    }
+
+    fn load(&self, addr: u32, size: u32, args: &EmuArgs) -> Result<u32, EmuStop> {
+        if (addr & (size - 1)) != 0 {
+            return Err(EmuStop::Exception { cause: ExcCause::LOAD_ADDR_MISALIGNED, tval: addr });
+        }
+        let mem: &[u8];
+        let offs: usize;
+        if addr.wrapping_sub(self.memconf.datamem_addr_virt) < self.datamem.len() as u32 {
+            mem = &self.datamem;
+            offs = addr.wrapping_sub(self.memconf.datamem_addr_virt) as usize;
+        }
+        else if addr.wrapping_sub(self.memconf.instmem_addr_virt) < self.instmem.len() as u32 {
+            mem = &self.instmem;
+            offs = addr.wrapping_sub(self.memconf.instmem_addr_virt) as usize;
+        }
+        else if addr.wrapping_sub(FUZZCORE_MMIO_BASE) < FUZZCORE_MMIO_SIZE && size == 4 {
+            return Ok(self.mmio_read(addr - FUZZCORE_MMIO_BASE, args));
+        }
+        else {
+            return Err(EmuStop::Exception { cause: ExcCause::LOAD_ACCESS_FAULT, tval: addr });
+        }
+        let mut val: u32 = 0;
+        for i in 0..size as usize {
+            val |= (mem[offs + i] as u32) << (8 * i);
+        }
+        Ok(val)
+    }
+
+    //Returns Ok(true) if the program signalled completion.
+    fn store(&mut self, addr: u32, size: u32, val: u32) -> Result<bool, EmuStop> {
+        if (addr & (size - 1)) != 0 {
+            return Err(EmuStop::Exception { cause: ExcCause::STORE_ADDR_MISALIGNED, tval: addr });
+        }
+        if addr.wrapping_sub(self.memconf.datamem_addr_virt) < self.datamem.len() as u32 {
+            let offs = addr.wrapping_sub(self.memconf.datamem_addr_virt) as usize;
+            for i in 0..size as usize {
+                self.datamem[offs + i] = (val >> (8 * i)) as u8;
+            }
+            self.datamem_dirty[offs >> DATAMEM_PAGE_SHIFT] = true;
+            return Ok(false);
+        }
+        if addr.wrapping_sub(FUZZCORE_MMIO_BASE) < FUZZCORE_MMIO_SIZE && size == 4 {
+            //All other FuzzerCore registers are only written by the host.
+            return Ok(addr - FUZZCORE_MMIO_BASE == FUZZCORE_REG_SETINTR && val != 0);
+        }
+        Err(EmuStop::Exception { cause: ExcCause::STORE_ACCESS_FAULT, tval: addr })
+    }
+
+    #[inline]
+    fn set_reg(&mut self, rd: usize, val: u32) {
+        if rd != 0 {
+            self.regs[rd] = val;
+        }
+    }
+
+    //Executes the program from its entry point until it signals completion, raises an exception or times out.
+    fn execute(&mut self, args: &EmuArgs, bitmap: &mut [u8]) -> EmuStop {
+        let bitmap_mask = bitmap.len() as u32 - 1;
+        self.regs = [0; 32];
+        self.pc = self.memconf.instmem_addr_virt;
+        self.trap_csrs = TrapCsrs::default();
+        self.counter = 1;
+        loop {
+            if args.timeout_cycles != 0 && self.counter >= args.timeout_cycles {
+                return EmuStop::Timeout;
+            }
+            if self.counter % STOP_POLL_INTERVAL == 0 && self.stop_req.load(std::sync::atomic::Ordering::Relaxed) {
+                return EmuStop::ShuttingDown;
+            }
+            let pc = self.pc;
+            let instr_offs = pc.wrapping_sub(self.memconf.instmem_addr_virt) as usize;
+            if instr_offs + 4 > self.instmem.len() {
+                return EmuStop::Exception { cause: ExcCause::INSTR_ACCESS_FAULT, tval: pc };
+            }
+            let instr = u32::from_le_bytes([
+                self.instmem[instr_offs], self.instmem[instr_offs + 1],
+                self.instmem[instr_offs + 2], self.instmem[instr_offs + 3]
+            ]);
+            let next_pc = match self.step(pc, instr, args) {
+                Ok(Some(next_pc)) => next_pc,
+                Ok(None) => return EmuStop::Done,
+                Err(stop) => return stop,
+            };
+            if (next_pc & 3) != 0 {
+                return EmuStop::Exception { cause: ExcCause::INSTR_ADDR_MISALIGNED, tval: next_pc };
+            }
+            if is_cf_instr(instr) && pc < args.ignore_addresses_min && next_pc < args.ignore_addresses_min {
+                let index = (cf_hash(next_pc.wrapping_add(pc)) & bitmap_mask) as usize;
+                bitmap[index] = bitmap[index].saturating_add(1);
+            }
+            self.pc = next_pc;
+            self.counter += 1;
+        }
+    }
+
+    //Executes a single instruction. Returns the next pc, or None if the program has signalled completion.
+    fn step(&mut self, pc: u32, instr: u32, args: &EmuArgs) -> Result<Option<u32>, EmuStop> {
+        let illegal = EmuStop::Exception { cause: ExcCause::ILLEGAL_INSTR, tval: instr };
+        let opcode = instr & 0x7F;
+        let rd = ((instr >> 7) & 0x1F) as usize;
+        let funct3 = (instr >> 12) & 7;
+        let rs1 = ((instr >> 15) & 0x1F) as usize;
+        let rs2 = ((instr >> 20) & 0x1F) as usize;
+        let funct7 = instr >> 25;
+        let v1 = self.regs[rs1];
+        let v2 = self.regs[rs2];
+        let imm_i = ((instr as i32) >> 20) as u32;
+        let imm_s = ((((instr as i32) >> 25) << 5) as u32) | ((instr >> 7) & 0x1F);
+        let imm_b = ((((instr as i32) >> 31) << 12) as u32)
+            | ((instr << 4) & 0x800)
+            | ((instr >> 20) & 0x7E0)
+            | ((instr >> 7) & 0x1E);
+        let imm_u = instr & 0xFFFFF000;
+        let imm_j = ((((instr as i32) >> 31) << 20) as u32)
+            | (instr & 0xFF000)
+            | ((instr >> 9) & 0x800)
+            | ((instr >> 20) & 0x7FE);
+        let mut next_pc = pc.wrapping_add(4);
+        match opcode {
+            0b0110111 => self.set_reg(rd, imm_u), //LUI
+            0b0010111 => self.set_reg(rd, pc.wrapping_add(imm_u)), //AUIPC
+            0b1101111 => { //JAL
+                self.set_reg(rd, next_pc);
+                next_pc = pc.wrapping_add(imm_j);
+            },
+            0b1100111 => { //JALR
+                if funct3 != 0 { return Err(illegal); }
+                let target = v1.wrapping_add(imm_i) & !1;
+                self.set_reg(rd, next_pc);
+                next_pc = target;
+            },
+            0b1100011 => { //BRANCH
+                let taken = match funct3 {
+                    0 => v1 == v2,
+                    1 => v1 != v2,
+                    4 => (v1 as i32) < (v2 as i32),
+                    5 => (v1 as i32) >= (v2 as i32),
+                    6 => v1 < v2,
+                    7 => v1 >= v2,
+                    _ => return Err(illegal),
+                };
+                if taken {
+                    next_pc = pc.wrapping_add(imm_b);
+                }
+            },
+            0b0000011 => { //LOAD
+                let addr = v1.wrapping_add(imm_i);
+                let val = match funct3 {
+                    0 => self.load(addr, 1, args)? as u8 as i8 as i32 as u32,
+                    1 => self.load(addr, 2, args)? as u16 as i16 as i32 as u32,
+                    2 => self.load(addr, 4, args)?,
+                    4 => self.load(addr, 1, args)?,
+                    5 => self.load(addr, 2, args)?,
+                    _ => return Err(illegal),
+                };
+                self.set_reg(rd, val);
+            },
+            0b0100011 => { //STORE
+                let addr = v1.wrapping_add(imm_s);
+                let size = match funct3 {
+                    0 => 1,
+                    1 => 2,
+                    2 => 4,
+                    _ => return Err(illegal),
+                };
+                if self.store(addr, size, v2)? {
+                    return Ok(None);
+                }
+            },
+            0b0010011 => { //OP-IMM
+                let shamt = (instr >> 20) & 0x1F;
+                let val = match funct3 {
+                    0 => v1.wrapping_add(imm_i),
+                    2 => ((v1 as i32) < (imm_i as i32)) as u32,
+                    3 => (v1 < imm_i) as u32,
+                    4 => v1 ^ imm_i,
+                    6 => v1 | imm_i,
+                    7 => v1 & imm_i,
+                    1 if funct7 == 0 => v1 << shamt,
+                    5 if funct7 == 0 => v1 >> shamt,
+                    5 if funct7 == 0b0100000 => ((v1 as i32) >> shamt) as u32,
+                    _ => return Err(illegal),
+                };
+                self.set_reg(rd, val);
+            },
+            0b0110011 => { //OP
+                let val = match (funct7, funct3) {
+                    (0, 0) => v1.wrapping_add(v2),
+                    (0b0100000, 0) => v1.wrapping_sub(v2),
+                    (0, 1) => v1 << (v2 & 0x1F),
+                    (0, 2) => ((v1 as i32) < (v2 as i32)) as u32,
+                    (0, 3) => (v1 < v2) as u32,
+                    (0, 4) => v1 ^ v2,
+                    (0, 5) => v1 >> (v2 & 0x1F),
+                    (0b0100000, 5) => ((v1 as i32) >> (v2 & 0x1F)) as u32,
+                    (0, 6) => v1 | v2,
+                    (0, 7) => v1 & v2,
+                    //M extension
+                    (1, 0) => v1.wrapping_mul(v2),
+                    (1, 1) => (((v1 as i32 as i64) * (v2 as i32 as i64)) >> 32) as u32,
+                    (1, 2) => (((v1 as i32 as i64) * (v2 as u64 as i64)) >> 32) as u32,
+                    (1, 3) => (((v1 as u64) * (v2 as u64)) >> 32) as u32,
+                    (1, 4) => if v2 == 0 { u32::MAX } else { (v1 as i32).wrapping_div(v2 as i32) as u32 },
+                    (1, 5) => if v2 == 0 { u32::MAX } else { v1 / v2 },
+                    (1, 6) => if v2 == 0 { v1 } else { (v1 as i32).wrapping_rem(v2 as i32) as u32 },
+                    (1, 7) => if v2 == 0 { v1 } else { v1 % v2 },
+                    _ => return Err(illegal),
+                };
+                self.set_reg(rd, val);
+            },
+            0b0001111 => {}, //FENCE, FENCE.I
+            0b1110011 => { //SYSTEM
+                match funct3 {
+                    0 if instr == 0x00000073 => {
+                        //ECALL: Like the FuzzerCore, environment calls are not reported as crashes but trap to mtvec (direct mode).
+                        self.trap_csrs.mepc = pc;
+                        self.trap_csrs.mcause = ExcCause::ECALL_M;
+                        self.trap_csrs.mtval = 0;
+                        return Ok(Some(self.trap_csrs.mtvec & !3));
+                    },
+                    0 if instr == 0x00100073 => return Err(EmuStop::Exception { cause: ExcCause::BREAKPOINT, tval: pc }),
+                    0 if instr == 0x30200073 => return Ok(Some(self.trap_csrs.mepc)), //MRET
+                    1 | 2 | 3 | 5 | 6 | 7 => {
+                        //Zicsr: Only the counters and the trap CSRs are modelled, all other CSRs read as zero and ignore writes.
+                        let csr = instr >> 20;
+                        let csr_val = match csr {
+                            0xC00 | 0xC01 | 0xC02 | 0xB00 | 0xB02 => self.counter as u32,
+                            0xC80 | 0xC81 | 0xC82 | 0xB80 | 0xB82 => (self.counter >> 32) as u32,
+                            _ => self.trap_csrs.get_mut(csr).map(|v| *v).unwrap_or(0),
+                        };
+                        let operand = if funct3 >= 5 { rs1 as u32 } else { v1 };
+                        if let Some(csr_ref) = self.trap_csrs.get_mut(csr) {
+                            match funct3 & 3 {
+                                1 => *csr_ref = operand,
+                                2 => *csr_ref |= operand,
+                                _ => *csr_ref &= !operand,
+                            }
+                        }
+                        self.set_reg(rd, csr_val);
+                    },
+                    _ => return Err(illegal),
+                }
+            },
+            _ => return Err(illegal),
+        }
+        Ok(Some(next_pc))
+    }
+}
+
+#[async_trait(?Send)]
+impl<'a> TapascoRunner for TapascoRunnerEmu<'a> {
+    async fn run(&mut self, input: &[u8], bitmap: &mut [u8]) -> Result<TapascoHostRun,TapascoRunError> {
+        //Not properly 'async' (runs the emulation to completion).
+        if self.stop_req.load(std::sync::atomic::Ordering::Relaxed) == true {
+            return Err(TapascoRunError::LibAFL { source: libafl::Error::ShuttingDown });
+        }
+        // Check whether there is enough room for the input data (see TapascoDispatcher::try_dispatch).
+        let datamem_len_aligned = (self.datamem_init_len + 15) & !15;
+        let input_len_aligned = (input.len() + 15) & !15;
+        if input_len_aligned > self.memconf.datamem_size_phys_without_stack - datamem_len_aligned {
+            return Err(TapascoRunError::InputTooLarge {});
+        }
+        let input_data_offs = (self.memconf.datamem_size_phys_without_stack - input.len()) & !15;
+
+        // Restore the data memory and place the input at the end of the free data memory region.
+        self.reset_datamem();
+        self.datamem[input_data_offs..input_data_offs + input.len()].copy_from_slice(input);
+        let input_end = std::cmp::max(input_data_offs + input.len(), input_data_offs + 1) - 1;
+        for i_page in (input_data_offs >> DATAMEM_PAGE_SHIFT)..=(input_end >> DATAMEM_PAGE_SHIFT) {
+            self.datamem_dirty[i_page] = true;
+        }
+
+        let status: u32;
+        let mut exception: Option<TapascoRunException> = None;
+        {
+            let bitmap_slice = &mut *bitmap;
+            bitmap_slice.fill(0);
+            if bitmap_slice.len() < 4 || bitmap_slice.len() > BITMAP_SIZE_MAX || !bitmap_slice.len().is_power_of_two() {
+                //Same behaviour as the FuzzerCore: The program is not started.
+                status = TapascoRunStatusFlags::INVALID_BITMAP_SIZE_FLAG;
+                self.counter = 0;
+            }
+            else {
+                let args = EmuArgs {
+                    input_len: input.len() as u32,
+                    input_addr: self.memconf.datamem_addr_virt + input_data_offs as u32,
+                    bitmap_len: bitmap_slice.len() as u32,
+                    timeout_cycles: self.timeout_cycles,
+                    ignore_addresses_min: self.ignore_addresses_min,
+                };
+                status = match self.execute(&args, bitmap_slice) {
+                    EmuStop::ShuttingDown => return Err(TapascoRunError::LibAFL { source: libafl::Error::ShuttingDown }),
+                    EmuStop::Done => 0,
+                    EmuStop::Timeout => TapascoRunStatusFlags::TIMEOUT_FLAG,
+                    EmuStop::Exception { cause, tval } => {
+                        exception = Some(TapascoRunException { cause: cause, epc: self.pc, tval: tval });
+                        TapascoRunStatusFlags::PROGRAM_CRASH_FLAG
+                            | ((cause << TapascoRunStatusFlags::PROGRAM_CRASH_CAUSE_SHIFT) & TapascoRunStatusFlags::PROGRAM_CRASH_CAUSE_MASK)
+                    },
+                };
+            }
+        }
+        if self.stop_req.load(std::sync::atomic::Ordering::Relaxed) == true {
+            return Err(TapascoRunError::LibAFL { source: libafl::Error::ShuttingDown });
+        }
+        Ok(TapascoHostRun {
+            status: status,
+            duration: Some(TapascoHostRun::duration_from_cycles(self.counter)),
+            exception: exception
+        })
+    }
+}
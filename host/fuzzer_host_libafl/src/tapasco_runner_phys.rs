@@ -1,29 +1,28 @@
+use std::os::unix::io::{AsRawFd, RawFd};
 use std::sync::Arc;
-use std::io::prelude::*;
-use std::io::SeekFrom;
-use std::sync::atomic::AtomicBool;
-use std::time::Duration;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use snafu::ResultExt;
 
 use async_trait::async_trait;
+use futures::stream::{FuturesUnordered, StreamExt};
 
 use tapasco::device::DeviceSize;
 use tapasco::device::OffchipMemory;
-use tapasco::device::{Device, DeviceAddress, PEParameter};
+use tapasco::device::{Device, DeviceAddress, DeviceId, PEParameter};
 use tapasco::pe::PE;
 
-use crate::tapasco_host::{TapascoRunStatusFlags, TapascoHostRun, TapascoRunner, TapascoRunError};
+use crate::tapasco_host::{TapascoRunStatusFlags, TapascoHostRun, TapascoRunException, TapascoRunner, TapascoRunError};
 use crate::tapasco_host::{TapascoDeviceSnafu, TapascoJobSnafu, TapascoAllocatorSnafu, TapascoDMASnafu, TapascoPESnafu};
+use crate::target_image::TargetImage;
+use crate::pe_completion::{CompletionReactor, InterruptFuture};
+use serde::Deserialize;
 
 
-//static INSTMEM_DRAM_OFFS : u32 = 0;
-static INSTMEM_SIZE : usize = 8*1024*1024;
-//static DATAMEM_DRAM_OFFS : u32 = INSTMEM_DRAM_OFFS + INSTMEM_SIZE as u32;
-static DATAMEM_SIZE : usize = 8*1024*1024;
-
+//Default DRAM region size, can be overridden in the PE profiles.
 static DRAM_REGION_SHIFT : u32 = 32-8;
-static DRAM_REGION_SIZE : u32 = 1 << DRAM_REGION_SHIFT;
-static DRAM_REGION_MASK : u64 = (!(DRAM_REGION_SIZE - 1)) as u64;
 //static PROCESSOR_INSTMEM_BASE : u32 = 0x40000000;
 //static PROCESSOR_DATAMEM_BASE : u32 = 0x40800000;
 
@@ -33,31 +32,43 @@ static PE_INVFLAG_ICACHE : u32 = 1 << 0;
 static PE_INVFLAG_BP : u32 = 1 << 1;
 static PE_INVFLAG_DCACHE : u32 = 1 << 2;
 
-#[derive(Copy, Clone)]
+//With data_snapshot, number of runs of a PE after which the read-only data in its memory is read back and compared to the image.
+static DATA_SNAPSHOT_VERIFY_INTERVAL : u32 = 64;
+
+#[derive(Copy, Clone, Debug)]
 pub struct PEMemConfig {
-    invalidate_caches: bool,
-    instmem_offs_phys: u32,
-    instmem_size_phys: usize,
-    datamem_offs_phys: u32,
-    datamem_size_phys_without_stack: usize,
-    datamem_size_phys: usize,
-    datamem_addr_virt: u32, //Virtual base location as visible from programs.
-    bitmap_via_dram_dma: bool,
-    bitmap_bram_addr: u32,
-    bitmap_bram_size: u32
+    pub(crate) invalidate_caches: bool,
+    pub(crate) instmem_offs_phys: u32,
+    pub(crate) instmem_size_phys: usize,
+    pub(crate) instmem_addr_virt: u32, //Virtual base location as visible from programs.
+    pub(crate) datamem_offs_phys: u32,
+    pub(crate) datamem_size_phys_without_stack: usize,
+    pub(crate) datamem_size_phys: usize,
+    pub(crate) datamem_addr_virt: u32, //Virtual base location as visible from programs.
+    pub(crate) bitmap_via_dram_dma: bool,
+    pub(crate) bitmap_bram_addr: u32,
+    pub(crate) bitmap_bram_size: u32,
+    pub(crate) dram_region_shift: u32 //Size of the DRAM region addressable by a PE (see arg 8).
+}
+impl PEMemConfig {
+    fn dram_region_mask(&self) -> u64 {
+        !((1u64 << self.dram_region_shift) - 1)
+    }
 }
 //DRAM: 16 MiB section; 1 MiB imem, 7 MiB padding, 8 MiB dmem. Assuming 32K stack.
-static PEMEMCONFIG_DRAM: PEMemConfig = PEMemConfig {
+pub(crate) static PEMEMCONFIG_DRAM: PEMemConfig = PEMemConfig {
     invalidate_caches: true,
     instmem_offs_phys: 0, 
     instmem_size_phys: 1*1024*1024,
+    instmem_addr_virt: 0x40000000,
     datamem_offs_phys: 8*1024*1024,
     datamem_size_phys_without_stack: 8*1024*1024 - 32*1024,
     datamem_size_phys: 8*1024*1024,
     datamem_addr_virt: 0x40800000,
     bitmap_via_dram_dma: false,
     bitmap_bram_addr: 0x20000,
-    bitmap_bram_size: 0x2000
+    bitmap_bram_size: 0x2000,
+    dram_region_shift: DRAM_REGION_SHIFT
 };
 //HBM: Like DRAM; 16 MiB section; 1 MiB imem, 7 MiB padding, 8 MiB dmem. Assuming 32K stack.
 //-> Accessed via PE local memory interface instead.
@@ -65,13 +76,15 @@ static PEMEMCONFIG_HBM: PEMemConfig = PEMemConfig {
     invalidate_caches: true,
     instmem_offs_phys: 0, 
     instmem_size_phys: 1*1024*1024,
+    instmem_addr_virt: 0x40000000,
     datamem_offs_phys: 8*1024*1024,
     datamem_size_phys_without_stack: 8*1024*1024 - 32*1024,
     datamem_size_phys: 8*1024*1024,
     datamem_addr_virt: 0x40800000,
     bitmap_via_dram_dma: false,
     bitmap_bram_addr: 0x01000000,
-    bitmap_bram_size: 0x2000
+    bitmap_bram_size: 0x2000,
+    dram_region_shift: DRAM_REGION_SHIFT
 };
 //BRAM: 2x64 KiB; 64 
 // -> Is configured in core to have the same imem, dmem base addresses as with DRAM.
@@ -79,13 +92,15 @@ static PEMEMCONFIG_BRAM: PEMemConfig = PEMemConfig {
     invalidate_caches: false,
     instmem_offs_phys: 0, 
     instmem_size_phys: 64*1024,
+    instmem_addr_virt: 0x40000000,
     datamem_offs_phys: 64*1024,
     datamem_size_phys_without_stack: 64*1024 - 4*1024,
     datamem_size_phys: 64*1024,
     datamem_addr_virt: 0x40800000,
     bitmap_via_dram_dma: false,
     bitmap_bram_addr: 0x20000,
-    bitmap_bram_size: 0x2000
+    bitmap_bram_size: 0x2000,
+    dram_region_shift: DRAM_REGION_SHIFT
 };
 //BRAM via DMA ("dram"): 2x64 KiB; 64 
 // -> Is configured in core to have the same imem, dmem base addresses as with DRAM.
@@ -93,13 +108,15 @@ static PEMEMCONFIG_BRAM_DMA: PEMemConfig = PEMemConfig {
     invalidate_caches: false,
     instmem_offs_phys: 0, 
     instmem_size_phys: 64*1024,
+    instmem_addr_virt: 0x40000000,
     datamem_offs_phys: 64*1024,
     datamem_size_phys_without_stack: 64*1024 - 4*1024,
     datamem_size_phys: 64*1024,
     datamem_addr_virt: 0x40800000,
     bitmap_via_dram_dma: true,
     bitmap_bram_addr: 0x20000,
-    bitmap_bram_size: 0x2000
+    bitmap_bram_size: 0x2000,
+    dram_region_shift: DRAM_REGION_SHIFT
 };
 //BRAM: 2x64 KiB
 // Variant without fuzzer hardware (standard tapasco-riscv PE)
@@ -107,46 +124,64 @@ static PEMEMCONFIG_BRAM_TAPASCORISCV: PEMemConfig = PEMemConfig {
     invalidate_caches: false,
     instmem_offs_phys: 0, 
     instmem_size_phys: 64*1024,
+    instmem_addr_virt: 0x00100000,
     datamem_offs_phys: 64*1024,
     datamem_size_phys_without_stack: 64*1024 - 4*1024,
     datamem_size_phys: 64*1024,
     datamem_addr_virt: 0x00800000,
     bitmap_via_dram_dma: false,
     bitmap_bram_addr: 0x20000,
-    bitmap_bram_size: 0
+    bitmap_bram_size: 0,
+    dram_region_shift: DRAM_REGION_SHIFT
 };
 
-fn read_binary(path: &str) -> Result<(Box<[u8]>, Box<[u8]>), std::io::Error> {
-    //Open the given file.
-    let mut f = std::fs::File::open(&path)?;
-
-    //Allocate a buffer that corresponds to the program binary in BRAM.
-    let mut buffer: Vec<u8> = Vec::new();
-    buffer.resize(INSTMEM_SIZE + DATAMEM_SIZE, 0);
-
-    //Determine the file size, don't read past the hardcoded BRAM size.
-    let mut file_size = f.seek(SeekFrom::End(0))?;
-    f.seek(SeekFrom::Start(0))?;
-    file_size = std::cmp::min(file_size, (INSTMEM_SIZE + DATAMEM_SIZE) as u64);
-
-    //Read the file. Note: read(..) does not guarantee that all data is read in one call.
-    let mut read_pos: usize = 0;
-    while read_pos < file_size as usize {
-        match f.read(&mut buffer[read_pos..file_size as usize]) {
-            Ok(n) if n == 0 => Err(std::io::Error::new(std::io::ErrorKind::Other, "File end before expected eof"))?,
-            Ok(n) => read_pos += n,
-            Err(e) => Err(e)?
-        };
+/// Location of the program memory of a PE.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PEMemoryKind {
+    /// Device DRAM (default memory), in a separate allocation for each PE.
+    Dram,
+    /// PE local memory (e.g. BRAM, or HBM programmed via the local memory interface).
+    Local,
+}
+
+/// Describes how to use the PEs with a given VLNV.
+#[derive(Clone, Debug)]
+pub struct PEProfile {
+    pub vlnv: String,
+    pub memconf: PEMemConfig,
+    pub memory: PEMemoryKind,
+    /// Standard tapasco-riscv PE without fuzzer hardware (always uses PE local memory).
+    pub tapascoriscv: bool,
+}
+
+/// Returns a built-in memory configuration by name, along with its memory location and whether it is for tapasco-riscv PEs.
+pub fn builtin_memconfig(name: &str) -> Option<(PEMemConfig, PEMemoryKind, bool)> {
+    match name {
+        "dram" => Some((PEMEMCONFIG_DRAM, PEMemoryKind::Dram, false)),
+        "hbm" => Some((PEMEMCONFIG_HBM, PEMemoryKind::Local, false)),
+        "bram" => Some((PEMEMCONFIG_BRAM, PEMemoryKind::Local, false)),
+        "bram_dma" => Some((PEMEMCONFIG_BRAM_DMA, PEMemoryKind::Dram, false)),
+        "bram_tapascoriscv" => Some((PEMEMCONFIG_BRAM_TAPASCORISCV, PEMemoryKind::Local, true)),
+        _ => None
     }
+}
 
-    //Select the memory ranges actually present in the binary.
-    
-    let instmem: Box<[u8]> = buffer[0..std::cmp::min(file_size, INSTMEM_SIZE as u64) as usize].into();
-    let datamem_size = 
-        if file_size <= INSTMEM_SIZE as u64 { 0 }
-        else { std::cmp::min(file_size - (INSTMEM_SIZE as u64), DATAMEM_SIZE as u64) as usize };
-    let datamem: Box<[u8]> = buffer[INSTMEM_SIZE..INSTMEM_SIZE+datamem_size].into();
-    Ok((instmem, datamem))
+/// Returns the profiles of all PE types supported by default.
+pub fn builtin_pe_profiles() -> Vec<PEProfile> {
+    [
+        //Regular DRAM
+        ("esa.informatik.tu-darmstadt.de:fuzzer:cva5_pe_fuzzer:1.0", "dram"),
+        //BRAM (alternatively: "bram_dma")
+        ("esa.informatik.tu-darmstadt.de:fuzzer:cva5_bram_pe_fuzzer:1.0", "bram"),
+        //HBM (programming via 'PE local memory' interface)
+        ("esa.informatik.tu-darmstadt.de:fuzzer:cva5_hbm_pe_fuzzer:1.0", "hbm"),
+        //tapasco-riscv BRAM (for evaluation purposes)
+        ("esa.informatik.tu-darmstadt.de:tapasco:cva5_pe:1.0", "bram_tapascoriscv"),
+    ].iter().map(|(vlnv, base)| {
+        let (memconf, memory, tapascoriscv) = builtin_memconfig(base).unwrap();
+        PEProfile { vlnv: vlnv.to_string(), memconf: memconf, memory: memory, tapascoriscv: tapascoriscv }
+    }).collect()
 }
 
 pub struct PEDesc {
@@ -155,7 +190,24 @@ pub struct PEDesc {
     dram: Option<Arc<OffchipMemory>>,
     dram_allocation: Option<DeviceAddress>,
     instmem_initialized: bool,
-    is_regular_tapascoriscv: bool
+    //Content hash of the image in the PE memory, if any (see TargetImage::content_hash).
+    loaded_image: Option<u64>,
+    //Whether the PE memory holds the complete data section of the image.
+    datamem_initialized: bool,
+    //Runs with a partial data upload since the read-only data was last uploaded or verified.
+    snapshot_runs: u32,
+    is_regular_tapascoriscv: bool,
+    //Index of the PE over all devices, for messages.
+    index: usize,
+    //Index of the PE within its dispatcher, for the busy time.
+    slot: usize,
+    //TLKM device and VLNV of the PE, to split the PEs across dispatchers.
+    device_id: DeviceId,
+    vlnv: String,
+    //Failed runs since the last successful run.
+    consecutive_errors: u32,
+    total_errors: u64,
+    recovery_attempted: bool
 }
 impl PEDesc {
     pub fn new(
@@ -167,7 +219,17 @@ impl PEDesc {
             dram: dram,
             dram_allocation: None,
             instmem_initialized: false,
-            is_regular_tapascoriscv: false
+            loaded_image: None,
+            datamem_initialized: false,
+            snapshot_runs: 0,
+            is_regular_tapascoriscv: false,
+            index: 0,
+            slot: 0,
+            device_id: 0,
+            vlnv: String::new(),
+            consecutive_errors: 0,
+            total_errors: 0,
+            recovery_attempted: false
         }
     }
     pub fn new_tapascoriscv(
@@ -186,16 +248,174 @@ impl Drop for PEDesc {
         }
     }
 }
+//A job that has been started on a PE, but whose results have not been retrieved yet.
+struct InflightRun {
+    pedesc: PEDesc,
+    devbitmap_addr_opt: Option<DeviceAddress>,
+    bitmap_len: u32,
+    start_time: Instant,
+    //Phases up to the start of the job, and the time of the start.
+    latency: LatencyStats,
+    exec_start_time: Instant,
+    //Completion interrupt, armed when the job is started.
+    completion: InterruptFuture
+}
+///Time spent in each phase of the jobs, see [`TapascoDispatcher::pe_latency_stats`] and [`thread_latency_stats`].
+///All phases are recorded for the successful jobs only.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LatencyStats {
+    ///Number of completed jobs.
+    pub runs: u64,
+    ///Waiting for an idle PE.
+    pub wait: Duration,
+    ///DMA uploads of the program, data section and input.
+    pub upload: Duration,
+    ///From the start of the job to its completion interrupt.
+    pub exec: Duration,
+    ///Reading back the status, exception details and bitmap.
+    pub readback: Duration,
+    ///Writes of the cache invalidation flags.
+    pub invalidate: Duration,
+}
+impl LatencyStats {
+    ///Adds the times and runs of another [`LatencyStats`].
+    pub fn add(&mut self, other: &LatencyStats) {
+        self.runs += other.runs;
+        self.wait += other.wait;
+        self.upload += other.upload;
+        self.exec += other.exec;
+        self.readback += other.readback;
+        self.invalidate += other.invalidate;
+    }
+    ///Returns the difference to an earlier snapshot of the same stats.
+    pub fn since(&self, earlier: &LatencyStats) -> LatencyStats {
+        LatencyStats {
+            runs: self.runs - earlier.runs,
+            wait: self.wait.saturating_sub(earlier.wait),
+            upload: self.upload.saturating_sub(earlier.upload),
+            exec: self.exec.saturating_sub(earlier.exec),
+            readback: self.readback.saturating_sub(earlier.readback),
+            invalidate: self.invalidate.saturating_sub(earlier.invalidate),
+        }
+    }
+    ///Formats the mean time per job of each phase.
+    pub fn mean_summary(&self) -> String {
+        let mean_us = |duration: Duration| duration.as_secs_f64() * 1e6 / std::cmp::max(self.runs, 1) as f64;
+        format!("wait {:.1}us, upload {:.1}us, exec {:.1}us, readback {:.1}us, invalidate {:.1}us",
+            mean_us(self.wait), mean_us(self.upload), mean_us(self.exec), mean_us(self.readback), mean_us(self.invalidate))
+    }
+}
+thread_local! {
+    //Phase times of the jobs run from the current thread, over all dispatchers.
+    static THREAD_LATENCY: RefCell<LatencyStats> = RefCell::new(LatencyStats::default());
+}
+///Returns the phase times of the jobs run from the current thread so far.
+pub fn thread_latency_stats() -> LatencyStats {
+    THREAD_LATENCY.with(|latency| *latency.borrow())
+}
+///Settings of a [`TapascoDispatcher`].
+#[derive(Clone, Copy, Debug)]
+pub struct DispatcherConfig {
+    ///Keep the read-only data of the first run in the PE memory, and re-upload only the writable data sections.
+    /// Relies on the target not writing to its read-only data (which would persist to the following runs).
+    /// To limit the effect of such writes, the full data section is uploaded again after a crash or timeout,
+    ///  and when a periodic readback of the read-only data finds a difference.
+    pub data_snapshot: bool,
+    ///Wall-clock time after which a running job is considered hung.
+    /// Must be above the largest cycle budget of the runs, which the PE enforces itself.
+    pub watchdog: Duration,
+    ///Number of failed runs in a row after which a PE is recovered or quarantined. Hangs are handled right away.
+    pub max_pe_errors: u32,
+}
+impl Default for DispatcherConfig {
+    fn default() -> Self {
+        Self {
+            data_snapshot: false,
+            watchdog: Duration::from_secs(10),
+            max_pe_errors: 3,
+        }
+    }
+}
+///Health of the PEs of a dispatcher, see [`TapascoDispatcher::pe_health`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PEHealth {
+    ///PEs still in use.
+    pub active: usize,
+    ///PEs taken out of service after repeated failures or a hang.
+    pub quarantined: usize,
+    ///Failed runs over all PEs, including the retried ones.
+    pub errors: u64,
+}
+//Collects the results of a batch, with the jobs yet to be (re-)started.
+struct BatchState<'b> {
+    results: Vec<Option<Result<TapascoHostRun, TapascoRunError>>>,
+    bitmaps: Vec<Option<&'b mut [u8]>>,
+    attempts: Vec<usize>,
+    pending: VecDeque<usize>,
+}
+impl<'b> BatchState<'b> {
+    //Stores the result of a job, or re-queues the job if it failed due to a faulty PE and may be retried on another PE.
+    fn complete(&mut self, i_input: usize, bitmap: &'b mut [u8], result: Result<TapascoHostRun, TapascoRunError>, max_attempts: usize) {
+        let retry = match &result {
+            Err(e) => e.is_pe_failure() && self.attempts[i_input] < max_attempts,
+            Ok(_) => false
+        };
+        if retry {
+            self.bitmaps[i_input] = Some(bitmap);
+            self.pending.push_back(i_input);
+        }
+        else {
+            self.results[i_input] = Some(result);
+        }
+    }
+}
+///Amount of data memory uploaded by a dispatcher, see [`TapascoDispatcher::data_upload_stats`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DataUploadStats {
+    ///Number of dispatched runs.
+    pub runs: u64,
+    ///Bytes uploaded for the data section and the inputs.
+    pub bytes: u64,
+    ///Bytes that a full upload of the data section would have taken.
+    pub bytes_full: u64,
+}
+///Time a PE has spent on jobs, see [`TapascoDispatcher::pe_busy_times`].
+#[derive(Clone, Copy, Debug)]
+pub struct PEBusyTime {
+    ///Index of the PE over all devices.
+    pub index: usize,
+    ///Time from the start of the upload to the release of the PE, summed over all jobs.
+    pub busy: Duration,
+}
 ///Manages a set of PEs to run fuzzing jobs on.
 pub struct TapascoDispatcher {
     idlepe_mutex: async_std::sync::Mutex<Vec<PEDesc>>,
-    idlepe_condvar: async_std::sync::Condvar //idlepe_mutex
+    idlepe_condvar: async_std::sync::Condvar, //idlepe_mutex
+    num_pes: usize,
+    //Waits for the completion interrupts of all jobs in flight.
+    reactor: CompletionReactor,
+    config: DispatcherConfig,
+    //PEs not quarantined, either idle or running a job.
+    num_active_pes: AtomicUsize,
+    //Quarantined PEs, kept to retain their memory allocations (a hung PE may still access them).
+    quarantine: async_std::sync::Mutex<Vec<PEDesc>>,
+    num_errors: AtomicU64,
+    upload_runs: AtomicU64,
+    upload_bytes: AtomicU64,
+    upload_bytes_full: AtomicU64,
+    //Busy time in ns and index over all devices for each PE slot.
+    pe_busy_ns: Vec<AtomicU64>,
+    pe_indices: Vec<usize>,
+    //Phase times for each PE slot.
+    pe_latency: Vec<std::sync::Mutex<LatencyStats>>
 }
 impl TapascoDispatcher {
+    ///Creates a dispatcher for the given PEs.
     pub fn new(
-        pes: Vec<PEDesc>
+        pes: Vec<PEDesc>, config: DispatcherConfig
     ) -> Result<Arc<Self>, TapascoRunError> {
-        let jobs_idle: Result<Vec<PEDesc>, TapascoRunError> = pes.into_iter().map(|mut pedesc| {
+        let jobs_idle: Result<Vec<PEDesc>, TapascoRunError> = pes.into_iter().enumerate().map(|(slot, mut pedesc)| {
+            pedesc.slot = slot;
             if let Some(dram) = pedesc.dram.as_deref() {
                 //Allocate DRAM according to the memory configuration.
                 let mut alloc_size = pedesc.memconf.datamem_offs_phys as DeviceSize + pedesc.memconf.datamem_size_phys as DeviceSize;
@@ -204,9 +424,9 @@ impl TapascoDispatcher {
                     alloc_size = bitmap_end;
                 }
                 let dram_allocation = dram.allocator().lock()?.allocate(alloc_size, None).context(TapascoAllocatorSnafu)?;
-                //For now: Assumes that all allocations are aligned to DRAM_REGION_MASK. 
+                //For now: Assumes that all allocations are aligned to the DRAM region size. 
                 //-> Relevant for regular (AU280: DDR4) DRAM variants, since the address space is shared across all PEs.
-                if (dram_allocation & !DRAM_REGION_MASK) != 0 {
+                if (dram_allocation & !pedesc.memconf.dram_region_mask()) != 0 {
                     dram.allocator().lock().unwrap().free(dram_allocation).unwrap();
                     return Err(TapascoRunError::DRAMAllocationMisaligned { addr: dram_allocation, alignment_num: alloc_size/1024 });
                 }
@@ -214,43 +434,284 @@ impl TapascoDispatcher {
             }
             Ok(pedesc)
         }).collect();
+        let jobs_idle = jobs_idle?;
+        let num_pes = jobs_idle.len();
+        let pe_indices = jobs_idle.iter().map(|pedesc| pedesc.index).collect();
         let _self = Arc::new(Self { 
-            idlepe_mutex: async_std::sync::Mutex::new(jobs_idle?),
-            idlepe_condvar: async_std::sync::Condvar::new()
+            idlepe_mutex: async_std::sync::Mutex::new(jobs_idle),
+            idlepe_condvar: async_std::sync::Condvar::new(),
+            num_pes: num_pes,
+            reactor: CompletionReactor::new()?,
+            config: config,
+            num_active_pes: AtomicUsize::new(num_pes),
+            quarantine: async_std::sync::Mutex::new(Vec::new()),
+            num_errors: AtomicU64::new(0),
+            upload_runs: AtomicU64::new(0),
+            upload_bytes: AtomicU64::new(0),
+            upload_bytes_full: AtomicU64::new(0),
+            pe_busy_ns: (0..num_pes).map(|_| AtomicU64::new(0)).collect(),
+            pe_indices: pe_indices,
+            pe_latency: (0..num_pes).map(|_| std::sync::Mutex::new(LatencyStats::default())).collect()
         });
         Ok(_self)
     }
+    ///Returns the number of PEs managed by this dispatcher.
+    pub fn num_pes(&self) -> usize {
+        self.num_pes
+    }
+    ///Returns the number of PEs currently waiting for a job.
+    pub async fn num_idle_pes(&self) -> usize {
+        self.idlepe_mutex.lock().await.len()
+    }
+    ///Returns the number of active and quarantined PEs, and the number of failed runs.
+    pub fn pe_health(&self) -> PEHealth {
+        let num_active = self.num_active_pes.load(Ordering::SeqCst);
+        PEHealth {
+            active: num_active,
+            quarantined: self.num_pes - num_active,
+            errors: self.num_errors.load(Ordering::Relaxed),
+        }
+    }
+    ///Returns the amount of data memory uploaded so far, compared to full uploads.
+    pub fn data_upload_stats(&self) -> DataUploadStats {
+        DataUploadStats {
+            runs: self.upload_runs.load(Ordering::Relaxed),
+            bytes: self.upload_bytes.load(Ordering::Relaxed),
+            bytes_full: self.upload_bytes_full.load(Ordering::Relaxed),
+        }
+    }
+    ///Returns the time each PE (including quarantined ones) has spent on jobs so far.
+    pub fn pe_busy_times(&self) -> Vec<PEBusyTime> {
+        self.pe_indices.iter().zip(&self.pe_busy_ns).map(|(&index, busy_ns)| PEBusyTime {
+            index: index,
+            busy: Duration::from_nanos(busy_ns.load(Ordering::Relaxed)),
+        }).collect()
+    }
+    ///Returns the phase times of the jobs of each PE so far, along with the PE index over all devices.
+    /// The time waiting for an idle PE is counted for the PE that was acquired.
+    pub fn pe_latency_stats(&self) -> Vec<(usize, LatencyStats)> {
+        self.pe_indices.iter().zip(&self.pe_latency)
+            .map(|(&index, latency)| (index, *latency.lock().unwrap()))
+            .collect()
+    }
     ///Runs a fuzzing job and returns its result and bitmap.
     /// Performs an async wait if no PE is ready.
     /// 
     /// * `task_id`: An ID to pass on to the PE. Can be set to any value for now.
-    /// * `instmem`, `datamem`: Program binary data with addresses and sizes as in the INSTMEM_* and DATAMEM_* constants.
+    /// * `image`: Program binary, checked against the memory layout of each PE before its first use.
     /// * `input`: Program inputs to store in the free data memory region. If no space is available, an error is returned.
-    /// * `bitmap_mem`: Buffer for the control flow bitmap. No other thread or task may lock the mutex.
+    /// * `bitmap`: Buffer for the control flow bitmap, which the DMA transfer writes to directly.
     /// 
     /// On success, the returned TapascoHostRun carries details on the execution result.
-    pub async fn run(&self, task_id: u32, instmem: &[u8], datamem: &[u8], ignore_addresses_min: u32, timeout_cycles: u64,
-            input: &[u8], bitmap_mem: Arc<std::sync::Mutex<Box<[u8]>>>
+    /// If the job fails due to a faulty PE, it is retried on another PE (up to the number of PEs).
+    /// Returns [`TapascoRunError::NoPEsLeft`] once all PEs are quarantined.
+    pub async fn run(&self, task_id: u32, image: &TargetImage, ignore_addresses_min: u32, timeout_cycles: u64,
+            input: &[u8], bitmap: &mut [u8]
     ) -> Result<TapascoHostRun, TapascoRunError> {
-        let bitmap_len: u32;
-        // Lock the bitmap_mem to access the length.
-        {
-            let lock_res = (*bitmap_mem).try_lock();
-            assert!(match lock_res {Ok(_) => {true} Err(_) => {false}},
-                "Unable to lock mutex that should have been unlocked: {}", lock_res.unwrap_err());
-            let bitmap_slice = &mut **lock_res.unwrap();
-            bitmap_len = bitmap_slice.len() as u32;
-        }
-        // Fetch a PE ready for a new task, or wait for one.
-        let mut pedesc;
+        self.run_from(None, task_id, image, ignore_addresses_min, timeout_cycles, input, bitmap).await
+    }
+
+    ///Runs a fuzzing job as in [`TapascoDispatcher::run`], but only if a PE is idle right now.
+    /// Returns None without running the job otherwise.
+    pub async fn try_run(&self, task_id: u32, image: &TargetImage, ignore_addresses_min: u32, timeout_cycles: u64,
+            input: &[u8], bitmap: &mut [u8]
+    ) -> Option<Result<TapascoHostRun, TapascoRunError>> {
+        let pedesc = self.try_acquire_pe().await?;
+        Some(self.run_from(Some((pedesc, Duration::ZERO)), task_id, image, ignore_addresses_min, timeout_cycles, input, bitmap).await)
+    }
+
+    // Runs a fuzzing job, starting with the given PE (if any).
+    #[allow(clippy::too_many_arguments)]
+    async fn run_from(&self, mut pedesc_first: Option<(PEDesc, Duration)>, task_id: u32, image: &TargetImage, ignore_addresses_min: u32, timeout_cycles: u64,
+            input: &[u8], bitmap: &mut [u8]
+    ) -> Result<TapascoHostRun, TapascoRunError> {
+        let mut i_attempt = 0;
+        loop {
+            i_attempt += 1;
+            // Fetch a PE ready for a new task, or wait for one.
+            let (pedesc, wait) = match pedesc_first.take() {
+                Some(pedesc_wait) => pedesc_wait,
+                None => self.acquire_pe().await.ok_or(TapascoRunError::NoPEsLeft {})?
+            };
+            let result = match self.start_run(pedesc, wait, task_id, image, ignore_addresses_min, timeout_cycles, input, bitmap.len() as u32).await {
+                Ok(inflight) => self.finish_run(inflight, bitmap).await,
+                Err(e) => Err(e)
+            };
+            match result {
+                Err(e) if e.is_pe_failure() && i_attempt < self.num_pes => {
+                    eprintln!("Retrying on another PE after a PE failure: {}", e);
+                },
+                result => return result
+            }
+        }
+    }
+
+    ///Runs several fuzzing jobs, keeping as many PEs busy as are idle.
+    /// Waits for a PE only if none of the jobs is in flight.
+    /// The results are retrieved as the jobs complete, and returned in the order of `inputs`.
+    /// Jobs that fail due to a faulty PE are retried as in [`TapascoDispatcher::run`].
+    ///
+    /// * `inputs`, `bitmaps`: Input and bitmap buffer for each job, see [`TapascoDispatcher::run`].
+    pub async fn run_batch(&self, task_id: u32, image: &TargetImage, ignore_addresses_min: u32, timeout_cycles: u64,
+            inputs: &[&[u8]], bitmaps: Vec<&mut [u8]>
+    ) -> Vec<Result<TapascoHostRun, TapascoRunError>> {
+        assert_eq!(inputs.len(), bitmaps.len(), "Need a bitmap buffer for each input");
+        let mut batch = BatchState {
+            results: (0..inputs.len()).map(|_| None).collect(),
+            bitmaps: bitmaps.into_iter().map(Some).collect(),
+            attempts: vec![0; inputs.len()],
+            pending: (0..inputs.len()).collect(),
+        };
+        let mut inflight = FuturesUnordered::new();
+        loop {
+            // Start jobs on all idle PEs.
+            while let Some(&i_input) = batch.pending.front() {
+                let pedesc_opt = if inflight.is_empty() {
+                    self.acquire_pe().await
+                }
+                else {
+                    match self.try_acquire_pe().await {
+                        Some(pedesc) => Some((pedesc, Duration::ZERO)),
+                        None => break
+                    }
+                };
+                let (pedesc, wait) = match pedesc_opt {
+                    Some(pedesc_wait) => pedesc_wait,
+                    None => {
+                        // All PEs are quarantined, fail the remaining jobs.
+                        for i_failed in batch.pending.drain(..) {
+                            batch.results[i_failed] = Some(Err(TapascoRunError::NoPEsLeft {}));
+                        }
+                        break;
+                    }
+                };
+                batch.pending.pop_front();
+                batch.attempts[i_input] += 1;
+                let bitmap = batch.bitmaps[i_input].take().unwrap();
+                match self.start_run(pedesc, wait, task_id, image, ignore_addresses_min, timeout_cycles, inputs[i_input], bitmap.len() as u32).await {
+                    Ok(run) => {
+                        inflight.push(async move {
+                            let result = self.finish_run(run, &mut *bitmap).await;
+                            (i_input, bitmap, result)
+                        });
+                    },
+                    Err(e) => batch.complete(i_input, bitmap, Err(e), self.num_pes)
+                }
+            }
+            // Retrieve the result of the next job to complete, which frees its PE for the next input.
+            match inflight.next().await {
+                Some((i_input, bitmap, result)) => batch.complete(i_input, bitmap, result, self.num_pes),
+                None if batch.pending.is_empty() => break,
+                None => () //Retry the re-queued jobs.
+            }
+        }
+        batch.results.into_iter().map(Option::unwrap).collect()
+    }
+
+    // Fetches a PE ready for a new task, or waits for one. Also returns the time spent waiting.
+    // Returns None if all PEs are quarantined.
+    async fn acquire_pe(&self) -> Option<(PEDesc, Duration)> {
+        let wait_start_time = Instant::now();
+        let mut idlepes_guard = self.idlepe_mutex.lock().await;
+        idlepes_guard = self.idlepe_condvar.wait_until(idlepes_guard,
+            |idlepes| -> bool {!idlepes.is_empty() || self.num_active_pes.load(Ordering::SeqCst) == 0}
+        ).await;
+        let pedesc = idlepes_guard.pop()?;
+        drop(idlepes_guard);
+        Some((pedesc, wait_start_time.elapsed()))
+    }
+
+    // Fetches a PE ready for a new task, if there is one.
+    async fn try_acquire_pe(&self) -> Option<PEDesc> {
+        self.idlepe_mutex.lock().await.pop()
+    }
+
+    // Frees the per-run allocations of a PE and puts it back in the idle list.
+    async fn release_pe(&self, pedesc: PEDesc, devbitmap_addr_opt: Option<DeviceAddress>) {
+        // Free all successful allocations, regardless of whether the overall dispatch succeeded or not.
+        match pedesc.pe.local_memory().as_ref() {
+            Some(localmem) => {
+                let mut allocator = localmem.allocator().lock().unwrap();
+                if let Some(devbitmap_addr) = devbitmap_addr_opt {allocator.free(devbitmap_addr).unwrap();}
+            },
+            None => (), //Don't panic if the PE has no local memory.
+        };
+        // Put the PE back in jobs_idle.
         {
             let mut idlepes_guard = self.idlepe_mutex.lock().await;
-            idlepes_guard = self.idlepe_condvar.wait_until(idlepes_guard,
-                |idlepes| -> bool {!idlepes.is_empty()}
-            ).await;
-            pedesc = idlepes_guard.pop().unwrap();
+            idlepes_guard.push(pedesc);
         }
-        
+        // Notify waiting tasks about the idle PE.
+        //-> Call notify_one while outside the mutex used for waiting
+        //  (is noted as possibly beneficial for C++'s std::condition_variable, not sure if the same applies to this Condvar implementation).
+        self.idlepe_condvar.notify_one();
+    }
+
+    // Counts a failed run of a PE, and puts the PE back in the idle list unless it hung or failed too often in a row.
+    // Such a PE gets one recovery attempt, after which it is quarantined if it fails again.
+    async fn release_failed_pe(&self, mut pedesc: PEDesc, devbitmap_addr_opt: Option<DeviceAddress>, hung: bool) {
+        pedesc.consecutive_errors += 1;
+        pedesc.total_errors += 1;
+        self.num_errors.fetch_add(1, Ordering::Relaxed);
+        if !hung && pedesc.consecutive_errors < self.config.max_pe_errors {
+            // The memory contents may be corrupted, so upload the whole program again before the next run.
+            pedesc.instmem_initialized = false;
+            pedesc.datamem_initialized = false;
+            self.release_pe(pedesc, devbitmap_addr_opt).await;
+            return;
+        }
+        if !pedesc.recovery_attempted {
+            pedesc.recovery_attempted = true;
+            if self.try_recover_pe(&mut pedesc, hung).await {
+                if hung {
+                    eprintln!("PE {}: Hung job completed within the grace period, uploading the program again.", pedesc.index);
+                }
+                else {
+                    eprintln!("PE {}: Uploading the program again after {} failed runs in a row.", pedesc.index, pedesc.consecutive_errors);
+                }
+                pedesc.consecutive_errors = 0;
+                self.release_pe(pedesc, devbitmap_addr_opt).await;
+                return;
+            }
+        }
+        // Take the PE out of service. Its allocations are kept, since a hung PE may still access its memory.
+        let num_active = self.num_active_pes.fetch_sub(1, Ordering::SeqCst) - 1;
+        eprintln!("PE {}: Quarantined after {} failed runs ({}). {} of {} PEs left.",
+            pedesc.index, pedesc.total_errors, if hung {"hung"} else {"repeated errors"}, num_active, self.num_pes);
+        self.quarantine.lock().await.push(pedesc);
+        // Wake all waiting tasks, so they notice if no PE is left.
+        // -> Lock the mutex first, so a task can not miss the notification between its check and its wait.
+        let _idlepes_guard = self.idlepe_mutex.lock().await;
+        self.idlepe_condvar.notify_all();
+    }
+
+    // Tries to bring a failed PE back into a usable state. This is not a hardware reset:
+    // TaPaSCo offers no reset for individual PEs, so a hung job only gets another watchdog period as a grace period to complete.
+    // The program is uploaded again and the caches are invalidated on the next run.
+    async fn try_recover_pe(&self, pedesc: &mut PEDesc, hung: bool) -> bool {
+        if hung {
+            match async_std::future::timeout(self.config.watchdog, self.reactor.wait_readable(interrupt_fd(&pedesc.pe))).await {
+                Ok(Ok(_)) => {
+                    if pedesc.pe.wait_for_completion().is_err() {
+                        return false;
+                    }
+                },
+                _ => return false
+            }
+        }
+        pedesc.instmem_initialized = false;
+        pedesc.datamem_initialized = false;
+        true
+    }
+
+    // Sets up the PE parameters and memory, and starts the job.
+    // On failure, the PE is released again.
+    // The wait for the PE is recorded with the other phases, i.e. only if the job succeeds.
+    #[allow(clippy::too_many_arguments)]
+    async fn start_run(&self, mut pedesc: PEDesc, wait: Duration, task_id: u32, image: &TargetImage, ignore_addresses_min: u32, timeout_cycles: u64,
+            input: &[u8], bitmap_len: u32
+    ) -> Result<InflightRun, TapascoRunError> {
+        let start_time = Instant::now();
         // Since each individual allocation may return an error, use a try-finally equivalent to free all successful allocations.
         // -> The bulk of the dispatch/result retrieval logic is implemented in different functions,
         //    and the result is only passed on to the caller after the 'finally' equivalent code.
@@ -258,7 +719,8 @@ impl TapascoDispatcher {
         
         let disable_bitmap_transfer = pedesc.memconf.bitmap_bram_size == 0;
         
-        let mut result_interm: Result<(), TapascoRunError> = Ok(());
+        let mut latency = LatencyStats { wait: wait, ..LatencyStats::default() };
+        let mut result_interm: Result<(u64, u64), TapascoRunError> = Ok((0, 0));
         if !disable_bitmap_transfer && (bitmap_len < 4 || bitmap_len > pedesc.memconf.bitmap_bram_size) {
             result_interm = Err(TapascoRunError::BitmapLen{s:
                 format!("Bitmap length is {}, but should be in [4, {}]", bitmap_len, pedesc.memconf.bitmap_bram_size)});
@@ -267,49 +729,96 @@ impl TapascoDispatcher {
         let mut devbitmap_addr_opt: Option<DeviceAddress> = None;
         if let Ok(_) = result_interm {
             // Setup the PE parameters and memory, and start the job.
-            result_interm = Self::try_dispatch(task_id, instmem, datamem, input, ignore_addresses_min, 
-                bitmap_len, timeout_cycles,
-                &mut pedesc, &mut devbitmap_addr_opt
+            result_interm = Self::try_dispatch(task_id, image, input, ignore_addresses_min, 
+                bitmap_len, timeout_cycles, self.config.data_snapshot,
+                &mut pedesc, &mut devbitmap_addr_opt, &mut latency
             );
         }
-        if let Ok(_) = result_interm {
-            // Wait for the job to finish.
-            //TODO: Implement an async equivalent for wait_for_completion.
-            result_interm = pedesc.pe.wait_for_completion().context(TapascoPESnafu);
+        match result_interm {
+            Ok((upload_bytes, upload_bytes_full)) => {
+                self.upload_runs.fetch_add(1, Ordering::Relaxed);
+                self.upload_bytes.fetch_add(upload_bytes, Ordering::Relaxed);
+                self.upload_bytes_full.fetch_add(upload_bytes_full, Ordering::Relaxed);
+                let exec_start_time = Instant::now();
+                let completion = self.reactor.wait_readable(interrupt_fd(&pedesc.pe));
+                Ok(InflightRun { pedesc: pedesc, devbitmap_addr_opt: devbitmap_addr_opt, bitmap_len: bitmap_len, start_time: start_time,
+                    latency: latency, exec_start_time: exec_start_time, completion: completion })
+            },
+            Err(e) => {
+                self.add_busy_time(&pedesc, start_time);
+                if e.is_pe_failure() {
+                    self.release_failed_pe(pedesc, devbitmap_addr_opt, false).await;
+                }
+                else {
+                    self.release_pe(pedesc, devbitmap_addr_opt).await;
+                }
+                Err(e)
+            }
         }
+    }
+
+    // Waits for a started job to finish, retrieves its results and releases the PE.
+    async fn finish_run(&self, mut run: InflightRun, bitmap: &mut [u8]) -> Result<TapascoHostRun, TapascoRunError> {
+        // Wait for the completion interrupt without blocking the thread, but at most for the watchdog period.
+        // Afterwards, wait_for_completion acknowledges the interrupt and returns right away.
+        // The execution time ends when the reactor saw the interrupt, not when this run is polled (e.g. after other uploads of a batch).
+        let mut hung = false;
+        let mut result_interm = match async_std::future::timeout(self.config.watchdog, &mut run.completion).await {
+            Ok(wait_result) => wait_result.map_err(TapascoRunError::from),
+            Err(_) => {
+                hung = true;
+                Err(TapascoRunError::PEWatchdog { seconds: self.config.watchdog.as_secs_f64() })
+            }
+        }
+        .and_then(|completion_time| {
+            run.latency.exec = completion_time.saturating_duration_since(run.exec_start_time);
+            run.pedesc.pe.wait_for_completion().context(TapascoPESnafu)
+        });
+        // Release the interrupt fd for the next wait on this PE.
+        drop(run.completion);
         if let Ok(_) = result_interm  {
             // Request data cache invalidation, so a following run does not use stale data.
-            result_interm = Self::set_invalidate_flags(&mut pedesc, PE_INVFLAG_DCACHE);
+            let invalidate_start_time = Instant::now();
+            result_interm = Self::set_invalidate_flags(&mut run.pedesc, PE_INVFLAG_DCACHE);
+            run.latency.invalidate += invalidate_start_time.elapsed();
         }
         // Retrieve the execution results, unless a previous step has failed.
         let result_final;
         match result_interm {
             Ok(_) => {
-                result_final = Self::try_get_results(&mut pedesc, devbitmap_addr_opt, bitmap_mem, bitmap_len);
+                let readback_start_time = Instant::now();
+                result_final = Self::try_get_results(&mut run.pedesc, run.devbitmap_addr_opt, bitmap, run.bitmap_len);
+                run.latency.readback = readback_start_time.elapsed();
             },
             Err(e) => result_final = Err(e),
         };
-        // Free all successful allocations, regardless of whether the overall dispatch succeeded or not.
-        match pedesc.pe.local_memory().as_ref() {
-            Some(localmem) => {
-                let mut allocator = localmem.allocator().lock().unwrap();
-                if let Some(devbitmap_addr) = devbitmap_addr_opt {allocator.free(devbitmap_addr).unwrap();}
-            },
-            None => (), //Don't panic if the PE has no local memory.
-        };
-        // Put the PE back in jobs_idle.
-        {
-            let mut idlepes_guard = self.idlepe_mutex.lock().await;
-            idlepes_guard.push(pedesc);
+        self.add_busy_time(&run.pedesc, run.start_time);
+        if let Ok(host_run) = &result_final {
+            // A crashing or hanging target may have written anywhere, so restore the whole data section on the next run (see data_snapshot).
+            if (host_run.status & (TapascoRunStatusFlags::PROGRAM_CRASH_FLAG | TapascoRunStatusFlags::TIMEOUT_FLAG)) != 0 {
+                run.pedesc.datamem_initialized = false;
+            }
+        }
+        if result_final.is_ok() {
+            run.latency.runs = 1;
+            self.pe_latency[run.pedesc.slot].lock().unwrap().add(&run.latency);
+            THREAD_LATENCY.with(|latency| latency.borrow_mut().add(&run.latency));
+        }
+        match &result_final {
+            Err(e) if e.is_pe_failure() => self.release_failed_pe(run.pedesc, run.devbitmap_addr_opt, hung).await,
+            _ => {
+                run.pedesc.consecutive_errors = 0;
+                self.release_pe(run.pedesc, run.devbitmap_addr_opt).await;
+            }
         }
-        // Notify waiting tasks about the idle PE.
-        //-> Call notify_one while outside the mutex used for waiting
-        //  (is noted as possibly beneficial for C++'s std::condition_variable, not sure if the same applies to this Condvar implementation).
-        self.idlepe_condvar.notify_one();
 
         result_final
     }
 
+    fn add_busy_time(&self, pedesc: &PEDesc, start_time: Instant) {
+        self.pe_busy_ns[pedesc.slot].fetch_add(start_time.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    }
+
     fn set_invalidate_flags(pedesc: &PEDesc, flags: u32) -> Result<(), TapascoRunError> {
         if pedesc.memconf.invalidate_caches {
             let flags_prev = match pedesc.pe.read_arg(9, 4).context(TapascoPESnafu)? {
@@ -322,25 +831,29 @@ impl TapascoDispatcher {
     }
 
     fn try_dispatch(
-        task_id: u32, instmem: &[u8], datamem: &[u8], input: &[u8], ignore_addresses_min: u32,
-        bitmap_len: u32, timeout_cycles: u64,
+        task_id: u32, image: &TargetImage, input: &[u8], ignore_addresses_min: u32,
+        bitmap_len: u32, timeout_cycles: u64, data_snapshot: bool,
         pedesc: &mut PEDesc,
-        devbitmap_addr_opt: &mut Option<DeviceAddress>
-    ) -> Result<(),TapascoRunError> {
+        devbitmap_addr_opt: &mut Option<DeviceAddress>,
+        latency: &mut LatencyStats
+    ) -> Result<(u64, u64),TapascoRunError> {
+        let (instmem, datamem) = image.sections(&pedesc.memconf);
         // Check whether there is enough room for the input data.
         let datamem_len_aligned = (datamem.len() + 15) & !15; //16 byte aligned length.
         let input_len_aligned = (input.len() + 15) & !15;
         if datamem_len_aligned > pedesc.memconf.datamem_size_phys_without_stack {
             return Err(TapascoRunError::ProgramOrDataTooLarge {});
         }
+        if pedesc.loaded_image != Some(image.content_hash) {
+            // Another program was run on the PE before (e.g. by another client of a shared PE), upload the whole image again.
+            pedesc.instmem_initialized = false;
+            pedesc.datamem_initialized = false;
+            pedesc.loaded_image = Some(image.content_hash);
+        }
         if !pedesc.instmem_initialized {
-            // -> Estimate the length of the used instruction memory.
-            //    (Assumption: all suffix zeroes in instmem are unused)
+            // Check the program sections against the PE memory layout.
             // Only run this once to save host cycles.
-            let instmem_len_nonzero = instmem.iter().rposition(|&v| v != 0).map(|rpos| rpos + 1).unwrap_or(0);
-            if instmem_len_nonzero > pedesc.memconf.instmem_size_phys {
-                return Err(TapascoRunError::ProgramOrDataTooLarge {});
-            }
+            image.check_memconfig(&pedesc.memconf)?;
         }
         if input_len_aligned > pedesc.memconf.datamem_size_phys_without_stack - datamem_len_aligned {
             return Err(TapascoRunError::InputTooLarge {});
@@ -365,13 +878,43 @@ impl TapascoDispatcher {
         let mut datamem_input_composed = vec![0; pedesc.memconf.datamem_size_phys_without_stack - input_data_offs];
         datamem_input_composed[0..input.len()].copy_from_slice(input);
 
+        // Data section ranges to upload: All of it, unless the PE memory still holds the read-only parts from a previous run.
+        // The PEs have no memory protection, so the read-only parts are read back periodically to catch stray writes by the target
+        //  (a crash or timeout already causes a full upload, see finish_run).
+        let mut full_upload = !(data_snapshot && pedesc.datamem_initialized && !image.is_flat());
+        if !full_upload {
+            pedesc.snapshot_runs += 1;
+            if pedesc.snapshot_runs >= DATA_SNAPSHOT_VERIFY_INTERVAL {
+                pedesc.snapshot_runs = 0;
+                if !Self::verify_readonly_data(pedesc, &datamem_composed, &image.datamem_writable)? {
+                    eprintln!("PE {}: Read-only data was modified by a run, uploading the full data section again.", pedesc.index);
+                    full_upload = true;
+                }
+            }
+        }
+        if full_upload {
+            pedesc.snapshot_runs = 0;
+        }
+        let datamem_full_range = [(0, datamem_composed.len())];
+        let datamem_ranges: &[(usize, usize)] = if full_upload {
+            &datamem_full_range[..]
+        }
+        else {
+            &image.datamem_writable[..]
+        };
+        let upload_bytes = datamem_ranges.iter().map(|(begin, end)| end - begin).sum::<usize>() + datamem_input_composed.len();
+        let upload_bytes_full = datamem_composed.len() + datamem_input_composed.len();
+
         // Invalidate data cache for the PE and instruction cache (if needed).
         // Note: If data cache has already been invalidated after the last run, the PE should notice and ignore the request (flag may stick to the next run).
+        let invalidate_start_time = Instant::now();
         Self::set_invalidate_flags(pedesc, PE_INVFLAG_DCACHE | (
                 if pedesc.instmem_initialized {0}
                 else {PE_INVFLAG_ICACHE | PE_INVFLAG_BP}
             )
         )?;
+        latency.invalidate += invalidate_start_time.elapsed();
+        let upload_start_time = Instant::now();
         let instmem_len_copy = std::cmp::min(instmem.len(), pedesc.memconf.instmem_size_phys);
         if let Some(dram) = pedesc.dram.as_deref() {
             // Program memory in DRAM
@@ -381,7 +924,13 @@ impl TapascoDispatcher {
                 pedesc.instmem_initialized = true;
             }
             // Upload the data section.
-            dram.dma().copy_to(&datamem_composed[..], pedesc.dram_allocation.unwrap() + pedesc.memconf.datamem_offs_phys as DeviceSize).context(TapascoDMASnafu)?;
+            for &(begin, end) in datamem_ranges {
+                dram.dma().copy_to(
+                    &datamem_composed[begin..end],
+                    pedesc.dram_allocation.unwrap() + (pedesc.memconf.datamem_offs_phys as usize + begin) as DeviceSize
+                ).context(TapascoDMASnafu)?;
+            }
+            pedesc.datamem_initialized = true;
             // Upload the input data.
             dram.dma().copy_to(
                 &datamem_input_composed[..],
@@ -396,13 +945,20 @@ impl TapascoDispatcher {
                 pedesc.instmem_initialized = true;
             }
             // Upload the data section.
-            localmem.dma().copy_to(&datamem_composed[..], pedesc.memconf.datamem_offs_phys as DeviceAddress).context(TapascoDMASnafu)?;
+            for &(begin, end) in datamem_ranges {
+                localmem.dma().copy_to(
+                    &datamem_composed[begin..end],
+                    (pedesc.memconf.datamem_offs_phys as usize + begin) as DeviceAddress
+                ).context(TapascoDMASnafu)?;
+            }
+            pedesc.datamem_initialized = true;
             // Upload the input data.
             localmem.dma().copy_to(
                 &datamem_input_composed[..],
                 (pedesc.memconf.datamem_offs_phys as usize + input_data_offs) as DeviceAddress
             ).context(TapascoDMASnafu)?;
         }
+        latency.upload = upload_start_time.elapsed();
         
         //Arg 0: Job ID
         pedesc.pe.set_arg(0, PEParameter::Single32(task_id)).context(TapascoPESnafu)?;
@@ -418,7 +974,7 @@ impl TapascoDispatcher {
             pedesc.pe.set_arg(5, PEParameter::Single64(timeout_cycles)).context(TapascoPESnafu)?; 
             if let Some(_) = pedesc.dram.as_deref() {
                 //Arg 8: DRAM region index.
-                pedesc.pe.set_arg(8, PEParameter::Single32(((pedesc.dram_allocation.unwrap() & DRAM_REGION_MASK) >> DRAM_REGION_SHIFT) as u32)).context(TapascoPESnafu)?; 
+                pedesc.pe.set_arg(8, PEParameter::Single32(((pedesc.dram_allocation.unwrap() & pedesc.memconf.dram_region_mask()) >> pedesc.memconf.dram_region_shift) as u32)).context(TapascoPESnafu)?; 
             }
             //Arg 9: Min address to skip
             pedesc.pe.set_arg(10, PEParameter::Single32(ignore_addresses_min));
@@ -432,13 +988,47 @@ impl TapascoDispatcher {
         //Start the task.
         pedesc.pe.start().context(TapascoPESnafu)?;
         
-        Ok(())
+        Ok((upload_bytes as u64, upload_bytes_full as u64))
+    }
+
+    // Reads back the data memory outside of the writable ranges, and returns whether it still matches the uploaded data.
+    fn verify_readonly_data(pedesc: &PEDesc, datamem_composed: &[u8], datamem_writable: &[(usize, usize)]) -> Result<bool, TapascoRunError> {
+        let mut readonly_ranges = Vec::new();
+        let mut begin = 0;
+        for &(writable_begin, writable_end) in datamem_writable {
+            if writable_begin > begin {
+                readonly_ranges.push((begin, writable_begin));
+            }
+            begin = writable_end;
+        }
+        if datamem_composed.len() > begin {
+            readonly_ranges.push((begin, datamem_composed.len()));
+        }
+        for (begin, end) in readonly_ranges {
+            let mut device_data = vec![0u8; end - begin];
+            if let Some(dram) = pedesc.dram.as_deref() {
+                dram.dma().copy_from(
+                    pedesc.dram_allocation.unwrap() + (pedesc.memconf.datamem_offs_phys as usize + begin) as DeviceSize,
+                    &mut device_data[..]
+                ).context(TapascoDMASnafu)?;
+            }
+            else {
+                let localmem = pedesc.pe.local_memory().as_ref()
+                    .ok_or(tapasco::job::Error::NoLocalMemory {  }).context(TapascoJobSnafu)?;
+                localmem.dma().copy_from((pedesc.memconf.datamem_offs_phys as usize + begin) as DeviceAddress, &mut device_data[..])
+                    .context(TapascoDMASnafu)?;
+            }
+            if device_data[..] != datamem_composed[begin..end] {
+                return Ok(false);
+            }
+        }
+        Ok(true)
     }
 
     fn try_get_results(
         pedesc: &mut PEDesc,
         devbitmap_addr: Option<DeviceAddress>,
-        bitmap_mem: Arc<std::sync::Mutex<Box<[u8]>>>,
+        bitmap: &mut [u8],
         bitmap_len: u32
     ) -> Result<TapascoHostRun, TapascoRunError> {
         //Return value bits:
@@ -452,16 +1042,12 @@ impl TapascoDispatcher {
             PEParameter::Single64(val) => val,
             _ => panic!("Unexpected PE::read_arg result type."),
         };
-        //Create a duration from counter with a cycle time of 1ns (i.e. 1GHz), regardless of actual clock speed.
-        let duration = Some(Duration::new(counter >> 32, (counter & 0xFFFFFFFF) as u32));
+        let duration = Some(TapascoHostRun::duration_from_cycles(counter));
         
         {
             //Copy the bitmap from the PE local memory.
             // LibAFL also expects a bitmap in case of a program crash.
-            let lock_res = (*bitmap_mem).try_lock();
-            assert!(match lock_res {Ok(_) => {true} Err(_) => {false}},
-                "Unable to lock mutex that should have been unlocked: {}", lock_res.unwrap_err());
-            let bitmap_slice = &mut **lock_res.unwrap();
+            let bitmap_slice = bitmap;
             if pedesc.is_regular_tapascoriscv {
                 //Regular tapasco-riscv has no bitmap hardware.
                 //Fill with zero (-> LibAFL strategy will detect input as dead end)
@@ -471,8 +1057,7 @@ impl TapascoDispatcher {
                 let localmem = pedesc.pe.local_memory().as_ref()
                     .ok_or(tapasco::job::Error::NoLocalMemory {  }).context(TapascoJobSnafu)?;
                 if bitmap_slice.len() != bitmap_len as usize {
-                    //Someone else may have locked and changed the slice length in the meantime.
-                    return Err(TapascoRunError::BitmapLen { s: "Bitmap slice length changed unexpectedly".to_string() });
+                    return Err(TapascoRunError::BitmapLen { s: "Bitmap slice length does not match the dispatched job".to_string() });
                 }
                 if pedesc.memconf.bitmap_via_dram_dma {
                     let dram = pedesc.dram.as_deref().unwrap();
@@ -485,97 +1070,276 @@ impl TapascoDispatcher {
         }
         
         if (retval & TapascoRunStatusFlags::PROGRAM_CRASH_FLAG) != 0 {
-            let _ecause = (retval & TapascoRunStatusFlags::PROGRAM_CRASH_CAUSE_MASK) >> TapascoRunStatusFlags::PROGRAM_CRASH_CAUSE_SHIFT;
+            let ecause = (retval & TapascoRunStatusFlags::PROGRAM_CRASH_CAUSE_MASK) >> TapascoRunStatusFlags::PROGRAM_CRASH_CAUSE_SHIFT;
             //Arg 4: exception epc (low 32bits), tval (high 32bits).
-            let (_tval, _epc) = match pedesc.pe.read_arg(4, 8).context(TapascoPESnafu)? {
+            let (tval, epc) = match pedesc.pe.read_arg(4, 8).context(TapascoPESnafu)? {
                 PEParameter::Single64(arg4_hi_lo) => ((arg4_hi_lo >> 32) as u32, (arg4_hi_lo & 0xFFFFFFFF) as u32),
                 _ => panic!("Unexpected PE::read_arg result type."),
             };
-            //Output the exception information to console.
-            // -> TODO: Save it alongside the corresponding crash input somehow?
-            println!("Exception: Cause {}, epc 0x{:08x}, tval 0x{:08x}", _ecause, _epc, _tval);
             return Ok(TapascoHostRun {
-                status: TapascoRunStatusFlags::PROGRAM_CRASH_FLAG,
-                bitmap: bitmap_mem,
-                duration: duration
+                status: retval & (TapascoRunStatusFlags::PROGRAM_CRASH_FLAG | TapascoRunStatusFlags::PROGRAM_CRASH_CAUSE_MASK),
+                duration: duration,
+                exception: Some(TapascoRunException { cause: ecause, epc: epc, tval: tval })
             });
         }
         else if ((retval >> 6) & 1) == 1 {
             return Err(TapascoRunError::BitmapLen { s: "PE reported an unsupported bitmap length".to_string() });
         }
         else if (retval & !(1 << 7)) == 0 {
-            if (retval & (1 << 7)) != 0 {println!("Timeout");}
+            if (retval & (1 << 7)) != 0 {eprintln!("Timeout");}
             // Program completed successfully, or a timeout occured.
             return Ok(TapascoHostRun {
                 status: retval, //Success / Timeout
-                bitmap: bitmap_mem,
-                duration: duration
+                duration: duration,
+                exception: None
             });
         }
 
         Err(TapascoRunError::Unknown { s: format!("Unexpected PE return value: 0x{:08x}", retval) })
     }
 }
-//Acquires the PEs of the device matching the given name, and calls the provided callback on each of them.
-fn acquire_pes<Cb>(dev: &mut Device, pe_name: &str, mut process_pe: Cb) -> Result<(), tapasco::device::Error>
+//Returns the eventfd that TLKM signals on a completion interrupt of the PE.
+fn interrupt_fd(pe: &PE) -> RawFd {
+    pe.interrupt().as_raw_fd()
+}
+//Acquires up to `max_count` (or all) PEs of the device matching the given name, and calls the provided callback on each of them.
+//Returns the number of acquired PEs. A failure after the first PE ends the acquisition (e.g. PEs taken by another process),
+// and is returned along with the number of PEs acquired before.
+fn acquire_pes<Cb>(dev: &mut Device, pe_name: &str, max_count: Option<usize>, mut process_pe: Cb)
+    -> Result<(usize, Option<tapasco::device::Error>), tapasco::device::Error>
 where Cb: FnMut(PE)
 {
     // Retrieve the ID of the fuzzer PE in the current device.
     let pe_id = dev.get_pe_id(pe_name)?;
-    for _i in 0..dev.num_pes(pe_id) {
+    let num_pes = max_count.map_or(dev.num_pes(pe_id), |max_count| std::cmp::min(max_count, dev.num_pes(pe_id)));
+    for i in 0..num_pes {
         // Retrieve the PE object.
         // Need access to the PE itself (esp. for read_arg).
-        let pe = dev.acquire_pe_without_job(pe_id)?;
+        let pe = match dev.acquire_pe_without_job(pe_id) {
+            Ok(pe) => pe,
+            Err(e) if i == 0 => return Err(e),
+            Err(e) => return Ok((i, Some(e)))
+        };
         process_pe(pe);
     }
-    Ok(())
+    Ok((num_pes, None))
+}
+///Selection of the devices and PEs to acquire.
+#[derive(Clone, Debug, Default)]
+pub struct PESelection {
+    ///TLKM device IDs to use, or all devices if empty.
+    pub devices: Vec<DeviceId>,
+    ///PE types (full VLNV or its name, e.g. cva5_bram_pe_fuzzer), each with an optional maximum count over all selected devices.
+    /// All PE types with a profile are used if empty.
+    pub pes: Vec<(String, Option<usize>)>,
+    ///Open the devices with shared instead of exclusive access, so other processes can acquire the remaining PEs.
+    pub shared_access: bool,
 }
-//Returns a descriptor object for all supported PEs in the given devices.
-pub fn get_pes(devices: &mut [Device]) -> Result<Vec<PEDesc>, TapascoRunError> {
+impl PESelection {
+    ///Parses a PE type entry in the format `<type>[:<count>]`.
+    pub fn parse_pes_entry(s: &str) -> Result<(String, Option<usize>), String> {
+        //The VLNV itself contains ':', so only treat a numeric last component as the count.
+        match s.rsplit_once(':') {
+            Some((name, count)) if !count.is_empty() && count.chars().all(|c| c.is_ascii_digit()) => {
+                let count = count.parse::<usize>().map_err(|e| e.to_string())?;
+                if count == 0 {
+                    return Err("PE count must be at least 1".to_string());
+                }
+                Ok((name.to_string(), Some(count)))
+            },
+            _ => Ok((s.to_string(), None))
+        }
+    }
+
+    //Returns whether the given PE type name refers to the VLNV.
+    pub(crate) fn matches(name: &str, vlnv: &str) -> bool {
+        name == vlnv || vlnv.split(':').nth(2) == Some(name)
+    }
+
+    //Returns the PEs to acquire for a profile: None if the type is not selected, otherwise the optional maximum count.
+    fn limit(&self, vlnv: &str) -> Option<Option<usize>> {
+        if self.pes.is_empty() {
+            return Some(None);
+        }
+        self.pes.iter().find(|(name, _)| Self::matches(name, vlnv)).map(|(_, count)| *count)
+    }
+}
+//Returns a descriptor object for the selected PEs in the given devices that match one of the profiles.
+//Failures are collected per device, and are returned if no PE was found (printed as a warning otherwise).
+pub fn get_pes(devices: &mut [Device], profiles: &[PEProfile], selection: &PESelection) -> Result<Vec<PEDesc>, TapascoRunError> {
+    for (name, _) in &selection.pes {
+        if !profiles.iter().any(|profile| PESelection::matches(name, profile.vlnv.as_str())) {
+            return Err(TapascoRunError::Config { s: format!("No PE profile for PE type '{}'", name) });
+        }
+    }
+    let mut device_errors: Vec<String> = Vec::new();
+    for device_id in &selection.devices {
+        if !devices.iter().any(|dev| dev.id() == *device_id) {
+            device_errors.push(format!("Device {}: Not found", device_id));
+        }
+    }
+    //Remaining number of PEs to acquire per profile (see PESelection::limit).
+    let mut remaining: Vec<Option<Option<usize>>> = profiles.iter().map(|profile| selection.limit(profile.vlnv.as_str())).collect();
     let mut pes: Vec<PEDesc> = Vec::new();
-    let mut last_err : Option<tapasco::device::Error> = None;
     for dev in devices {
-        dev.change_access(tapasco::tlkm::tlkm_access::TlkmAccessExclusive)
-            .context(TapascoDeviceSnafu {})?;
-        let dram = dev.default_memory().context(TapascoDeviceSnafu)?;
-        //Acquire PEs of all supported types:
-        //Regular DRAM
-        let mut cur_result = acquire_pes(dev, "esa.informatik.tu-darmstadt.de:fuzzer:cva5_pe_fuzzer:1.0",
-            |pe: PE| {pes.push(PEDesc::new(pe, PEMEMCONFIG_DRAM, Some(dram.clone())));}
-        );
-        //BRAM
-        cur_result = cur_result.or(acquire_pes(dev, "esa.informatik.tu-darmstadt.de:fuzzer:cva5_bram_pe_fuzzer:1.0",
-            |pe: PE| {pes.push(PEDesc::new(pe, PEMEMCONFIG_BRAM, None));}
-            //|pe: PE| {pes.push(PEDesc::new(pe, PEMEMCONFIG_BRAM_DMA, Some(dram.clone())));}
-        ));
-        //HBM (programming via 'PE local memory' interface)
-        cur_result = cur_result.or(acquire_pes(dev, "esa.informatik.tu-darmstadt.de:fuzzer:cva5_hbm_pe_fuzzer:1.0",
-            |pe: PE| {pes.push(PEDesc::new(pe, PEMEMCONFIG_HBM, None));}
-        ));
-        //tapasco-riscv BRAM (for evaluation purposes)
-        cur_result = cur_result.or(acquire_pes(dev, "esa.informatik.tu-darmstadt.de:tapasco:cva5_pe:1.0",
-            |pe: PE| {pes.push(PEDesc::new_tapascoriscv(pe, PEMEMCONFIG_BRAM_TAPASCORISCV));}
-        ));
-        if let Err(e) = cur_result {
-            last_err = Some(e); // Don't immediately return an error in case there are several devices.
+        let device_id = dev.id();
+        if !selection.devices.is_empty() && !selection.devices.contains(&device_id) {
+            continue;
+        }
+        let access = if selection.shared_access {
+            tapasco::tlkm::tlkm_access::TlkmAccessShared
+        }
+        else {
+            tapasco::tlkm::tlkm_access::TlkmAccessExclusive
+        };
+        if let Err(e) = dev.change_access(access) {
+            device_errors.push(format!("Device {}: Failed to change the access mode: {}", device_id, e));
+            continue;
+        }
+        let dram = match dev.default_memory() {
+            Ok(dram) => dram,
+            Err(e) => {
+                device_errors.push(format!("Device {}: No default memory: {}", device_id, e));
+                continue;
+            }
+        };
+        //Acquire PEs of all selected types.
+        //Without an explicit selection, missing PE types are only reported if no PEs were found on the device.
+        let mut num_device_pes = 0;
+        let mut type_errors: Vec<String> = Vec::new();
+        for (profile, remaining_count) in profiles.iter().zip(remaining.iter_mut()) {
+            let max_count = match remaining_count {
+                None => continue, //Not selected
+                Some(Some(0)) => continue, //Enough PEs of this type.
+                Some(max_count) => *max_count
+            };
+            let profile_result = acquire_pes(dev, profile.vlnv.as_str(), max_count,
+                |pe: PE| {
+                    let mut pedesc = match (profile.tapascoriscv, profile.memory) {
+                        (true, _) => PEDesc::new_tapascoriscv(pe, profile.memconf),
+                        (false, PEMemoryKind::Dram) => PEDesc::new(pe, profile.memconf, Some(dram.clone())),
+                        (false, PEMemoryKind::Local) => PEDesc::new(pe, profile.memconf, None)
+                    };
+                    pedesc.index = pes.len();
+                    pedesc.device_id = device_id;
+                    pedesc.vlnv = profile.vlnv.clone();
+                    pes.push(pedesc);
+                }
+            );
+            match profile_result {
+                Ok((num_acquired, partial_error)) => {
+                    num_device_pes += num_acquired;
+                    if let Some(Some(count)) = remaining_count {
+                        *count -= num_acquired;
+                    }
+                    if let Some(e) = partial_error {
+                        device_errors.push(format!("Device {}: Stopped acquiring PEs of type {} after {}: {}",
+                            device_id, profile.vlnv, num_acquired, e));
+                    }
+                },
+                Err(e) => type_errors.push(format!("{}: {}", profile.vlnv, e))
+            }
+        }
+        if !type_errors.is_empty() && (num_device_pes == 0 || !selection.pes.is_empty()) {
+            device_errors.push(format!("Device {}: {}", device_id, type_errors.join("; ")));
+        }
+    }
+    for (profile, remaining_count) in profiles.iter().zip(&remaining) {
+        if let (Some(Some(count_missing)), Some(Some(count))) = (remaining_count, selection.limit(profile.vlnv.as_str())) {
+            if *count_missing > 0 {
+                device_errors.push(format!("Only acquired {} of {} PEs of type {}", count - count_missing, count, profile.vlnv));
+            }
         }
     }
     if pes.is_empty() {
-        return Err(TapascoRunError::TapascoPENotFound { last: last_err });
+        return Err(TapascoRunError::TapascoPENotFound { details: device_errors.join("\n") });
+    }
+    for device_error in &device_errors {
+        eprintln!("Warning: {}", device_error);
     }
     Ok(pes)
 }
-//Splits the Jobs (PE wrappers) across new TapascoDispatchers.
-//For now: Creates a single TapascoDispatchers with all Jobs.
-pub fn create_dispatchers(pes: Vec<PEDesc>) -> Result<Vec<Arc<TapascoDispatcher>>, TapascoRunError> {
-    Ok(vec![TapascoDispatcher::new(pes)?])
+///Policy to split the PEs across TapascoDispatchers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DispatcherPolicy {
+    ///A single dispatcher with all PEs.
+    Shared,
+    ///One dispatcher per fuzzing thread, with the PEs distributed evenly.
+    Thread,
+    ///One dispatcher per FPGA device.
+    Device,
+    ///One dispatcher per PE type (VLNV).
+    PEType,
+}
+impl DispatcherPolicy {
+    ///Names of the policies, as accepted by [`DispatcherPolicy::from_name`].
+    pub const NAMES: [&'static str; 4] = ["shared", "thread", "device", "petype"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "shared" => Some(Self::Shared),
+            "thread" => Some(Self::Thread),
+            "device" => Some(Self::Device),
+            "petype" => Some(Self::PEType),
+            _ => None
+        }
+    }
+}
+//Splits the Jobs (PE wrappers) across new TapascoDispatchers according to the policy.
+// * `num_threads`: Number of fuzzing threads, for DispatcherPolicy::Thread.
+pub fn create_dispatchers(pes: Vec<PEDesc>, config: DispatcherConfig, policy: DispatcherPolicy, num_threads: usize)
+    -> Result<Vec<Arc<TapascoDispatcher>>, TapascoRunError> {
+    let mut groups: Vec<Vec<PEDesc>> = Vec::new();
+    match policy {
+        DispatcherPolicy::Shared => groups.push(pes),
+        DispatcherPolicy::Thread => {
+            let num_groups = std::cmp::max(std::cmp::min(num_threads, pes.len()), 1);
+            groups.resize_with(num_groups, Vec::new);
+            for (i, pedesc) in pes.into_iter().enumerate() {
+                groups[i % num_groups].push(pedesc);
+            }
+        },
+        DispatcherPolicy::Device | DispatcherPolicy::PEType => {
+            //Group by key, keeping the order of first occurrence.
+            let mut keys: Vec<String> = Vec::new();
+            for pedesc in pes {
+                let key = match policy {
+                    DispatcherPolicy::Device => pedesc.device_id.to_string(),
+                    _ => pedesc.vlnv.clone()
+                };
+                match keys.iter().position(|existing| *existing == key) {
+                    Some(i) => groups[i].push(pedesc),
+                    None => {
+                        keys.push(key);
+                        groups.push(vec![pedesc]);
+                    }
+                }
+            }
+        }
+    }
+    groups.into_iter().filter(|group| !group.is_empty())
+        .map(|group| TapascoDispatcher::new(group, config))
+        .collect()
+}
+///Orders the dispatchers for a fuzzing thread: Its own dispatcher comes first,
+/// followed by the others to steal idle PEs from (unless `work_stealing` is false).
+pub fn thread_dispatchers(dispatchers: &[Arc<TapascoDispatcher>], i_thread: usize, work_stealing: bool) -> Vec<Arc<TapascoDispatcher>> {
+    if dispatchers.is_empty() {
+        return Vec::new();
+    }
+    let i_own = i_thread % dispatchers.len();
+    if !work_stealing {
+        return vec![dispatchers[i_own].clone()];
+    }
+    dispatchers[i_own..].iter().chain(dispatchers[..i_own].iter()).cloned().collect()
 }
 
 /// The [`TapascoRunnerPhysical`] manages the execution on TaPaSCo fuzzer PEs and retrieves the results.
-/// Assumption: All users of the given TapascoDispatchers use the same program instruction memory.
+/// The users of the given TapascoDispatchers may run different programs, but each change of the program on a PE requires a full upload.
+/// Jobs go to an idle PE of the first dispatcher, else to an idle PE of any other dispatcher (work stealing).
+/// If all PEs are busy, the job waits for a PE of the first dispatcher that has not lost all of its PEs.
 pub struct TapascoRunnerPhysical<'a> {
-    instmem: Box<[u8]>,
-    datamem: Box<[u8]>,
+    image: Arc<TargetImage>,
     ignore_addresses_min: u32,
     dispatchers: Vec<Arc<TapascoDispatcher>>,
     timeout_cycles: u64,
@@ -585,20 +1349,18 @@ pub struct TapascoRunnerPhysical<'a> {
 impl<'a> TapascoRunnerPhysical<'a> {
     /// Create a new [`TapascoRunnerPhysical`]
     pub fn new(
-        target: String,
+        image: Arc<TargetImage>,
         ignore_addresses_min: u32,
         dispatchers: Vec<Arc<TapascoDispatcher>>,
         timeout_cycles: u64,
         stop_req: &'a AtomicBool
     ) -> Result<Self, libafl::Error> {
-        let (instmem, datamem) = read_binary(target.as_str())?;
         if dispatchers.is_empty() {
             return Err(libafl::Error::Unknown("TapascoRunnerPhysical: No dispatchers provided!".to_string()));
         }
 
         Ok(Self {
-            instmem: instmem,
-            datamem: datamem,
+            image: image,
             ignore_addresses_min: ignore_addresses_min,
             dispatchers: dispatchers,
             timeout_cycles: timeout_cycles,
@@ -609,19 +1371,73 @@ impl<'a> TapascoRunnerPhysical<'a> {
 
 #[async_trait(?Send)]
 impl<'a> TapascoRunner for TapascoRunnerPhysical<'a> {
-    async fn run(&mut self, input: &[u8], bitmap_mem: Arc<std::sync::Mutex<Box<[u8]>>>) -> Result<TapascoHostRun,TapascoRunError> {
+    async fn run(&mut self, input: &[u8], bitmap: &mut [u8]) -> Result<TapascoHostRun,TapascoRunError> {
         if self.stop_req.load(std::sync::atomic::Ordering::Relaxed) == true {
             return Err(TapascoRunError::LibAFL { source: libafl::Error::ShuttingDown });
         }
-        //For now: Always give the task to the first dispatcher.
-        let res = self.dispatchers.first().unwrap().run(0,
-            &*self.instmem, &*self.datamem, self.ignore_addresses_min,
-            self.timeout_cycles,
-            input, bitmap_mem
-        ).await;
+        let mut res_opt = None;
+        for dispatcher in &self.dispatchers {
+            match dispatcher.try_run(0, &*self.image, self.ignore_addresses_min, self.timeout_cycles, input, bitmap).await {
+                None | Some(Err(TapascoRunError::NoPEsLeft {})) => (),
+                Some(dispatcher_res) => {
+                    res_opt = Some(dispatcher_res);
+                    break;
+                }
+            }
+        }
+        let res = match res_opt {
+            Some(dispatcher_res) => dispatcher_res,
+            None => {
+                //No PE is idle, wait for one.
+                let mut wait_res = Err(TapascoRunError::NoPEsLeft {});
+                for dispatcher in &self.dispatchers {
+                    wait_res = dispatcher.run(0, &*self.image, self.ignore_addresses_min, self.timeout_cycles, input, bitmap).await;
+                    if !matches!(wait_res, Err(TapascoRunError::NoPEsLeft {})) {
+                        break;
+                    }
+                }
+                wait_res
+            }
+        };
         if self.stop_req.load(std::sync::atomic::Ordering::Relaxed) == true {
             return Err(TapascoRunError::LibAFL { source: libafl::Error::ShuttingDown });
         }
         res
     }
+
+    async fn run_batch(&mut self, inputs: &[&[u8]], bitmaps: Vec<&mut [u8]>) -> Vec<Result<TapascoHostRun,TapascoRunError>> {
+        if self.stop_req.load(std::sync::atomic::Ordering::Relaxed) == true {
+            return inputs.iter().map(|_| Err(TapascoRunError::LibAFL { source: libafl::Error::ShuttingDown })).collect();
+        }
+        //Hand each dispatcher as many inputs as it has idle PEs, and queue the rest on the first dispatcher with PEs left.
+        let mut counts = vec![0usize; self.dispatchers.len()];
+        let mut num_remaining = inputs.len();
+        for (count, dispatcher) in counts.iter_mut().zip(&self.dispatchers) {
+            *count = std::cmp::min(dispatcher.num_idle_pes().await, num_remaining);
+            num_remaining -= *count;
+        }
+        let i_wait = self.dispatchers.iter().position(|dispatcher| dispatcher.pe_health().active > 0).unwrap_or(0);
+        counts[i_wait] += num_remaining;
+        //Run the sub-batches concurrently, in input order.
+        let mut bitmaps_iter = bitmaps.into_iter();
+        let mut sub_batches = Vec::new();
+        let mut i_first = 0;
+        for (&count, dispatcher) in counts.iter().zip(&self.dispatchers) {
+            if count == 0 {
+                continue;
+            }
+            let sub_bitmaps: Vec<&mut [u8]> = bitmaps_iter.by_ref().take(count).collect();
+            sub_batches.push(dispatcher.run_batch(0,
+                &*self.image, self.ignore_addresses_min,
+                self.timeout_cycles,
+                &inputs[i_first..i_first + count], sub_bitmaps
+            ));
+            i_first += count;
+        }
+        let res: Vec<_> = futures::future::join_all(sub_batches).await.into_iter().flatten().collect();
+        if self.stop_req.load(std::sync::atomic::Ordering::Relaxed) == true {
+            return inputs.iter().map(|_| Err(TapascoRunError::LibAFL { source: libafl::Error::ShuttingDown })).collect();
+        }
+        res
+    }
 }
@@ -0,0 +1,245 @@
+use std::io::{BufReader, Read, Write};
+use std::net::TcpStream;
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::tapasco_host::{TapascoHostRun, TapascoRunException, TapascoRunner, TapascoRunError};
+
+//Protocol between TapascoRunnerRemote and the serve mode (all integers little endian, byte strings prefixed with a u32 length).
+// Hello (client): magic, version u32, ignore_min u32, timeout_cycles u64, program file contents (bytes).
+// Hello reply (server): u8 0 on success, or u8 1 and an error message (bytes).
+// Request (client): input count u32, then per input: bitmap length u32, input (bytes).
+// Reply (server), per input: u8 0, status u32, u8 has_duration, duration u64 (ns), u8 has_exception, cause u32, epc u32, tval u32, bitmap (bytes);
+//  or u8 1 and an error message (bytes).
+pub(crate) const PROTOCOL_MAGIC: [u8; 4] = *b"TPFZ";
+pub(crate) const PROTOCOL_VERSION: u32 = 1;
+//Upper bounds for lengths received from the other side.
+pub(crate) const MAX_IMAGE_LEN: usize = 64 * 1024 * 1024;
+pub(crate) const MAX_INPUT_LEN: usize = 16 * 1024 * 1024;
+pub(crate) const MAX_BITMAP_LEN: usize = 1024 * 1024;
+pub(crate) const MAX_BATCH_LEN: usize = 4096;
+//Upper bound for the sum of the input and bitmap lengths of a request.
+pub(crate) const MAX_BATCH_BYTES: usize = 64 * 1024 * 1024;
+const MAX_MESSAGE_LEN: usize = 64 * 1024;
+
+/// Connection of a [`TapascoRunnerRemote`] or the serve mode.
+pub(crate) enum RemoteStream {
+    Unix(UnixStream),
+    Tcp(TcpStream),
+}
+impl RemoteStream {
+    /// Connects to a serve process at a Unix socket path or a TCP `host:port` address (see [`is_tcp_address`]).
+    pub(crate) fn connect(address: &str) -> Result<Self, std::io::Error> {
+        if is_tcp_address(address) {
+            let stream = TcpStream::connect(address)?;
+            stream.set_nodelay(true)?;
+            Ok(Self::Tcp(stream))
+        }
+        else {
+            Ok(Self::Unix(UnixStream::connect(address)?))
+        }
+    }
+}
+impl Read for RemoteStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Unix(stream) => stream.read(buf),
+            Self::Tcp(stream) => stream.read(buf)
+        }
+    }
+}
+impl Write for RemoteStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Unix(stream) => stream.write(buf),
+            Self::Tcp(stream) => stream.write(buf)
+        }
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Unix(stream) => stream.flush(),
+            Self::Tcp(stream) => stream.flush()
+        }
+    }
+}
+
+/// Whether an address refers to TCP (`host:port`) rather than a Unix socket path (anything containing '/' or no ':').
+pub(crate) fn is_tcp_address(address: &str) -> bool {
+    address.contains(':') && !address.contains('/')
+}
+
+pub(crate) fn put_u32(buf: &mut Vec<u8>, val: u32) {
+    buf.extend_from_slice(&val.to_le_bytes());
+}
+pub(crate) fn put_u64(buf: &mut Vec<u8>, val: u64) {
+    buf.extend_from_slice(&val.to_le_bytes());
+}
+pub(crate) fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    put_u32(buf, bytes.len() as u32);
+    buf.extend_from_slice(bytes);
+}
+pub(crate) fn read_u8<R: Read>(r: &mut R) -> Result<u8, std::io::Error> {
+    let mut val = [0u8; 1];
+    r.read_exact(&mut val)?;
+    Ok(val[0])
+}
+pub(crate) fn read_u32<R: Read>(r: &mut R) -> Result<u32, std::io::Error> {
+    let mut val = [0u8; 4];
+    r.read_exact(&mut val)?;
+    Ok(u32::from_le_bytes(val))
+}
+pub(crate) fn read_u64<R: Read>(r: &mut R) -> Result<u64, std::io::Error> {
+    let mut val = [0u8; 8];
+    r.read_exact(&mut val)?;
+    Ok(u64::from_le_bytes(val))
+}
+//Reads a length-prefixed byte string of at most max_len bytes.
+pub(crate) fn read_bytes<R: Read>(r: &mut R, max_len: usize) -> Result<Vec<u8>, std::io::Error> {
+    let len = read_u32(r)? as usize;
+    if len > max_len {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Length {} exceeds the limit of {}", len, max_len)));
+    }
+    let mut bytes = vec![0u8; len];
+    r.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+fn read_message<R: Read>(r: &mut R) -> Result<String, std::io::Error> {
+    Ok(String::from_utf8_lossy(&read_bytes(r, MAX_MESSAGE_LEN)?).into_owned())
+}
+
+/// Appends the reply for a run result (see the protocol description above).
+pub(crate) fn put_run_result(buf: &mut Vec<u8>, result: &Result<TapascoHostRun, TapascoRunError>, bitmap: &[u8]) {
+    match result {
+        Ok(run) => {
+            buf.push(0);
+            put_u32(buf, run.status);
+            buf.push(run.duration.is_some() as u8);
+            put_u64(buf, run.duration.map_or(0, |duration| duration.as_nanos() as u64));
+            buf.push(run.exception.is_some() as u8);
+            let exception = run.exception.unwrap_or(TapascoRunException { cause: 0, epc: 0, tval: 0 });
+            put_u32(buf, exception.cause);
+            put_u32(buf, exception.epc);
+            put_u32(buf, exception.tval);
+            put_bytes(buf, bitmap);
+        },
+        Err(e) => {
+            buf.push(1);
+            put_bytes(buf, e.to_string().as_bytes());
+        }
+    }
+}
+
+/// Appends the reply to a hello message.
+pub(crate) fn put_hello_reply(buf: &mut Vec<u8>, result: Result<(), String>) {
+    match result {
+        Ok(()) => buf.push(0),
+        Err(s) => {
+            buf.push(1);
+            put_bytes(buf, s.as_bytes());
+        }
+    }
+}
+
+//Reads the reply for a run, writing the bitmap to the given buffer.
+// The outer error is a connection or protocol failure, the inner error a failed run on the server side.
+fn read_run_result<R: Read>(r: &mut R, bitmap: &mut [u8]) -> Result<Result<TapascoHostRun, TapascoRunError>, std::io::Error> {
+    if read_u8(r)? != 0 {
+        return Ok(Err(TapascoRunError::Remote { s: read_message(r)? }));
+    }
+    let status = read_u32(r)?;
+    let has_duration = read_u8(r)? != 0;
+    let duration_ns = read_u64(r)?;
+    let has_exception = read_u8(r)? != 0;
+    let exception = TapascoRunException { cause: read_u32(r)?, epc: read_u32(r)?, tval: read_u32(r)? };
+    let bitmap_len = read_u32(r)? as usize;
+    if bitmap_len != bitmap.len() {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+            format!("Bitmap length {} in the reply, expected {}", bitmap_len, bitmap.len())));
+    }
+    r.read_exact(bitmap)?;
+    Ok(Ok(TapascoHostRun {
+        status: status,
+        duration: if has_duration { Some(Duration::from_nanos(duration_ns)) } else { None },
+        exception: if has_exception { Some(exception) } else { None }
+    }))
+}
+
+/// The [`TapascoRunnerRemote`] runs inputs on the backend of a `serve` process, e.g. PEs shared with other campaigns or on another host.
+/// The program is sent on connection, so the serve process may be used with different programs at a time.
+pub struct TapascoRunnerRemote<'a> {
+    stream: BufReader<RemoteStream>,
+    stop_req: &'a AtomicBool
+}
+
+impl<'a> TapascoRunnerRemote<'a> {
+    /// Connects to a serve process and sets up the program.
+    /// * `address`: Unix socket path or TCP `host:port` address.
+    /// * `program`: Contents of the program file (ELF or flat binary).
+    pub fn new(
+        address: &str,
+        program: &[u8],
+        ignore_addresses_min: u32,
+        timeout_cycles: u64,
+        stop_req: &'a AtomicBool
+    ) -> Result<Self, TapascoRunError> {
+        let mut stream = BufReader::new(RemoteStream::connect(address)?);
+        let mut hello = Vec::with_capacity(program.len() + 24);
+        hello.extend_from_slice(&PROTOCOL_MAGIC);
+        put_u32(&mut hello, PROTOCOL_VERSION);
+        put_u32(&mut hello, ignore_addresses_min);
+        put_u64(&mut hello, timeout_cycles);
+        put_bytes(&mut hello, program);
+        stream.get_mut().write_all(&hello)?;
+        if read_u8(&mut stream)? != 0 {
+            return Err(TapascoRunError::Remote { s: read_message(&mut stream)? });
+        }
+        Ok(Self {
+            stream: stream,
+            stop_req: stop_req
+        })
+    }
+
+    fn try_run_batch(&mut self, inputs: &[&[u8]], bitmaps: &mut [&mut [u8]]) -> Result<Vec<Result<TapascoHostRun,TapascoRunError>>, std::io::Error> {
+        let mut request = Vec::with_capacity(4 + inputs.iter().map(|input| input.len() + 8).sum::<usize>());
+        put_u32(&mut request, inputs.len() as u32);
+        for (input, bitmap) in inputs.iter().zip(bitmaps.iter()) {
+            put_u32(&mut request, bitmap.len() as u32);
+            put_bytes(&mut request, input);
+        }
+        self.stream.get_mut().write_all(&request)?;
+        bitmaps.iter_mut().map(|bitmap| read_run_result(&mut self.stream, bitmap)).collect()
+    }
+}
+
+#[async_trait(?Send)]
+impl<'a> TapascoRunner for TapascoRunnerRemote<'a> {
+    async fn run(&mut self, input: &[u8], bitmap: &mut [u8]) -> Result<TapascoHostRun,TapascoRunError> {
+        self.run_batch(&[input], vec![bitmap]).await.pop().unwrap()
+    }
+
+    async fn run_batch(&mut self, inputs: &[&[u8]], mut bitmaps: Vec<&mut [u8]>) -> Vec<Result<TapascoHostRun,TapascoRunError>> {
+        if self.stop_req.load(std::sync::atomic::Ordering::Relaxed) == true {
+            return inputs.iter().map(|_| Err(TapascoRunError::LibAFL { source: libafl::Error::ShuttingDown })).collect();
+        }
+        //Split into requests within the batch limits of the serve process.
+        let mut results = Vec::with_capacity(inputs.len());
+        let mut begin = 0;
+        while begin < inputs.len() {
+            let mut end = begin + 1;
+            let mut batch_bytes = inputs[begin].len() + bitmaps[begin].len();
+            while end < inputs.len() && end - begin < MAX_BATCH_LEN && batch_bytes + inputs[end].len() + bitmaps[end].len() <= MAX_BATCH_BYTES {
+                batch_bytes += inputs[end].len() + bitmaps[end].len();
+                end += 1;
+            }
+            match self.try_run_batch(&inputs[begin..end], &mut bitmaps[begin..end]) {
+                Ok(batch_results) => results.extend(batch_results),
+                Err(e) => results.extend(inputs[begin..end].iter().map(|_| Err(TapascoRunError::Remote { s: format!("Connection error: {}", e) })))
+            }
+            begin = end;
+        }
+        results
+    }
+}
@@ -1,12 +1,11 @@
 use core::fmt::Debug;
 use std::{
     io::{prelude::*},
-    sync::{Arc, atomic::AtomicBool},
+    sync::atomic::AtomicBool,
     process::{Command, Child},
     cmp,
     fs::File,
-    os::unix::io::IntoRawFd,
-    time::Duration
+    os::unix::io::IntoRawFd
 };
 
 use libafl::{
@@ -15,7 +14,8 @@ use libafl::{
 };
 use async_trait::async_trait;
 
-use crate::tapasco_host::{TapascoHostRun, TapascoRunner, TapascoRunError};
+use crate::tapasco_host::{TapascoRunStatusFlags, TapascoHostRun, TapascoRunException, TapascoRunner, TapascoRunError};
+use crate::target_image::TargetImage;
 
 
 /// The [`TapascoRunnerSim`] manages the execution on the tapasco-pe-tb fuzzer PE simulation.
@@ -27,6 +27,7 @@ pub struct TapascoRunnerSim<'a> {
     req_pipe: Pipe,
     resp_pipe: Pipe,
     fuzzer_stdout: std::os::raw::c_int,
+    target_flat_path: String,
     timeout_cycles: u64,
     stop_req: &'a AtomicBool
 }
@@ -34,7 +35,7 @@ pub struct TapascoRunnerSim<'a> {
 impl<'a> TapascoRunnerSim<'a> {
     /// Create a new [`TapascoRunnerSim`]
     pub fn new(
-        target: String,
+        image: &TargetImage,
         ignore_addresses_min: u32,
         pesimdir: String,
         stdout_filepath: Option<String>,
@@ -57,6 +58,11 @@ impl<'a> TapascoRunnerSim<'a> {
         let shmem = shmem_res?;
         //Hack (MmapShMem does not provide a getter for the name).
         let shmem_path = format!("/libafl_{}_{}", std::process::id(), shmem_cnt);
+        //tapasco-pe-tb loads flat binaries only.
+        let target_flat_path = std::env::temp_dir()
+            .join(format!("fuzztb_target_{}_{}.bin", std::process::id(), shmem_cnt))
+            .to_string_lossy().to_string();
+        image.write_flat(target_flat_path.as_str())?;
         //stdout)
         let fuzzer_stdout = match stdout_filepath {
             Some(s) => {
@@ -84,7 +90,7 @@ impl<'a> TapascoRunnerSim<'a> {
 
         let child = Command::new("make")
             .current_dir(pesimdir)
-            .env("FUZZTB_TARGET", target)
+            .env("FUZZTB_TARGET", &target_flat_path)
             .env("FUZZTB_IGNOREMIN", format!("{:08x}", ignore_addresses_min))
             .env("FUZZTB_SHMEM", shmem_path)
             .env("FUZZTB_SHMEM_SIZE", format!("{}", shmem.len()))
@@ -104,6 +110,7 @@ impl<'a> TapascoRunnerSim<'a> {
             req_pipe: req_pipe,
             resp_pipe: resp_pipe,
             fuzzer_stdout: fuzzer_stdout,
+            target_flat_path: target_flat_path,
             stop_req: stop_req,
             timeout_cycles: timeout_cycles
         })
@@ -131,7 +138,7 @@ enum SimRequestType {
 
 #[async_trait(?Send)]
 impl<'a> TapascoRunner for TapascoRunnerSim<'a> {
-    async fn run(&mut self, input: &[u8], bitmap_mem: Arc<std::sync::Mutex<Box<[u8]>>>) -> Result<TapascoHostRun,TapascoRunError> {
+    async fn run(&mut self, input: &[u8], bitmap: &mut [u8]) -> Result<TapascoHostRun,TapascoRunError> {
         //Not properly 'async' (only blocking operations).
         if self.stop_req.load(std::sync::atomic::Ordering::Relaxed) == true {
             return Err(TapascoRunError::LibAFL { source: libafl::Error::ShuttingDown });
@@ -149,11 +156,10 @@ impl<'a> TapascoRunner for TapascoRunnerSim<'a> {
         self.read_resp()?;
         let ret_status: u32;
         let counter: u64;
+        let arg4_lo: u32;
+        let arg4_hi: u32;
         {
-            let lock_res = (*bitmap_mem).try_lock();
-            assert!(match lock_res {Ok(_) => {true} Err(_) => {false}},
-                "Unable to lock mutex that should have been unlocked: {}", lock_res.unwrap_err());
-            let bitmap_slice = &mut **lock_res.unwrap();
+            let bitmap_slice = &mut *bitmap;
             if bitmap_slice.len() < 4 || bitmap_slice.len() > self.shmem.len() {
                 return Err(TapascoRunError::BitmapLen{s:
                     format!("Bitmap length is {}, but should be in [4, {}]", bitmap_slice.len(), self.shmem.len())});
@@ -186,6 +192,8 @@ impl<'a> TapascoRunner for TapascoRunnerSim<'a> {
 
             ret_status = ret_lo;
             counter = _counter;
+            arg4_lo = _arg4_lo;
+            arg4_hi = _arg4_hi;
 
             //Read the bitmap.
             self.write_req_type(SimRequestType::CopyBitmap as u8)?;
@@ -199,10 +207,17 @@ impl<'a> TapascoRunner for TapascoRunnerSim<'a> {
         if self.stop_req.load(std::sync::atomic::Ordering::Relaxed) == true {
             return Err(TapascoRunError::LibAFL { source: libafl::Error::ShuttingDown });
         }
+        let exception = if (ret_status & TapascoRunStatusFlags::PROGRAM_CRASH_FLAG) != 0 {
+            Some(TapascoRunException {
+                cause: (ret_status & TapascoRunStatusFlags::PROGRAM_CRASH_CAUSE_MASK) >> TapascoRunStatusFlags::PROGRAM_CRASH_CAUSE_SHIFT,
+                epc: arg4_lo,
+                tval: arg4_hi
+            })
+        } else { None };
         Ok(TapascoHostRun {
             status: ret_status,
-            bitmap: bitmap_mem,
-            duration: Some(Duration::new(counter >> 32, (counter & 0xFFFFFFFF) as u32))
+            duration: Some(TapascoHostRun::duration_from_cycles(counter)),
+            exception: exception
         })
     }
 }
@@ -218,5 +233,6 @@ impl<'a> Drop for TapascoRunnerSim<'a> {
             unsafe { libc::close(self.fuzzer_stdout); };
             self.fuzzer_stdout = -1;
         }
+        let _removeres = std::fs::remove_file(&self.target_flat_path);
     }
 }
\ No newline at end of file
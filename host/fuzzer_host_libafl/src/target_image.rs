@@ -0,0 +1,280 @@
+use std::collections::BTreeMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use goblin::elf::Elf;
+use goblin::elf::header::EM_RISCV;
+use goblin::elf::section_header::{SHF_ALLOC, SHF_EXECINSTR, SHF_WRITE, SHT_NOBITS};
+use goblin::elf::sym::{STT_FUNC, STT_OBJECT};
+
+use crate::tapasco_host::TapascoRunError;
+use crate::tapasco_runner_phys::PEMemConfig;
+
+//Nominal layout of flat binaries as created by objcopy (see DUMPFLAGS in testPrograms/base.mk) for testPrograms/rv.ld.
+// The actual split into instruction and data memory follows the memory layout of the PE, see TargetImage::sections.
+static FLAT_INSTMEM_ADDR : u32 = 0x40000000;
+static FLAT_INSTMEM_SIZE : usize = 8*1024*1024;
+static FLAT_MAX_SIZE : usize = 16*1024*1024;
+
+/// A symbol of the target program.
+#[derive(Debug, Clone)]
+pub struct TargetSymbol {
+    pub name: String,
+    pub size: u32
+}
+
+/// Program image of the fuzzing target, split into the instruction and initialized data memory contents.
+#[derive(Debug, Clone)]
+pub struct TargetImage {
+    /// Virtual base address of `instmem`.
+    pub instmem_addr: u32,
+    pub instmem: Box<[u8]>,
+    /// Virtual base address of `datamem`.
+    pub datamem_addr: u32,
+    /// Initialized data memory (.data, .rodata etc.), excluding zero-initialized sections.
+    pub datamem: Box<[u8]>,
+    /// Virtual end address of all data sections, including zero-initialized sections such as .bss.
+    pub datamem_end: u32,
+    /// Ranges of `datamem` (offsets, 16 byte aligned) that belong to writable sections (.data etc.), i.e. that a run can modify.
+    /// Covers all of `datamem` for flat binaries.
+    pub datamem_writable: Vec<(usize, usize)>,
+    /// Hash over the memory contents and addresses, to tell whether a PE already holds this image.
+    pub content_hash: u64,
+    /// Function and object symbols by address.
+    symbols: BTreeMap<u32, TargetSymbol>,
+    //Contents of a flat binary, which has no addresses of its own.
+    flat: Option<Box<[u8]>>
+}
+
+impl TargetImage {
+    /// Loads the target from an ELF file, or from a flat objcopy binary if the file has no ELF header.
+    pub fn load(path: &str) -> Result<Self, TapascoRunError> {
+        let buf = std::fs::read(path)?;
+        Self::from_bytes(&buf)
+    }
+
+    /// Loads the target from the contents of an ELF file or flat objcopy binary, see [`TargetImage::load`].
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, TapascoRunError> {
+        let mut image = if buf.len() >= 4 && &buf[0..4] == b"\x7fELF" {
+            Self::from_elf(buf)?
+        }
+        else {
+            //Don't accept binaries larger than the largest supported memory layout.
+            if buf.len() > FLAT_MAX_SIZE {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Flat binary larger than the instruction and data memory").into());
+            }
+            //Fill in the nominal layout; the runners use the split for the memory layout of the PE instead.
+            let (instmem, datamem) = split_flat(buf, FLAT_INSTMEM_SIZE);
+            let datamem_addr = FLAT_INSTMEM_ADDR + FLAT_INSTMEM_SIZE as u32;
+            Self {
+                instmem_addr: FLAT_INSTMEM_ADDR,
+                instmem: instmem.into(),
+                datamem_addr: datamem_addr,
+                datamem_end: datamem_addr + datamem.len() as u32,
+                datamem_writable: aligned_ranges(vec![(0, datamem.len())], datamem.len()),
+                datamem: datamem.into(),
+                content_hash: 0,
+                symbols: BTreeMap::new(),
+                flat: Some(buf.into())
+            }
+        };
+        let mut hasher = DefaultHasher::new();
+        (image.instmem_addr, &image.instmem, image.datamem_addr, &image.datamem, image.datamem_end, &image.flat).hash(&mut hasher);
+        image.content_hash = hasher.finish();
+        Ok(image)
+    }
+
+    /// Loads all allocated sections of a 32bit RISC-V ELF file by their load addresses.
+    /// Executable sections make up the instruction memory, all other sections the data memory.
+    fn from_elf(buf: &[u8]) -> Result<Self, TapascoRunError> {
+        let elf = Elf::parse(buf).map_err(|e| TapascoRunError::TargetBinary { s: e.to_string() })?;
+        if elf.is_64 || elf.header.e_machine != EM_RISCV {
+            return Err(TapascoRunError::TargetBinary { s: "Expected a 32bit RISC-V ELF file".to_string() });
+        }
+        //Determine the memory ranges.
+        let mut instmem_range: Option<(u32, u32)> = None;
+        let mut datamem_range: Option<(u32, u32)> = None;
+        let mut datamem_end: Option<u32> = None;
+        let extend = |range: Option<(u32, u32)>, begin: u32, end: u32| -> Option<(u32, u32)> {
+            match range {
+                Some((cur_begin, cur_end)) => Some((std::cmp::min(cur_begin, begin), std::cmp::max(cur_end, end))),
+                None => Some((begin, end))
+            }
+        };
+        let alloc_sections: Vec<_> = elf.section_headers.iter()
+            .filter(|sh| (sh.sh_flags & SHF_ALLOC as u64) != 0 && sh.sh_size > 0)
+            .collect();
+        for sh in &alloc_sections {
+            let begin = sh.sh_addr as u32;
+            let end = (sh.sh_addr + sh.sh_size) as u32;
+            let is_exec = (sh.sh_flags & SHF_EXECINSTR as u64) != 0;
+            if sh.sh_type == SHT_NOBITS {
+                if is_exec {
+                    return Err(TapascoRunError::TargetBinary { s: "Zero-initialized executable sections are not supported".to_string() });
+                }
+                datamem_end = Some(datamem_end.map_or(end, |cur| std::cmp::max(cur, end)));
+                //Only used to determine the data memory base.
+                datamem_range = match datamem_range {
+                    Some((cur_begin, cur_end)) => Some((std::cmp::min(cur_begin, begin), cur_end)),
+                    None => Some((begin, begin))
+                };
+            }
+            else if is_exec {
+                instmem_range = extend(instmem_range, begin, end);
+            }
+            else {
+                datamem_range = extend(datamem_range, begin, end);
+                datamem_end = Some(datamem_end.map_or(end, |cur| std::cmp::max(cur, end)));
+            }
+        }
+        let (instmem_addr, instmem_end) = instmem_range
+            .ok_or(TapascoRunError::TargetBinary { s: "No executable sections found".to_string() })?;
+        let (datamem_addr, datamem_init_end) = datamem_range.unwrap_or((instmem_end, instmem_end));
+        if datamem_addr < instmem_end && instmem_addr < datamem_end.unwrap_or(datamem_addr) {
+            return Err(TapascoRunError::TargetBinary { s: "Executable and data sections overlap".to_string() });
+        }
+
+        //Copy the section contents.
+        let mut instmem = vec![0u8; (instmem_end - instmem_addr) as usize];
+        let mut datamem = vec![0u8; datamem_init_end.saturating_sub(datamem_addr) as usize];
+        let mut datamem_writable = Vec::new();
+        for sh in &alloc_sections {
+            if sh.sh_type == SHT_NOBITS {
+                continue;
+            }
+            if (sh.sh_flags & SHF_EXECINSTR as u64) == 0 && (sh.sh_flags & SHF_WRITE as u64) != 0 {
+                let offs = (sh.sh_addr as u32 - datamem_addr) as usize;
+                datamem_writable.push((offs, offs + sh.sh_size as usize));
+            }
+            let file_range = sh.file_range()
+                .filter(|range| range.end <= buf.len())
+                .ok_or(TapascoRunError::TargetBinary { s: "Section data out of file bounds".to_string() })?;
+            let (mem, offs) = if (sh.sh_flags & SHF_EXECINSTR as u64) != 0 {
+                (&mut instmem, (sh.sh_addr as u32 - instmem_addr) as usize)
+            } else {
+                (&mut datamem, (sh.sh_addr as u32 - datamem_addr) as usize)
+            };
+            mem[offs..offs + file_range.len()].copy_from_slice(&buf[file_range]);
+        }
+
+        //Keep the function and object symbols, e.g. to name crash locations.
+        let mut symbols = BTreeMap::new();
+        for sym in elf.syms.iter() {
+            if sym.st_type() != STT_FUNC && sym.st_type() != STT_OBJECT {
+                continue;
+            }
+            if let Some(name) = elf.strtab.get_at(sym.st_name) {
+                symbols.insert(sym.st_value as u32, TargetSymbol { name: name.to_string(), size: sym.st_size as u32 });
+            }
+        }
+
+        Ok(Self {
+            instmem_addr: instmem_addr,
+            instmem: instmem.into_boxed_slice(),
+            datamem_addr: datamem_addr,
+            datamem_writable: aligned_ranges(datamem_writable, datamem.len()),
+            datamem: datamem.into_boxed_slice(),
+            datamem_end: datamem_end.unwrap_or(datamem_addr),
+            content_hash: 0,
+            symbols: symbols,
+            flat: None
+        })
+    }
+
+    /// Returns whether the image was loaded from a flat binary, i.e. has no addresses of its own.
+    /// All of its data memory is then considered writable.
+    #[must_use]
+    pub fn is_flat(&self) -> bool {
+        self.flat.is_some()
+    }
+
+    /// Returns the instruction and initialized data memory contents for the memory layout of a PE.
+    /// Flat binaries are split at the offset of the PE data memory base from its instruction memory base,
+    ///  e.g. 8 MiB for testPrograms/rv.ld and 7 MiB for testPrograms/rv_bram_tapascoriscv.ld.
+    #[must_use]
+    pub fn sections(&self, memconf: &PEMemConfig) -> (&[u8], &[u8]) {
+        match &self.flat {
+            Some(flat) => split_flat(flat, memconf.datamem_addr_virt.wrapping_sub(memconf.instmem_addr_virt) as usize),
+            None => (&self.instmem, &self.datamem)
+        }
+    }
+
+    /// Checks whether the image fits the memory layout of a PE.
+    /// Flat binaries are assumed to be linked for the PE, so only their sizes are checked.
+    pub fn check_memconfig(&self, memconf: &PEMemConfig) -> Result<(), TapascoRunError> {
+        if self.is_flat() {
+            let (instmem, datamem) = self.sections(memconf);
+            if instmem.len() > memconf.instmem_size_phys || ((datamem.len() + 15) & !15) > memconf.datamem_size_phys_without_stack {
+                return Err(TapascoRunError::ProgramOrDataTooLarge {});
+            }
+            return Ok(());
+        }
+        if self.instmem_addr != memconf.instmem_addr_virt {
+            return Err(TapascoRunError::TargetBinary { s: format!(
+                "Instruction memory base 0x{:08x} does not match the PE (0x{:08x})", self.instmem_addr, memconf.instmem_addr_virt) });
+        }
+        if self.datamem_end > self.datamem_addr && self.datamem_addr != memconf.datamem_addr_virt {
+            return Err(TapascoRunError::TargetBinary { s: format!(
+                "Data memory base 0x{:08x} does not match the PE (0x{:08x})", self.datamem_addr, memconf.datamem_addr_virt) });
+        }
+        if self.instmem.len() > memconf.instmem_size_phys
+            || ((self.datamem.len() + 15) & !15) > memconf.datamem_size_phys_without_stack
+            || (self.datamem_end - self.datamem_addr) as usize > memconf.datamem_size_phys {
+            return Err(TapascoRunError::ProgramOrDataTooLarge {});
+        }
+        Ok(())
+    }
+
+    /// Returns the address of the symbol with the given name.
+    pub fn symbol_addr(&self, name: &str) -> Option<u32> {
+        self.symbols.iter().find(|(_, sym)| sym.name == name).map(|(addr, _)| *addr)
+    }
+
+    /// Returns the symbol containing the given address, and the offset of the address within the symbol.
+    pub fn symbolize(&self, addr: u32) -> Option<(&str, u32)> {
+        let (sym_addr, sym) = self.symbols.range(..=addr).next_back()?;
+        let offs = addr - sym_addr;
+        if sym.size != 0 && offs >= sym.size {
+            return None;
+        }
+        Some((sym.name.as_str(), offs))
+    }
+
+    /// Writes the image in the flat objcopy layout, with the data memory at its offset from the instruction memory base.
+    pub fn write_flat(&self, path: &str) -> Result<(), std::io::Error> {
+        if let Some(flat) = &self.flat {
+            return std::fs::write(path, flat);
+        }
+        let datamem_offs = self.datamem_addr.wrapping_sub(self.instmem_addr) as usize;
+        let mut buffer = vec![0u8; std::cmp::max(self.instmem.len(), datamem_offs + self.datamem.len())];
+        buffer[..self.instmem.len()].copy_from_slice(&self.instmem);
+        buffer[datamem_offs..datamem_offs + self.datamem.len()].copy_from_slice(&self.datamem);
+        std::fs::write(path, buffer)
+    }
+}
+
+//Aligns the ranges to 16 bytes (as for the data memory DMA uploads), and merges overlapping or adjacent ranges.
+fn aligned_ranges(mut ranges: Vec<(usize, usize)>, len: usize) -> Vec<(usize, usize)> {
+    let len_aligned = (len + 15) & !15;
+    ranges.sort();
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (begin, end) in ranges {
+        let (begin, end) = (begin & !15, std::cmp::min((end + 15) & !15, len_aligned));
+        if begin >= end {
+            continue;
+        }
+        match merged.last_mut() {
+            Some(last) if begin <= last.1 => last.1 = std::cmp::max(last.1, end),
+            _ => merged.push((begin, end))
+        }
+    }
+    merged
+}
+
+//Splits a flat binary into the instruction and data memory contents, with the data memory at `datamem_offs`.
+fn split_flat(buf: &[u8], datamem_offs: usize) -> (&[u8], &[u8]) {
+    let (instmem, datamem) = buf.split_at(std::cmp::min(buf.len(), datamem_offs));
+    //Trailing zeroes in instmem are assumed to be unused (the flat layout does not carry the actual lengths).
+    let instmem_len_nonzero = instmem.iter().rposition(|&v| v != 0).map(|rpos| rpos + 1).unwrap_or(0);
+    (&instmem[..instmem_len_nonzero], datamem)
+}
@@ -0,0 +1,276 @@
+use std::sync::{Arc, Mutex, atomic::AtomicBool, mpsc};
+
+use clap::ArgMatches;
+use futures::executor;
+use libafl::executors::ExitKind;
+use snafu::ResultExt;
+
+use crate::{FuzzErr, TapascoRunSnafu};
+use crate::tapasco_host::{TapascoHostRun, TapascoRunError, TapascoRunner, TapascoRunStatusFlags};
+use crate::tapasco_runner_phys::TapascoDispatcher;
+use crate::target_image::TargetImage;
+
+//Initial block deletion length, relative to the input length (as in afl-tmin).
+const TRIM_START_STEPS: usize = 16;
+//Final block deletion length, relative to the input length.
+const TRIM_END_STEPS: usize = 1024;
+//Byte value used for simplification (as in afl-tmin).
+const SIMPLE_BYTE: u8 = b'0';
+
+/// Execution result that has to be preserved by the minimization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TminSignature {
+    Ok,
+    Crash { cause: u32, epc: u32 },
+    Timeout,
+}
+
+impl TminSignature {
+    fn from_run(run: &TapascoHostRun) -> Result<Self, TapascoRunError> {
+        if (run.status & TapascoRunStatusFlags::INVALID_BITMAP_SIZE_FLAG) != 0
+            || (run.status & !TapascoRunStatusFlags::ALL_MASK) != 0 {
+            return Err(TapascoRunError::Unknown { s: format!("PE: Invalid run status 0x{:x}", run.status) });
+        }
+        if (run.status & TapascoRunStatusFlags::TIMEOUT_FLAG) != 0 {
+            return Ok(Self::Timeout);
+        }
+        if (run.status & TapascoRunStatusFlags::PROGRAM_CRASH_FLAG) != 0 {
+            let cause = (run.status & TapascoRunStatusFlags::PROGRAM_CRASH_CAUSE_MASK) >> TapascoRunStatusFlags::PROGRAM_CRASH_CAUSE_SHIFT;
+            return Ok(match run.exception {
+                Some(exception) => Self::Crash { cause: exception.cause, epc: exception.epc },
+                None => Self::Crash { cause: cause, epc: 0 }
+            });
+        }
+        Ok(Self::Ok)
+    }
+}
+
+type TminJob = (usize, Vec<u8>);
+type TminResult = (usize, Result<TminSignature, String>);
+
+//Pool of threads that each run inputs through their own TapascoRunner.
+// With TaPaSCo, the runners share the dispatchers, so the inputs of a batch run in parallel on the PEs.
+struct TminWorkers {
+    job_tx: Option<mpsc::Sender<TminJob>>,
+    result_rx: mpsc::Receiver<TminResult>,
+    threads: Vec<std::thread::JoinHandle<()>>,
+    num_execs: u64,
+}
+
+impl TminWorkers {
+    fn new(cmd_matches: &ArgMatches, image: Arc<TargetImage>, tapasco_dispatchers: Vec<Arc<TapascoDispatcher>>, num_threads: usize) -> Self {
+        let (job_tx, job_rx) = mpsc::channel::<TminJob>();
+        let (result_tx, result_rx) = mpsc::channel::<TminResult>();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        let bitmap_size = cmd_matches.value_of("bitmapsize").map_or(8192, |s| s.parse::<usize>().unwrap());
+        let threads = (0..num_threads).map(|_| {
+            let thr_cmd_matches = cmd_matches.clone();
+            let thr_image = image.clone();
+            let thr_dispatchers = tapasco_dispatchers.clone();
+            let thr_job_rx = job_rx.clone();
+            let thr_result_tx = result_tx.clone();
+            std::thread::spawn(move || {
+                let stop_req = AtomicBool::new(false);
+                let mut tapasco_host = crate::create_tapascorunner(&thr_cmd_matches, thr_image, thr_dispatchers, &stop_req);
+                let mut bitmap = vec![0u8; bitmap_size].into_boxed_slice();
+                loop {
+                    //Only hold the lock while waiting for the next job.
+                    let job = thr_job_rx.lock().unwrap().recv();
+                    let (id, input) = match job {
+                        Ok(job) => job,
+                        Err(_) => break //All jobs done.
+                    };
+                    let result = match tapasco_host.as_mut() {
+                        Ok(tapasco_host) => executor::block_on(tapasco_host.run(&input, &mut bitmap))
+                            .and_then(|run| TminSignature::from_run(&run))
+                            .map_err(|e| e.to_string()),
+                        Err(e) => Err(e.to_string())
+                    };
+                    if thr_result_tx.send((id, result)).is_err() {
+                        break;
+                    }
+                }
+            })
+        }).collect();
+        Self {
+            job_tx: Some(job_tx),
+            result_rx: result_rx,
+            threads: threads,
+            num_execs: 0,
+        }
+    }
+
+    fn num_threads(&self) -> usize {
+        self.threads.len()
+    }
+
+    //Runs a batch of inputs and returns their signatures in order.
+    fn run_batch(&mut self, inputs: Vec<Vec<u8>>) -> Result<Vec<TminSignature>, FuzzErr> {
+        let num_inputs = inputs.len();
+        for job in inputs.into_iter().enumerate() {
+            self.job_tx.as_ref().unwrap().send(job).map_err(|e| FuzzErr::WorkerError { s: e.to_string() })?;
+        }
+        let mut signatures: Vec<Option<TminSignature>> = vec![None; num_inputs];
+        for _ in 0..num_inputs {
+            let (id, result) = self.result_rx.recv().map_err(|e| FuzzErr::WorkerError { s: e.to_string() })?;
+            signatures[id] = Some(result.map_err(|s| FuzzErr::WorkerError { s: s })?);
+        }
+        self.num_execs += num_inputs as u64;
+        Ok(signatures.into_iter().map(|signature| signature.unwrap()).collect())
+    }
+
+    //Evaluates candidates in batches of the worker count, and returns the first candidate preserving the signature.
+    // Candidates after it are run speculatively, and their results are discarded.
+    fn find_first<F>(&mut self, num_candidates: usize, mut make_candidate: F, signature: TminSignature)
+        -> Result<Option<(usize, Vec<u8>)>, FuzzErr>
+    where F: FnMut(usize) -> Vec<u8> {
+        let mut i_first = 0;
+        while i_first < num_candidates {
+            let i_end = std::cmp::min(i_first + self.num_threads(), num_candidates);
+            let candidates: Vec<Vec<u8>> = (i_first..i_end).map(&mut make_candidate).collect();
+            let signatures = self.run_batch(candidates.clone())?;
+            if let Some(i_match) = signatures.iter().position(|s| *s == signature) {
+                return Ok(Some((i_first + i_match, candidates.into_iter().nth(i_match).unwrap())));
+            }
+            i_first = i_end;
+        }
+        Ok(None)
+    }
+}
+
+impl Drop for TminWorkers {
+    fn drop(&mut self) {
+        //Closing the job channel ends the worker loops.
+        self.job_tx = None;
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+//Removes blocks of decreasing length, keeping each removal that preserves the signature.
+fn pass_block_deletion(workers: &mut TminWorkers, input: &mut Vec<u8>, signature: TminSignature) -> Result<bool, FuzzErr> {
+    let mut changed = false;
+    let mut del_len = std::cmp::max((input.len() / TRIM_START_STEPS).next_power_of_two(), 1);
+    let del_len_min = std::cmp::max(input.len() / TRIM_END_STEPS, 1);
+    while del_len >= del_len_min && !input.is_empty() {
+        let mut pos = 0;
+        while pos < input.len() {
+            let num_candidates = (input.len() - pos + del_len - 1) / del_len;
+            let cur = input.clone();
+            let found = workers.find_first(num_candidates, |i| {
+                let block_start = pos + i * del_len;
+                let block_end = std::cmp::min(block_start + del_len, cur.len());
+                [&cur[..block_start], &cur[block_end..]].concat()
+            }, signature)?;
+            match found {
+                Some((i, candidate)) => {
+                    //Retry at the same position, which now contains the data after the removed block.
+                    pos += i * del_len;
+                    *input = candidate;
+                    changed = true;
+                },
+                None => break
+            }
+        }
+        del_len /= 2;
+    }
+    Ok(changed)
+}
+
+//Replaces all occurrences of a byte value with SIMPLE_BYTE, keeping each replacement that preserves the signature.
+fn pass_alphabet(workers: &mut TminWorkers, input: &mut Vec<u8>, signature: TminSignature) -> Result<bool, FuzzErr> {
+    let mut changed = false;
+    let mut values: Vec<u8> = input.iter().copied().filter(|&v| v != SIMPLE_BYTE).collect();
+    values.sort_unstable();
+    values.dedup();
+    let mut i_value = 0;
+    while i_value < values.len() {
+        let cur = input.clone();
+        let remaining = &values[i_value..];
+        let found = workers.find_first(remaining.len(), |i| {
+            cur.iter().map(|&v| if v == remaining[i] { SIMPLE_BYTE } else { v }).collect()
+        }, signature)?;
+        match found {
+            Some((i, candidate)) => {
+                i_value += i + 1;
+                *input = candidate;
+                changed = true;
+            },
+            None => break
+        }
+    }
+    Ok(changed)
+}
+
+//Replaces individual bytes with SIMPLE_BYTE, keeping each replacement that preserves the signature.
+fn pass_bytes(workers: &mut TminWorkers, input: &mut Vec<u8>, signature: TminSignature) -> Result<bool, FuzzErr> {
+    let mut changed = false;
+    let mut pos = 0;
+    loop {
+        let positions: Vec<usize> = (pos..input.len()).filter(|&i| input[i] != SIMPLE_BYTE).collect();
+        if positions.is_empty() {
+            break;
+        }
+        let cur = input.clone();
+        let found = workers.find_first(positions.len(), |i| {
+            let mut candidate = cur.clone();
+            candidate[positions[i]] = SIMPLE_BYTE;
+            candidate
+        }, signature)?;
+        match found {
+            Some((i, candidate)) => {
+                pos = positions[i] + 1;
+                *input = candidate;
+                changed = true;
+            },
+            None => break
+        }
+    }
+    Ok(changed)
+}
+
+//Minimizes a crashing or timing out input, keeping the exception cause and epc or the timeout status.
+pub fn tmin(cmd_matches: &ArgMatches, tapasco_dispatchers: Vec<Arc<TapascoDispatcher>>) -> Result<ExitKind, FuzzErr> {
+    let input_path = cmd_matches.value_of("input").ok_or(FuzzErr::ArgError{s:"input argument missing".to_string()})?;
+    let output_path = cmd_matches.value_of("output").ok_or(FuzzErr::ArgError{s:"output argument missing".to_string()})?;
+    //By default, use one thread per PE.
+    let num_threads = match cmd_matches.value_of("numthreads") {
+        Some(s) => s.parse::<usize>().unwrap(),
+        None => std::cmp::max(tapasco_dispatchers.iter().map(|dispatcher| dispatcher.num_pes()).sum(), 1)
+    };
+    let mut input = std::fs::read(input_path).map_err(TapascoRunError::from).context(TapascoRunSnafu)?;
+    let input_len_orig = input.len();
+
+    let image = crate::load_target_image(cmd_matches)?;
+    let mut workers = TminWorkers::new(cmd_matches, image, tapasco_dispatchers, num_threads);
+
+    let signature = workers.run_batch(vec![input.clone()])?[0];
+    match signature {
+        TminSignature::Ok => {
+            return Err(FuzzErr::ArgError{s: "The input neither crashes nor times out".to_string()});
+        },
+        TminSignature::Crash { cause, epc } => println!("Preserving crash with cause {}, epc 0x{:08x}.", cause, epc),
+        TminSignature::Timeout => println!("Preserving timeout.")
+    };
+    println!("Minimizing {} bytes on {} threads.", input_len_orig, workers.num_threads());
+
+    //Repeat all passes until none of them changes the input any more.
+    let mut i_round = 0;
+    loop {
+        i_round += 1;
+        let mut changed = pass_block_deletion(&mut workers, &mut input, signature)?;
+        println!("Round {}: {} bytes after block deletion.", i_round, input.len());
+        changed |= pass_alphabet(&mut workers, &mut input, signature)?;
+        changed |= pass_bytes(&mut workers, &mut input, signature)?;
+        println!("Round {}: {} non-'0' bytes after simplification.", i_round,
+            input.iter().filter(|&&v| v != SIMPLE_BYTE).count());
+        if !changed {
+            break;
+        }
+    }
+
+    std::fs::write(output_path, &input).map_err(TapascoRunError::from).context(TapascoRunSnafu)?;
+    println!("Minimized from {} to {} bytes in {} executions.", input_len_orig, input.len(), workers.num_execs);
+    Ok(ExitKind::Ok)
+}